@@ -1,15 +1,18 @@
 use crate::{
     config::*,
     core::*,
-    feat,
-    utils::{dirs, help, resolve},
+    enhance, feat,
+    utils::{
+        backup, dirs, help, init, local_api, log_tail, proxy_env::ShellKind, resolve,
+        rule_match::RuleMatch,
+    },
 };
 use crate::{ret_err, wrap_err};
 use anyhow::{Context, Result};
 use serde_yaml::Mapping;
 use std::collections::{HashMap, VecDeque};
 use sysproxy::Sysproxy;
-use tauri::{api, Manager};
+use tauri::Manager;
 type CmdResult<T = ()> = Result<T, String>;
 
 #[tauri::command]
@@ -17,6 +20,20 @@ pub fn get_profiles() -> CmdResult<IProfiles> {
     Ok(Config::profiles().data().clone())
 }
 
+/// search the profiles by name, desc, group and url
+#[tauri::command]
+pub fn search_profiles(query: String) -> CmdResult<Vec<String>> {
+    Ok(Config::profiles().latest().search(&query))
+}
+
+/// the subscription user info of the remote profile
+#[tauri::command]
+pub fn get_profile_userinfo(index: String) -> CmdResult<Option<PrfExtra>> {
+    let profiles = Config::profiles();
+    let profiles = profiles.latest();
+    Ok(wrap_err!(profiles.get_item(&index))?.extra)
+}
+
 #[tauri::command]
 pub async fn enhance_profiles() -> CmdResult {
     wrap_err!(CoreManager::global().update_config().await)?;
@@ -24,10 +41,29 @@ pub async fn enhance_profiles() -> CmdResult {
     Ok(())
 }
 
+/// `name` overrides the one from the subscription, `icon` is an emoji or an image url
 #[tauri::command]
-pub async fn import_profile(url: String, option: Option<PrfOption>) -> CmdResult {
-    let item = wrap_err!(PrfItem::from_url(&url, None, None, option).await)?;
-    wrap_err!(Config::profiles().data().append_item(item))
+pub async fn import_profile(
+    url: String,
+    option: Option<PrfOption>,
+    name: Option<String>,
+    icon: Option<String>,
+) -> CmdResult {
+    let item = wrap_err!(feat::download_profile(url.clone(), &url, option, None).await)?;
+    let mut item = item.ok_or("failed to download the profile")?;
+    if let Some(name) = name.filter(|n| !n.trim().is_empty()) {
+        item.name = Some(name);
+    }
+    item.icon = icon.filter(|i| !i.trim().is_empty());
+    wrap_err!(Config::profiles().data().append_item(item))?;
+    log_err!(handle::Handle::update_systray());
+    Ok(())
+}
+
+/// create a merge item attached to the base profile
+#[tauri::command]
+pub async fn create_merge_profile(base: String, name: Option<String>) -> CmdResult<String> {
+    wrap_err!(feat::create_merge_profile(base, name).await)
 }
 
 #[tauri::command]
@@ -38,7 +74,21 @@ pub async fn reorder_profile(active_id: String, over_id: String) -> CmdResult {
 #[tauri::command]
 pub async fn create_profile(item: PrfItem, file_data: Option<String>) -> CmdResult {
     let item = wrap_err!(PrfItem::from(item, file_data).await)?;
-    wrap_err!(Config::profiles().data().append_item(item))
+    wrap_err!(Config::profiles().data().append_item(item))?;
+    log_err!(handle::Handle::update_systray());
+    Ok(())
+}
+
+/// import the subscriptions concurrently, report the result of each url
+#[tauri::command]
+pub async fn import_profiles_batch(urls: Vec<String>) -> CmdResult<Vec<feat::ImportResult>> {
+    Ok(feat::import_profiles_batch(urls).await)
+}
+
+/// check the subscription and return the metadata without importing
+#[tauri::command]
+pub async fn probe_subscription(url: String, option: Option<PrfOption>) -> CmdResult<PrfProbe> {
+    wrap_err!(PrfItem::probe_url(&url, option).await)
 }
 
 #[tauri::command]
@@ -46,6 +96,28 @@ pub async fn update_profile(index: String, option: Option<PrfOption>) -> CmdResu
     wrap_err!(feat::update_profile(index, option).await)
 }
 
+/// update now and restart the timer of the profile
+#[tauri::command]
+pub async fn update_profile_now(uid: String) -> CmdResult<feat::UpdateResult> {
+    wrap_err!(feat::update_profile_now(uid).await)
+}
+
+/// retry the failed update now, the timer keeps its own schedule
+#[tauri::command]
+pub async fn retry_update(uid: String) -> CmdResult {
+    wrap_err!(feat::update_profile(uid, None).await)
+}
+
+#[tauri::command]
+pub async fn update_all_profiles() -> CmdResult<feat::UpdateSummary> {
+    wrap_err!(feat::update_all_profiles().await)
+}
+
+#[tauri::command]
+pub fn cancel_update_all() -> CmdResult<bool> {
+    Ok(feat::cancel_update_all())
+}
+
 #[tauri::command]
 pub async fn delete_profile(index: String) -> CmdResult {
     let should_update = wrap_err!({ Config::profiles().data().delete_item(index) })?;
@@ -53,38 +125,70 @@ pub async fn delete_profile(index: String) -> CmdResult {
         wrap_err!(CoreManager::global().update_config().await)?;
         handle::Handle::refresh_clash();
     }
+    log_err!(handle::Handle::update_systray());
 
     Ok(())
 }
 
+/// add the merge or script item to the chain of the base profile
+#[tauri::command]
+pub async fn add_profile_chain(base: String, uid: String, index: Option<usize>) -> CmdResult {
+    let patch = |profiles: &mut IProfiles| profiles.chain_add(&base, uid, index);
+    wrap_err!(feat::patch_profile_chain(base.clone(), patch).await)
+}
+
+#[tauri::command]
+pub async fn remove_profile_chain(base: String, uid: String) -> CmdResult {
+    let patch = |profiles: &mut IProfiles| profiles.chain_remove(&base, &uid);
+    wrap_err!(feat::patch_profile_chain(base.clone(), patch).await)
+}
+
+#[tauri::command]
+pub async fn reorder_profile_chain(base: String, order: Vec<String>) -> CmdResult {
+    let patch = |profiles: &mut IProfiles| profiles.chain_reorder(&base, order);
+    wrap_err!(feat::patch_profile_chain(base.clone(), patch).await)
+}
+
 /// 修改profiles的
 #[tauri::command]
 pub async fn patch_profiles_config(profiles: IProfiles) -> CmdResult {
-    wrap_err!({ Config::profiles().draft().patch_config(profiles) })?;
-
-    match CoreManager::global().update_config().await {
-        Ok(_) => {
-            handle::Handle::refresh_clash();
-            let _ = handle::Handle::update_systray_part();
-            Config::profiles().apply();
-            wrap_err!(Config::profiles().data().save_file())?;
-            Ok(())
-        }
-        Err(err) => {
-            Config::profiles().discard();
-            log::error!(target: "app", "{err}");
-            Err(format!("{err}"))
-        }
-    }
+    wrap_err!(feat::patch_profiles_config(profiles).await)
 }
 
 /// 修改某个profile item的
 #[tauri::command]
 pub fn patch_profile(index: String, profile: PrfItem) -> CmdResult {
+    let rename = profile.name.is_some() || profile.group.is_some();
     wrap_err!(Config::profiles().data().patch_item(index, profile))?;
+    if rename {
+        log_err!(handle::Handle::update_systray());
+    }
     wrap_err!(timer::Timer::global().refresh())
 }
 
+#[tauri::command]
+pub fn rename_profile(uid: String, name: String) -> CmdResult {
+    wrap_err!(feat::rename_profile(uid, name))
+}
+
+/// return the interval in minutes
+#[tauri::command]
+pub fn set_update_interval(uid: String, value: u64, unit: String) -> CmdResult<u64> {
+    wrap_err!(feat::set_update_interval(uid, value, unit))
+}
+
+/// apply the merge or script later, return the new order
+#[tauri::command]
+pub async fn raise_merge_order(uid: String) -> CmdResult<i32> {
+    wrap_err!(feat::bump_merge_order(uid, 1).await)
+}
+
+/// apply the merge or script earlier, return the new order
+#[tauri::command]
+pub async fn lower_merge_order(uid: String) -> CmdResult<i32> {
+    wrap_err!(feat::bump_merge_order(uid, -1).await)
+}
+
 #[tauri::command]
 pub fn view_profile(app_handle: tauri::AppHandle, index: String) -> CmdResult {
     let file = {
@@ -128,6 +232,18 @@ pub fn get_clash_info() -> CmdResult<ClashInfo> {
     Ok(Config::clash().latest().get_client_info())
 }
 
+/// the controller of the core, the secret is redacted for display
+#[tauri::command]
+pub fn get_controller_info() -> CmdResult<ControllerInfo> {
+    Ok(Config::clash().latest().get_controller_info())
+}
+
+/// set or rotate the controller secret, generate one if `secret` is none
+#[tauri::command]
+pub async fn set_controller_secret(secret: Option<String>) -> CmdResult<String> {
+    wrap_err!(feat::set_controller_secret(secret).await)
+}
+
 #[tauri::command]
 pub fn get_runtime_config() -> CmdResult<Option<Mapping>> {
     Ok(Config::runtime().latest().config.clone())
@@ -145,6 +261,13 @@ pub fn get_runtime_yaml() -> CmdResult<String> {
         ))
 }
 
+/// generate the config of the profile without activating it
+#[tauri::command]
+pub async fn dry_run_profile(uid: String) -> CmdResult<String> {
+    let config = wrap_err!(enhance::enhance_dry_run(&uid))?;
+    wrap_err!(serde_yaml::to_string(&config).context("failed to convert config to yaml"))
+}
+
 #[tauri::command]
 pub fn get_runtime_exists() -> CmdResult<Vec<String>> {
     Ok(Config::runtime().latest().exists_keys.clone())
@@ -155,14 +278,72 @@ pub fn get_runtime_logs() -> CmdResult<HashMap<String, Vec<(String, String)>>> {
     Ok(Config::runtime().latest().chain_logs.clone())
 }
 
+#[tauri::command]
+pub async fn match_rule(target: String) -> CmdResult<RuleMatch> {
+    wrap_err!(feat::match_rule(target).await)
+}
+
+/// the latest memory of the core, `unsupported` if the core has no such api
+#[tauri::command]
+pub fn get_core_memory() -> CmdResult<monitor::MemoryState> {
+    Ok(monitor::Monitor::global().memory())
+}
+
+/// the bytes by host, proxy, group and rule since the core started
+/// the top `limit` of each, 20 by default
+#[tauri::command]
+pub fn get_connection_stats(limit: Option<usize>) -> CmdResult<conn_stats::ConnStatsReport> {
+    Ok(conn_stats::ConnStats::global().report(limit))
+}
+
+/// refresh the tray icon by the proxy and core state
+#[tauri::command]
+pub fn refresh_tray_icon() -> CmdResult {
+    wrap_err!(handle::Handle::update_systray_part())
+}
+
+/// the result of the last startup script
+#[tauri::command]
+pub fn get_startup_script_result() -> CmdResult<Option<init::StartupScriptResult>> {
+    Ok(init::StartupScriptResult::global().lock().clone())
+}
+
+#[tauri::command]
+pub fn get_running_port() -> CmdResult<u16> {
+    Ok(feat::get_running_port())
+}
+
 #[tauri::command]
 pub async fn patch_clash_config(payload: Mapping) -> CmdResult {
     wrap_err!(feat::patch_clash(payload).await)
 }
 
+#[tauri::command]
+pub fn get_core_log_level() -> CmdResult<String> {
+    Ok(feat::get_core_log_level())
+}
+
+#[tauri::command]
+pub async fn set_core_log_level(level: String) -> CmdResult {
+    wrap_err!(feat::set_core_log_level(level).await)
+}
+
+#[tauri::command]
+pub fn get_dns_config() -> CmdResult<IClashDNS> {
+    Ok(feat::get_dns())
+}
+
+#[tauri::command]
+pub async fn patch_dns_config(payload: IClashDNS) -> CmdResult {
+    wrap_err!(feat::patch_dns(payload).await)
+}
+
 #[tauri::command]
 pub fn get_verge_config() -> CmdResult<IVerge> {
-    Ok(Config::verge().data().clone())
+    let mut verge = Config::verge().data().clone();
+    // 局域网认证只通过 `get_lan_auth_users` 返回用户名
+    verge.lan_authentication = None;
+    Ok(verge)
 }
 
 #[tauri::command]
@@ -170,17 +351,105 @@ pub async fn patch_verge_config(payload: IVerge) -> CmdResult {
     wrap_err!(feat::patch_verge(payload).await)
 }
 
+/// reset all the verge settings, return the backup path of the old `verge.yaml`
+/// none if there was no `verge.yaml` to backup
+#[tauri::command]
+pub async fn reset_verge_settings() -> CmdResult<Option<String>> {
+    let backup = wrap_err!(feat::reset_verge_settings().await)?;
+    Ok(backup.map(|path| path.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+pub async fn reset_verge_key(key: String) -> CmdResult<serde_json::Value> {
+    wrap_err!(feat::reset_verge_key(key).await)
+}
+
 #[tauri::command]
 pub async fn change_clash_core(clash_core: Option<String>) -> CmdResult {
     wrap_err!(CoreManager::global().change_core(clash_core).await)
 }
 
+#[tauri::command]
+pub async fn get_core_version() -> CmdResult<clash_api::CoreVersion> {
+    wrap_err!(CoreManager::global().get_version().await)
+}
+
+/// flush the fake-ip cache of the core
+#[tauri::command]
+pub async fn flush_fakeip() -> CmdResult<FlushResult> {
+    wrap_err!(CoreManager::global().flush_fakeip().await)
+}
+
+/// the unexpected exits of the core since the app started
+#[tauri::command]
+pub fn get_core_crash_state() -> CmdResult<CoreCrashState> {
+    Ok(CoreManager::global().crash_state())
+}
+
 /// restart the sidecar
 #[tauri::command]
 pub async fn restart_sidecar() -> CmdResult {
     wrap_err!(CoreManager::global().run_core().await)
 }
 
+#[tauri::command]
+pub async fn check_tun_capability() -> CmdResult<manager::TunCapability> {
+    Ok(manager::check_tun_capability().await)
+}
+
+#[tauri::command]
+pub fn is_elevated() -> CmdResult<bool> {
+    Ok(manager::is_elevated())
+}
+
+/// 下载并替换geodata文件，返回更新的时间
+#[tauri::command]
+pub async fn update_geodata() -> CmdResult<usize> {
+    wrap_err!(feat::update_geodata().await)
+}
+
+#[tauri::command]
+pub fn export_hotkeys() -> CmdResult<String> {
+    wrap_err!(feat::export_hotkeys())
+}
+
+#[tauri::command]
+pub async fn import_hotkeys(json: String) -> CmdResult {
+    wrap_err!(feat::import_hotkeys(json).await)
+}
+
+/// 当前订阅的节点都不可用时切换到备用订阅，None 时关闭
+#[tauri::command]
+pub async fn set_fallback_profile(uid: Option<String>) -> CmdResult {
+    wrap_err!(feat::set_fallback_profile(uid).await)
+}
+
+#[tauri::command]
+pub async fn set_tun_mode(enable: bool) -> CmdResult {
+    wrap_err!(feat::set_tun_mode(enable).await)
+}
+
+#[tauri::command]
+pub async fn set_tun_auto_route(enable: bool) -> CmdResult {
+    wrap_err!(feat::set_tun_auto_route(enable).await)
+}
+
+#[tauri::command]
+pub async fn set_tun_strict_route(enable: bool) -> CmdResult {
+    wrap_err!(feat::set_tun_strict_route(enable).await)
+}
+
+#[tauri::command]
+pub async fn set_tun_dns_hijack(list: Vec<String>) -> CmdResult {
+    wrap_err!(feat::set_tun_dns_hijack(list).await)
+}
+
+/// `system` / `gvisor` / `mixed`
+#[tauri::command]
+pub async fn set_tun_stack(stack: String) -> CmdResult {
+    wrap_err!(feat::set_tun_stack(stack).await)
+}
+
 #[tauri::command]
 pub fn grant_permission(_core: String) -> CmdResult {
     #[cfg(any(target_os = "macos", target_os = "linux"))]
@@ -190,6 +459,23 @@ pub fn grant_permission(_core: String) -> CmdResult {
     return Err("Unsupported target".into());
 }
 
+/// set the system proxy only, the core keeps running
+#[tauri::command]
+pub async fn enable_system_proxy() -> CmdResult {
+    wrap_err!(feat::set_system_proxy(true).await)
+}
+
+#[tauri::command]
+pub async fn disable_system_proxy() -> CmdResult {
+    wrap_err!(feat::set_system_proxy(false).await)
+}
+
+/// the system proxy set by other tools, for the onboarding
+#[tauri::command]
+pub fn detect_existing_proxy() -> CmdResult<Option<sysopt::ExistingProxy>> {
+    wrap_err!(sysopt::Sysopt::global().detect_existing_proxy())
+}
+
 /// get the system proxy
 #[tauri::command]
 pub fn get_sys_proxy() -> CmdResult<Mapping> {
@@ -206,15 +492,38 @@ pub fn get_sys_proxy() -> CmdResult<Mapping> {
     Ok(map)
 }
 
+/// get the proxy env statements for the shell
+#[tauri::command]
+pub fn get_proxy_env(shell: ShellKind) -> CmdResult<String> {
+    Ok(feat::get_proxy_env(shell))
+}
+
+#[tauri::command]
+pub fn copy_proxy_env(app_handle: tauri::AppHandle, shell: ShellKind) -> CmdResult {
+    wrap_err!(feat::copy_proxy_env(&app_handle, shell))
+}
+
 #[tauri::command]
 pub fn get_clash_logs() -> CmdResult<VecDeque<String>> {
     Ok(logger::Logger::global().get_log())
 }
 
+/// the core logs parsed with the level
+#[tauri::command]
+pub fn get_core_logs() -> CmdResult<Vec<logger::CoreLog>> {
+    Ok(logger::Logger::global().get_core_logs())
+}
+
 #[tauri::command]
 pub fn open_app_dir() -> CmdResult<()> {
     let app_dir = wrap_err!(dirs::app_home_dir())?;
-    wrap_err!(open::that(app_dir))
+    wrap_err!(feat::open_dir(app_dir))
+}
+
+/// the dir of the configs and profiles, same as the app dir
+#[tauri::command]
+pub fn open_config_dir() -> CmdResult<()> {
+    open_app_dir()
 }
 
 #[tauri::command]
@@ -227,7 +536,26 @@ pub fn open_core_dir() -> CmdResult<()> {
 #[tauri::command]
 pub fn open_logs_dir() -> CmdResult<()> {
     let log_dir = wrap_err!(dirs::app_logs_dir())?;
-    wrap_err!(open::that(log_dir))
+    wrap_err!(feat::open_dir(log_dir))
+}
+
+/// the last lines of the app log file
+#[tauri::command]
+pub fn read_log_tail(lines: usize) -> CmdResult<Vec<String>> {
+    let path = wrap_err!(log_tail::current_log_file())?;
+    let lines = lines.min(log_tail::MAX_TAIL_LINES);
+    wrap_err!(log_tail::read_tail(&path, lines).map(|(lines, _)| lines))
+}
+
+/// emit the new log lines with `verge://log-lines` until stopped
+#[tauri::command]
+pub fn start_log_stream(lines: usize) -> CmdResult<Vec<String>> {
+    wrap_err!(log_tail::start_stream(lines))
+}
+
+#[tauri::command]
+pub fn stop_log_stream() {
+    log_tail::stop_stream();
 }
 
 #[tauri::command]
@@ -252,10 +580,174 @@ pub async fn clash_api_get_proxy_delay(
     url: Option<String>,
     timeout: i32,
 ) -> CmdResult<clash_api::DelayRes> {
-    match clash_api::get_proxy_delay(name, url, timeout).await {
-        Ok(res) => Ok(res),
-        Err(err) => Err(err.to_string()),
-    }
+    let api = wrap_err!(clash_api::ClashApi::from_config())?;
+    wrap_err!(api.get_proxy_delay(&name, url, timeout).await)
+}
+
+#[tauri::command]
+pub fn get_mode() -> CmdResult<String> {
+    Ok(feat::get_clash_mode())
+}
+
+/// return the previous mode for undoing
+#[tauri::command]
+pub async fn set_mode(mode: String) -> CmdResult<String> {
+    wrap_err!(feat::set_clash_mode(mode).await)
+}
+
+/// test the proxies as a relay chain, in order
+/// the core reloads a temporary config during the test, the connections are dropped
+#[tauri::command]
+pub async fn test_proxy_chain(
+    names: Vec<String>,
+    url: Option<String>,
+    timeout: i32,
+) -> CmdResult<clash_api::ChainResult> {
+    wrap_err!(feat::test_proxy_chain(names, url, timeout).await)
+}
+
+#[tauri::command]
+pub async fn list_providers() -> CmdResult<Vec<clash_api::ProviderInfo>> {
+    let api = wrap_err!(clash_api::ClashApi::from_config())?;
+    let mut list = wrap_err!(api.get_providers(clash_api::ProviderKind::Proxies).await)?;
+    list.extend(wrap_err!(
+        api.get_providers(clash_api::ProviderKind::Rules).await
+    )?);
+    Ok(list)
+}
+
+#[tauri::command]
+pub async fn refresh_provider(
+    kind: clash_api::ProviderKind,
+    name: String,
+) -> CmdResult<clash_api::ProviderInfo> {
+    let api = wrap_err!(clash_api::ClashApi::from_config())?;
+    wrap_err!(api.update_provider(kind, &name).await)
+}
+
+/// the rule providers of the profile, including the disabled ones
+#[tauri::command]
+pub fn list_rule_providers() -> CmdResult<Vec<feat::RuleProviderState>> {
+    Ok(feat::list_rule_providers())
+}
+
+#[tauri::command]
+pub async fn set_rule_provider_enabled(name: String, enabled: bool) -> CmdResult {
+    wrap_err!(feat::set_rule_provider_enabled(name, enabled).await)
+}
+
+/// the users of the lan authentication, the passwords are not returned
+#[tauri::command]
+pub fn get_lan_auth_users() -> CmdResult<Vec<String>> {
+    Ok(feat::get_lan_auth_users())
+}
+
+#[tauri::command]
+pub async fn add_lan_auth_user(user: String, pass: String) -> CmdResult {
+    wrap_err!(feat::add_lan_auth_user(user, pass).await)
+}
+
+#[tauri::command]
+pub async fn remove_lan_auth_user(user: String) -> CmdResult {
+    wrap_err!(feat::remove_lan_auth_user(user).await)
+}
+
+/// the active connections of the core
+#[tauri::command]
+pub async fn get_connections() -> CmdResult<Vec<clash_api::ConnectionInfo>> {
+    let api = wrap_err!(clash_api::ClashApi::from_config())?;
+    wrap_err!(api.get_connections().await)
+}
+
+#[tauri::command]
+pub async fn close_connection(id: String) -> CmdResult {
+    let api = wrap_err!(clash_api::ClashApi::from_config())?;
+    wrap_err!(api.close_connection(&id).await)
+}
+
+/// the token of the local api, generated on the first call
+#[tauri::command]
+pub fn get_local_api_token() -> CmdResult<String> {
+    wrap_err!(local_api::read_token())
+}
+
+#[tauri::command]
+pub fn reset_local_api_token() -> CmdResult<String> {
+    wrap_err!(local_api::reset_token())
+}
+
+/// the actual autostart state of the os
+#[tauri::command]
+pub fn is_autostart_enabled() -> CmdResult<bool> {
+    wrap_err!(sysopt::Sysopt::global().is_autostart_enabled())
+}
+
+/// toggle the auto launch and return the os state after the change
+#[tauri::command]
+pub async fn set_auto_launch(enable: bool) -> CmdResult<bool> {
+    wrap_err!(feat::set_auto_launch(enable).await)
+}
+
+/// set the dedicated socks / http ports and reload the core
+#[tauri::command]
+pub async fn set_proxy_ports(socks_port: Option<u16>, port: Option<u16>) -> CmdResult {
+    wrap_err!(feat::set_proxy_ports(socks_port, port).await)
+}
+
+#[tauri::command]
+pub async fn set_always_on_top(enable: bool) -> CmdResult {
+    wrap_err!(feat::set_always_on_top(enable).await)
+}
+
+/// force the software rendering, restart the app to apply
+#[tauri::command]
+pub async fn set_software_rendering(enable: bool) -> CmdResult {
+    wrap_err!(feat::set_software_rendering(enable).await)
+}
+
+/// pause the auto updates of the profiles
+#[tauri::command]
+pub async fn pause_timers() -> CmdResult {
+    wrap_err!(feat::set_timer_paused(true).await)
+}
+
+#[tauri::command]
+pub async fn resume_timers() -> CmdResult {
+    wrap_err!(feat::set_timer_paused(false).await)
+}
+
+#[tauri::command]
+pub fn is_timer_paused() -> CmdResult<bool> {
+    Ok(timer::Timer::global().is_paused())
+}
+
+/// the configured ports and whether they are free
+#[tauri::command]
+pub fn check_ports() -> CmdResult<Vec<resolve::PortStatus>> {
+    Ok(resolve::check_ports())
+}
+
+#[tauri::command]
+pub fn is_port_available(port: u16) -> CmdResult<bool> {
+    Ok(resolve::is_port_available(port))
+}
+
+/// the time spent by each startup phase
+#[tauri::command]
+pub fn get_startup_timings() -> CmdResult<resolve::StartupTimings> {
+    Ok(resolve::StartupTimings::global().lock().clone())
+}
+
+/// check the resource files, the broken ones are copied from the bundle again
+#[tauri::command]
+pub fn verify_resources() -> CmdResult<Vec<init::ResourceState>> {
+    wrap_err!(resolve::check_resources(false))
+}
+
+/// recreate the missing app dirs
+#[tauri::command]
+pub fn repair_app_dirs() -> CmdResult<init::RepairReport> {
+    wrap_err!(init::repair_app_dirs())
 }
 
 #[tauri::command]
@@ -320,6 +812,20 @@ pub fn copy_icon_file(path: String, name: String) -> CmdResult<String> {
     }
 }
 
+/// pack the profiles and configs into a zip archive at `path`
+#[tauri::command]
+pub async fn export_backup(path: String, include_logs: Option<bool>) -> CmdResult {
+    let path = std::path::PathBuf::from(path);
+    wrap_err!(backup::create_backup(&path, include_logs.unwrap_or(false)))
+}
+
+/// restore the profiles and configs from the backup archive
+#[tauri::command]
+pub async fn restore_backup(path: String, force: Option<bool>) -> CmdResult {
+    let path = std::path::PathBuf::from(path);
+    wrap_err!(feat::restore_backup(path, force.unwrap_or(false)).await)
+}
+
 #[tauri::command]
 pub fn open_devtools(app_handle: tauri::AppHandle) {
     if let Some(window) = app_handle.get_window("main") {
@@ -331,11 +837,15 @@ pub fn open_devtools(app_handle: tauri::AppHandle) {
     }
 }
 
+/// 以管理员权限重启app
+#[tauri::command]
+pub async fn relaunch_as_admin(app_handle: tauri::AppHandle) -> CmdResult {
+    wrap_err!(resolve::relaunch_as_admin(&app_handle).await)
+}
+
 #[tauri::command]
 pub fn exit_app(app_handle: tauri::AppHandle) {
-    let _ = resolve::save_window_size_position(&app_handle, true);
-    resolve::resolve_reset();
-    api::process::kill_children();
+    resolve::on_exit(&app_handle);
     app_handle.exit(0);
     std::process::exit(0);
 }
@@ -1,5 +1,5 @@
 use crate::utils::{dirs, help};
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use serde_yaml::{Mapping, Value};
 use std::{
@@ -104,6 +104,13 @@ impl IClashTemp {
         Self::guard_port(&self.0)
     }
 
+    /// the dns config which overrides the profiles
+    pub fn get_dns(&self) -> Option<IClashDNS> {
+        self.0
+            .get("dns")
+            .and_then(|value| serde_yaml::from_value(value.clone()).ok())
+    }
+
     pub fn get_client_info(&self) -> ClashInfo {
         let config = &self.0;
 
@@ -112,14 +119,34 @@ impl IClashTemp {
             socks_port: Self::guard_socks_port(config),
             port: Self::guard_port(config),
             server: Self::guard_client_ctrl(config),
-            secret: config.get("secret").and_then(|value| match value {
-                Value::String(val_str) => Some(val_str.clone()),
-                Value::Bool(val_bool) => Some(val_bool.to_string()),
-                Value::Number(val_num) => Some(val_num.to_string()),
-                _ => None,
-            }),
+            // 空的secret等于不鉴权，不要发送空的 Bearer
+            secret: config
+                .get("secret")
+                .and_then(|value| match value {
+                    Value::String(val_str) => Some(val_str.trim().to_string()),
+                    Value::Bool(val_bool) => Some(val_bool.to_string()),
+                    Value::Number(val_num) => Some(val_num.to_string()),
+                    _ => None,
+                })
+                .filter(|secret| !secret.is_empty()),
+        }
+    }
+
+    /// the controller which the app connects to, the secret is redacted
+    pub fn get_controller_info(&self) -> ControllerInfo {
+        let server = Self::guard_client_ctrl(&self.0);
+        let addr = SocketAddr::from_str(&server).ok();
+        let secret = self.get_client_info().secret.unwrap_or_default();
+
+        ControllerInfo {
+            host: addr.map(|a| a.ip().to_string()).unwrap_or_default(),
+            port: addr.map(|a| a.port()).unwrap_or_default(),
+            server,
+            secret: redact_secret(&secret),
+            has_secret: !secret.is_empty(),
         }
     }
+
     #[cfg(not(target_os = "windows"))]
     pub fn guard_redir_port(config: &Mapping) -> u16 {
         let mut port = config
@@ -244,6 +271,38 @@ pub struct ClashInfo {
     pub secret: Option<String>,
 }
 
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ControllerInfo {
+    pub host: String,
+    pub port: u16,
+    /// `host:port`
+    pub server: String,
+    /// only the first and the last two chars are kept
+    pub secret: String,
+    pub has_secret: bool,
+}
+
+fn redact_secret(secret: &str) -> String {
+    let chars = secret.chars().collect::<Vec<char>>();
+    match chars.len() {
+        0 => String::new(),
+        len if len <= 6 => "*".repeat(len),
+        len => format!(
+            "{}{}{}",
+            chars[..2].iter().collect::<String>(),
+            "*".repeat(len - 4),
+            chars[len - 2..].iter().collect::<String>()
+        ),
+    }
+}
+
+#[test]
+fn test_redact_secret() {
+    assert_eq!(redact_secret(""), "");
+    assert_eq!(redact_secret("abc"), "***");
+    assert_eq!(redact_secret("abcdefgh"), "ab****gh");
+}
+
 #[test]
 fn test_clash_info() {
     fn get_case<T: Into<Value>, D: Into<Value>>(mp: T, ec: D) -> ClashInfo {
@@ -332,29 +391,299 @@ pub struct IClashTUN {
     pub auto_route: Option<bool>,
     pub auto_detect_interface: Option<bool>,
     pub dns_hijack: Option<Vec<String>>,
+    pub strict_route: Option<bool>,
+}
+
+/// the tun stacks supported by the core
+pub const TUN_STACKS: [&str; 3] = ["system", "gvisor", "mixed"];
+
+impl IClashTUN {
+    /// check the tun stack and return the lowercase one
+    pub fn check_stack(stack: &str) -> Result<String> {
+        let stack = stack.trim().to_lowercase();
+        if !TUN_STACKS.contains(&stack.as_str()) {
+            bail!(
+                "invalid tun stack \"{stack}\", should be one of {}",
+                TUN_STACKS.join("/")
+            );
+        }
+        Ok(stack)
+    }
+
+    /// check the `dns-hijack` entries like `any:53` / `tcp://any:53` / `8.8.8.8:53`
+    pub fn check_dns_hijack(list: &[String]) -> Result<Vec<String>> {
+        let mut result = vec![];
+        for item in list.iter() {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+
+            let addr = item
+                .strip_prefix("tcp://")
+                .or(item.strip_prefix("udp://"))
+                .unwrap_or(item);
+            let valid = match addr.rsplit_once(':') {
+                Some((host, port)) => {
+                    let host = host.trim_start_matches('[').trim_end_matches(']');
+                    (host == "any" || IpAddr::from_str(host).is_ok())
+                        && port.parse::<u16>().map_or(false, |p| p > 0)
+                }
+                None => false,
+            };
+            if !valid {
+                bail!("invalid dns-hijack \"{item}\"");
+            }
+            result.push(item.to_string());
+        }
+        Ok(result)
+    }
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub struct IClashDNS {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub enable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub listen: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub default_nameserver: Option<Vec<String>>,
+    /// `fake-ip` or `redir-host`
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub enhanced_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub fake_ip_range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub use_hosts: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub fake_ip_filter: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub nameserver: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub fallback: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub fallback_filter: Option<IClashFallbackFilter>,
-    pub nameserver_policy: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nameserver_policy: Option<Mapping>,
+}
+
+impl IClashDNS {
+    pub fn template() -> Self {
+        Self {
+            enable: Some(true),
+            enhanced_mode: Some("fake-ip".into()),
+            fake_ip_range: Some("198.18.0.1/16".into()),
+            nameserver: Some(vec![
+                "114.114.114.114".into(),
+                "223.5.5.5".into(),
+                "8.8.8.8".into(),
+            ]),
+            fallback: Some(vec![]),
+            #[cfg(target_os = "windows")]
+            fake_ip_filter: Some(vec![
+                "dns.msftncsi.com".into(),
+                "www.msftncsi.com".into(),
+                "www.msftconnecttest.com".into(),
+            ]),
+            ..Self::default()
+        }
+    }
+
+    pub fn patch_config(&mut self, patch: IClashDNS) {
+        macro_rules! patch {
+            ($key: tt) => {
+                if patch.$key.is_some() {
+                    self.$key = patch.$key;
+                }
+            };
+        }
+
+        patch!(enable);
+        patch!(listen);
+        patch!(default_nameserver);
+        patch!(enhanced_mode);
+        patch!(fake_ip_range);
+        patch!(use_hosts);
+        patch!(fake_ip_filter);
+        patch!(nameserver);
+        patch!(fallback);
+        patch!(fallback_filter);
+        patch!(nameserver_policy);
+    }
+
+    /// check the nameserver entries before writing to the config
+    pub fn validate(&self) -> Result<()> {
+        if self.enable.unwrap_or(false) && self.nameserver.as_ref().map_or(true, |n| n.is_empty()) {
+            bail!("the nameserver list should not be empty when dns is enabled");
+        }
+
+        if let Some(mode) = self.enhanced_mode.as_ref() {
+            if !["fake-ip", "redir-host", "normal"].contains(&mode.as_str()) {
+                bail!("invalid enhanced mode \"{mode}\"");
+            }
+        }
+
+        let servers = [
+            self.default_nameserver.as_ref(),
+            self.nameserver.as_ref(),
+            self.fallback.as_ref(),
+        ];
+        for server in servers.into_iter().flatten().flatten() {
+            if !is_valid_nameserver(server) {
+                bail!("invalid nameserver \"{server}\"");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// nameserver forms:
+/// `8.8.8.8` `udp://8.8.8.8:53` `tcp://[2001:4860::8888]:53`
+/// `tls://dns.google:853` `https://1.1.1.1/dns-query#Proxy` `dhcp://en0`
+pub fn is_valid_nameserver(server: &str) -> bool {
+    // `#` is followed by the outbound proxy or the interface
+    let server = server.split('#').next().unwrap_or("").trim();
+
+    let (scheme, rest) = match server.split_once("://") {
+        Some((scheme, rest)) => (scheme.to_lowercase(), rest),
+        None => ("udp".to_string(), server),
+    };
+
+    let is_host = |host: &str| {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        IpAddr::from_str(host).is_ok()
+            || (!host.is_empty()
+                && host.split('.').all(|l| {
+                    !l.is_empty() && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                }))
+    };
+
+    // host or host:port or [ipv6]:port
+    let split_authority = |authority: &str| -> Option<String> {
+        if IpAddr::from_str(authority).is_ok() {
+            return Some(authority.to_string());
+        }
+        match authority.rsplit_once(':') {
+            Some((host, port)) => port.parse::<u16>().ok().map(|_| host.to_string()),
+            None => Some(authority.to_string()),
+        }
+    };
+
+    match scheme.as_str() {
+        "udp" | "tcp" => match split_authority(rest) {
+            Some(host) => {
+                IpAddr::from_str(host.trim_start_matches('[').trim_end_matches(']')).is_ok()
+            }
+            None => false,
+        },
+        "tls" | "https" | "quic" => {
+            let authority = rest.split('/').next().unwrap_or("");
+            split_authority(authority).map_or(false, |host| is_host(&host))
+        }
+        "dhcp" => !rest.is_empty(),
+        _ => false,
+    }
+}
+
+#[test]
+fn test_nameserver() {
+    let valid = [
+        "8.8.8.8",
+        "8.8.8.8:53",
+        "2001:4860:4860::8888",
+        "[2001:4860:4860::8888]:53",
+        "udp://223.5.5.5",
+        "tcp://223.5.5.5:53",
+        "tls://dns.google:853",
+        "tls://1.1.1.1",
+        "https://1.1.1.1/dns-query",
+        "https://doh.pub/dns-query#Proxy",
+        "quic://dns.adguard.com:784",
+        "dhcp://en0",
+        "dhcp://system",
+    ];
+    let invalid = [
+        "",
+        "dns.google",
+        "udp://dns.google",
+        "tcp://8.8.8.8:99999",
+        "https://",
+        "https://bad_host/dns-query",
+        "dhcp://",
+        "ftp://8.8.8.8",
+    ];
+
+    for server in valid {
+        assert!(is_valid_nameserver(server), "{server}");
+    }
+    for server in invalid {
+        assert!(!is_valid_nameserver(server), "{server}");
+    }
+
+    let mut dns = IClashDNS::template();
+    assert!(dns.validate().is_ok());
+
+    dns.patch_config(IClashDNS {
+        nameserver: Some(vec![]),
+        ..IClashDNS::default()
+    });
+    assert!(dns.validate().is_err());
+
+    dns.patch_config(IClashDNS {
+        enable: Some(false),
+        ..IClashDNS::default()
+    });
+    assert!(dns.validate().is_ok());
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub struct IClashFallbackFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub geoip: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub geoip_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ipcidr: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub domain: Option<Vec<String>>,
 }
+
+#[test]
+fn test_client_secret() {
+    let secret = |value: Value| {
+        let mut map = Mapping::new();
+        map.insert("secret".into(), value);
+        IClashTemp(IClashTemp::guard(map)).get_client_info().secret
+    };
+
+    assert_eq!(secret("".into()), None);
+    assert_eq!(secret("  ".into()), None);
+    assert_eq!(secret(" abc ".into()), Some("abc".into()));
+    assert_eq!(secret(123.into()), Some("123".into()));
+    assert_eq!(secret(Value::Null), None);
+}
+
+#[test]
+fn test_tun_options() {
+    assert_eq!(IClashTUN::check_stack(" gVisor ").unwrap(), "gvisor");
+    assert!(IClashTUN::check_stack("lwip").is_err());
+
+    let list = vec![
+        "any:53".to_string(),
+        " tcp://any:53".into(),
+        "".into(),
+        "198.18.0.2:53".into(),
+        "udp://[::1]:53".into(),
+    ];
+    assert_eq!(
+        IClashTUN::check_dns_hijack(&list).unwrap(),
+        vec!["any:53", "tcp://any:53", "198.18.0.2:53", "udp://[::1]:53"]
+    );
+    assert!(IClashTUN::check_dns_hijack(&["any".into()]).is_err());
+    assert!(IClashTUN::check_dns_hijack(&["any:0".into()]).is_err());
+    assert!(IClashTUN::check_dns_hijack(&["example.com:53".into()]).is_err());
+    assert!(IClashTUN::check_dns_hijack(&["http://any:53".into()]).is_err());
+}
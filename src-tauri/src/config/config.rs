@@ -9,6 +9,7 @@ use std::{env::temp_dir, path::PathBuf};
 
 pub const RUNTIME_CONFIG: &str = "clash-verge.yaml";
 pub const CHECK_CONFIG: &str = "clash-verge-check.yaml";
+pub const CHAIN_TEST_CONFIG: &str = "clash-verge-chain-test.yaml";
 
 pub struct Config {
     clash_config: Draft<IClashTemp>,
@@ -47,6 +48,7 @@ impl Config {
 
     /// 初始化订阅
     pub fn init_config() -> Result<()> {
+        crate::log_err!(Self::apply_startup_profile());
         crate::log_err!(Self::generate());
         if let Err(err) = Self::generate_file(ConfigType::Run) {
             log::error!(target: "app", "{err}");
@@ -64,6 +66,55 @@ impl Config {
         Ok(())
     }
 
+    /// 按 `startup_profile_policy` 决定启动时激活的订阅
+    fn apply_startup_profile() -> Result<()> {
+        let (policy, uid) = {
+            let verge = Config::verge();
+            let verge = verge.latest();
+            (
+                verge.startup_profile_policy.clone(),
+                verge.startup_profile_uid.clone(),
+            )
+        };
+
+        match policy.as_deref() {
+            Some("specific-uid") => {
+                let uid = uid.ok_or(anyhow!("the startup profile uid is not set"))?;
+                let profiles = Config::profiles();
+                let mut profiles = profiles.data();
+                if profiles.get_item(&uid).is_err() {
+                    log::warn!(target: "app", "the startup profile \"uid:{uid}\" is not found, use the last selected");
+                    return Ok(());
+                }
+                if profiles.current.as_ref() != Some(&uid) {
+                    profiles.current = Some(uid);
+                    profiles.save_file()?;
+                }
+            }
+            // 不激活任何订阅，仅使用 config.yaml 启动内核
+            // 只在生成配置时跳过，不改动保存的current
+            Some("none") => {
+                Config::profiles().data().skip_current = true;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// 从文件重新读取所有配置
+    pub fn reload() -> Result<()> {
+        Config::clash().discard();
+        Config::verge().discard();
+        Config::profiles().discard();
+        Config::runtime().discard();
+
+        *Config::clash().data() = IClashTemp::new();
+        *Config::verge().data() = IVerge::new();
+        *Config::profiles().data() = IProfiles::new();
+
+        Self::init_config()
+    }
+
     /// 将订阅丢到对应的文件中
     pub fn generate_file(typ: ConfigType) -> Result<PathBuf> {
         let path = match typ {
@@ -78,13 +129,19 @@ impl Config {
             .as_ref()
             .ok_or(anyhow!("failed to get runtime config"))?;
 
-        help::save_yaml(&path, &config, Some("# Generated by Clash Verge"))?;
+        // 带着局域网认证的明文和 secret，只给自己读
+        help::save_private_yaml(&path, &config, "# Generated by Clash Verge")?;
         Ok(path)
     }
 
     /// 生成订阅存好
     pub fn generate() -> Result<()> {
-        let (config, exists_keys, logs) = enhance::enhance();
+        Self::generate_with(true)
+    }
+
+    /// 生成订阅，`use_scripts` 为假时跳过用户的script
+    pub fn generate_with(use_scripts: bool) -> Result<()> {
+        let (config, exists_keys, logs) = enhance::enhance_with(use_scripts);
 
         *Config::runtime().draft() = IRuntime {
             config: Some(config),
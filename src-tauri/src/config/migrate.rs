@@ -0,0 +1,93 @@
+use super::DEFAULT_WINDOW_MONITOR;
+use anyhow::{bail, Result};
+use serde_yaml::{Mapping, Value};
+
+/// the current schema version of `verge.yaml`
+pub const VERGE_VERSION: u64 = 1;
+
+const VERSION_KEY: &str = "config_version";
+
+type Migration = fn(&mut Mapping);
+
+/// `VERGE_MIGRATIONS[i]` upgrades the config from version `i` to `i + 1`
+const VERGE_MIGRATIONS: [Migration; VERGE_VERSION as usize] = [verge_v1];
+
+/// 没有版本号的都是版本 0
+pub fn verge_version(config: &Mapping) -> u64 {
+    config.get(VERSION_KEY).and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// 依次执行旧版本到当前版本的迁移，返回是否有改动
+/// 比当前更新的版本无法迁移，返回错误
+pub fn migrate_verge(config: &mut Mapping) -> Result<bool> {
+    let version = verge_version(config);
+    if version > VERGE_VERSION {
+        bail!("the verge config version {version} is newer than {VERGE_VERSION}");
+    }
+
+    for (from, migration) in VERGE_MIGRATIONS.iter().enumerate().skip(version as usize) {
+        log::info!(target: "app", "migrate the verge config from version {from}");
+        migration(config);
+        config.insert(VERSION_KEY.into(), (from as u64 + 1).into());
+    }
+    Ok(version < VERGE_VERSION)
+}
+
+/// v1: 窗口大小位置按显示器保存
+/// `window_size_position: [w, h, x, y]` -> `window_layouts: { default: [w, h, x, y] }`
+/// 旧配置不知道在哪个显示器上，记在默认的key下
+fn verge_v1(config: &mut Mapping) {
+    let Some(old) = config.remove("window_size_position") else {
+        return;
+    };
+    let valid = old.as_sequence().map_or(false, |seq| {
+        seq.len() == 4 && seq.iter().all(Value::is_number)
+    });
+    if !valid || config.contains_key("window_layouts") {
+        return;
+    }
+
+    let mut layouts = Mapping::new();
+    layouts.insert(DEFAULT_WINDOW_MONITOR.into(), old);
+    config.insert("window_layouts".into(), layouts.into());
+    config.insert("window_monitor".into(), DEFAULT_WINDOW_MONITOR.into());
+}
+
+#[test]
+fn test_migrate_verge() {
+    let mut config = Mapping::new();
+    config.insert("language".into(), "en".into());
+    assert!(migrate_verge(&mut config).unwrap());
+    assert_eq!(verge_version(&config), VERGE_VERSION);
+    assert_eq!(config.get("language"), Some(&"en".into()));
+
+    // 已经是当前版本
+    assert!(!migrate_verge(&mut config).unwrap());
+
+    let mut config = Mapping::new();
+    config.insert(VERSION_KEY.into(), (VERGE_VERSION + 1).into());
+    assert!(migrate_verge(&mut config).is_err());
+}
+
+#[test]
+fn test_verge_v1() {
+    let v0: Mapping =
+        serde_yaml::from_str("language: en\nwindow_size_position: [800.0, 640.0, 100.0, 50.0]\n")
+            .unwrap();
+
+    let mut config = v0.clone();
+    assert!(migrate_verge(&mut config).unwrap());
+    let expected: Mapping = serde_yaml::from_str(
+        "language: en\n\
+         window_layouts:\n  default: [800.0, 640.0, 100.0, 50.0]\n\
+         window_monitor: default\n\
+         config_version: 1\n",
+    )
+    .unwrap();
+    assert_eq!(config, expected);
+
+    // the broken value is dropped
+    let mut config: Mapping = serde_yaml::from_str("window_size_position: [800.0]\n").unwrap();
+    verge_v1(&mut config);
+    assert!(config.is_empty());
+}
@@ -1,6 +1,7 @@
 mod clash;
 mod config;
 mod draft;
+mod migrate;
 mod prfitem;
 mod profiles;
 mod runtime;
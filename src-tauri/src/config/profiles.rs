@@ -1,10 +1,16 @@
-use super::prfitem::PrfItem;
+use super::prfitem::{PrfItem, PrfOption, PrfValidation};
 use crate::utils::{dirs, help};
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_yaml::Mapping;
 use std::{fs, io::Write};
 
+/// the max length of `IProfiles.recent`
+const RECENT_LEN: usize = 20;
+
+/// the max chars of the profile name
+pub const PROFILE_NAME_MAX_LEN: usize = 64;
+
 /// Define the `profiles.yaml` schema
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct IProfiles {
@@ -16,6 +22,15 @@ pub struct IProfiles {
 
     /// profile list
     pub items: Option<Vec<PrfItem>>,
+
+    /// the uids of the recently used profiles, the latest first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recent: Option<Vec<String>>,
+
+    /// 启动策略为 `none` 时跳过current，不写入文件
+    /// 本次运行中选择订阅后恢复
+    #[serde(skip)]
+    pub skip_current: bool,
 }
 
 macro_rules! patch {
@@ -77,6 +92,8 @@ impl IProfiles {
 
             if items.iter().any(|e| e.uid == some_uid) {
                 self.current = some_uid;
+                self.skip_current = false;
+                self.mark_recent();
             }
         }
 
@@ -87,6 +104,40 @@ impl IProfiles {
         Ok(())
     }
 
+    /// move the current to the front of the recent list
+    fn mark_recent(&mut self) {
+        if let Some(current) = self.current.clone() {
+            let recent = self.recent.get_or_insert(vec![]);
+            recent.retain(|uid| uid != &current);
+            recent.insert(0, current);
+            recent.truncate(RECENT_LEN);
+        }
+    }
+
+    /// the base profiles for quick switching
+    /// the recently used first, then the others in the list order
+    pub fn recent_items(&self, limit: usize) -> Vec<&PrfItem> {
+        let items = match self.items.as_ref() {
+            Some(items) => items,
+            None => return vec![],
+        };
+        let recent = self.recent.clone().unwrap_or_default();
+        let find = |uid: &String| items.iter().find(|e| e.uid.as_ref() == Some(uid));
+
+        let mut list = recent
+            .iter()
+            .filter_map(find)
+            .filter(|e| e.is_base())
+            .collect::<Vec<&PrfItem>>();
+        for item in items.iter().filter(|e| e.is_base()) {
+            if !list.iter().any(|e| e.uid == item.uid) {
+                list.push(item);
+            }
+        }
+        list.truncate(limit);
+        list
+    }
+
     pub fn get_current(&self) -> Option<String> {
         self.current.clone()
     }
@@ -118,6 +169,9 @@ impl IProfiles {
         if item.uid.is_none() {
             bail!("the uid should not be null");
         }
+        item.check_tags()?;
+        item.color = item.color.filter(|c| !c.is_empty());
+        item.icon = item.icon.filter(|i| !i.is_empty());
 
         // save the file data
         // move the field value after save
@@ -170,7 +224,9 @@ impl IProfiles {
     }
 
     /// update the item value
-    pub fn patch_item(&mut self, uid: String, item: PrfItem) -> Result<()> {
+    /// the empty `color` / `icon` removes the tag
+    pub fn patch_item(&mut self, uid: String, mut item: PrfItem) -> Result<()> {
+        item.check_tags()?;
         let mut items = self.items.take().unwrap_or_default();
 
         for each in items.iter_mut() {
@@ -178,12 +234,19 @@ impl IProfiles {
                 patch!(each, item, itype);
                 patch!(each, item, name);
                 patch!(each, item, desc);
+                patch!(each, item, group);
+                patch!(each, item, icon);
+                patch!(each, item, color);
+                each.icon = each.icon.take().filter(|i| !i.is_empty());
+                each.color = each.color.take().filter(|c| !c.is_empty());
                 patch!(each, item, file);
                 patch!(each, item, url);
                 patch!(each, item, selected);
                 patch!(each, item, extra);
                 patch!(each, item, updated);
                 patch!(each, item, option);
+                patch!(each, item, base);
+                patch!(each, item, merge_order);
 
                 self.items = Some(items);
                 return self.save_file();
@@ -194,8 +257,184 @@ impl IProfiles {
         bail!("failed to find the profile item \"uid:{uid}\"")
     }
 
+    /// change the display name only
+    /// the uid, the file and the chain references stay the same
+    pub fn rename_item(&mut self, uid: &String, name: &str) -> Result<()> {
+        self.set_item_name(uid, name)?;
+        self.save_file()
+    }
+
+    fn set_item_name(&mut self, uid: &String, name: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("the profile name can not be empty");
+        }
+        if name.chars().count() > PROFILE_NAME_MAX_LEN {
+            bail!("the profile name is longer than {PROFILE_NAME_MAX_LEN} chars");
+        }
+
+        let item = self
+            .items
+            .as_mut()
+            .and_then(|items| items.iter_mut().find(|e| e.uid.as_ref() == Some(uid)))
+            .ok_or(anyhow!("failed to find the profile item \"uid:{uid}\""))?;
+        item.name = Some(name.to_string());
+        Ok(())
+    }
+
+    /// set the auto update interval in minutes, `0` disables it
+    pub fn set_update_interval(&mut self, uid: &String, minutes: u64) -> Result<()> {
+        let item = self
+            .items
+            .as_mut()
+            .and_then(|items| items.iter_mut().find(|e| e.uid.as_ref() == Some(uid)))
+            .ok_or(anyhow!("failed to find the profile item \"uid:{uid}\""))?;
+        if item.itype.as_deref() != Some("remote") {
+            bail!("only the remote profile can be updated");
+        }
+
+        let option = item.option.get_or_insert_with(PrfOption::default);
+        option.update_interval = (minutes > 0).then_some(minutes);
+        self.save_file()
+    }
+
+    /// the merge and script items applied to the current profile in order
+    /// use the chain of the current profile if set, otherwise the global chain
+    /// the deleted or invalid uids are skipped
+    /// sorted by `merge_order` stably, the equal ones keep the chain order
+    pub fn current_chain(&self) -> Vec<&PrfItem> {
+        let mut chain = self.chain_items();
+        chain.sort_by_key(|item| item.merge_order.unwrap_or(0));
+        chain
+    }
+
+    fn chain_items(&self) -> Vec<&PrfItem> {
+        let current = self
+            .active()
+            .filter(|uid| self.has_own_chain(uid))
+            .and_then(|uid| self.get_item(uid).ok());
+
+        if let Some(chain) = current.and_then(|item| item.chain.as_ref()) {
+            return chain
+                .iter()
+                .filter_map(|uid| match self.get_item(uid) {
+                    Ok(item) if item.is_enhance() => Some(item),
+                    _ => {
+                        log::warn!(target: "app", "skip the invalid chain item \"uid:{uid}\"");
+                        None
+                    }
+                })
+                .collect();
+        }
+
+        // 挂载到其他订阅上的项不生效
+        match self.chain.as_ref() {
+            Some(chain) => chain
+                .iter()
+                .filter_map(|uid| self.get_item(uid).ok())
+                .filter(|item| item.base.is_none() || item.base.as_ref() == self.active())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// move the merge or script item by `step` in the applying order
+    /// return the new `merge_order`
+    pub fn bump_merge_order(&mut self, uid: &String, step: i32) -> Result<i32> {
+        let item = self
+            .items
+            .as_mut()
+            .and_then(|items| items.iter_mut().find(|e| e.uid.as_ref() == Some(uid)))
+            .ok_or(anyhow!("failed to find the profile item \"uid:{uid}\""))?;
+        if !item.is_enhance() {
+            bail!("the profile \"uid:{uid}\" is not a merge or script");
+        }
+
+        let order = item.merge_order.unwrap_or(0).saturating_add(step);
+        item.merge_order = Some(order);
+        self.save_file()?;
+        Ok(order)
+    }
+
+    /// whether the base profile applies its own chain instead of the global one
+    /// 订阅的chain为空时用全局的chain
+    pub fn has_own_chain(&self, base: &String) -> bool {
+        self.get_item(base)
+            .ok()
+            .and_then(|item| item.chain.as_ref())
+            .map_or(false, |chain| !chain.is_empty())
+    }
+
+    /// add the merge or script item to the chain of the base profile
+    /// append if `index` is none
+    pub fn chain_add(&mut self, base: &String, uid: String, index: Option<usize>) -> Result<()> {
+        if !self.get_item(&uid)?.is_enhance() {
+            bail!("the profile \"uid:{uid}\" is not a merge or script");
+        }
+        let item = self.get_base_mut(base)?;
+        let chain = item.chain.get_or_insert(vec![]);
+        chain.retain(|e| e != &uid);
+        let index = index.unwrap_or(chain.len()).min(chain.len());
+        chain.insert(index, uid);
+        self.save_file()
+    }
+
+    /// remove the item from the chain of the base profile
+    pub fn chain_remove(&mut self, base: &String, uid: &String) -> Result<()> {
+        let item = self.get_base_mut(base)?;
+        if let Some(chain) = item.chain.as_mut() {
+            chain.retain(|e| e != uid);
+        }
+        self.save_file()
+    }
+
+    /// reorder the chain of the base profile, the uids should be the same set
+    pub fn chain_reorder(&mut self, base: &String, order: Vec<String>) -> Result<()> {
+        let item = self.get_base_mut(base)?;
+        let chain = item.chain.clone().unwrap_or_default();
+
+        let mut sorted = order.clone();
+        sorted.sort();
+        let mut current = chain;
+        current.sort();
+        if sorted != current {
+            bail!("the new order does not match the chain of \"uid:{base}\"");
+        }
+        item.chain = Some(order);
+        self.save_file()
+    }
+
+    fn get_base_mut(&mut self, base: &String) -> Result<&mut PrfItem> {
+        let item = self
+            .items
+            .as_mut()
+            .and_then(|items| items.iter_mut().find(|e| e.uid.as_ref() == Some(base)))
+            .ok_or(anyhow!("failed to find the profile item \"uid:{base}\""))?;
+        if !item.is_base() {
+            bail!("the profile \"uid:{base}\" can not have a chain");
+        }
+        Ok(item)
+    }
+
+    /// search the items by name, desc, group and url
+    /// return the matched uids, the more relevant the former
+    pub fn search(&self, query: &str) -> Vec<String> {
+        let items = match self.items.as_ref() {
+            Some(items) => items,
+            None => return vec![],
+        };
+
+        let mut matched = items
+            .iter()
+            .filter_map(|item| Some((item.uid.clone()?, search_score(item, query)?)))
+            .collect::<Vec<(String, u32)>>();
+        // the stable sort keeps the original order of the same score
+        matched.sort_by(|a, b| b.1.cmp(&a.1));
+        matched.into_iter().map(|(uid, _)| uid).collect()
+    }
+
     /// be used to update the remote item
-    /// only patch `updated` `extra` `file_data`
+    /// only patch `updated` `extra` `validation` `file_data`
     pub fn update_item(&mut self, uid: String, mut item: PrfItem) -> Result<()> {
         if self.items.is_none() {
             self.items = Some(vec![]);
@@ -212,6 +451,12 @@ impl IProfiles {
                     each.extra = item.extra;
                     each.updated = item.updated;
                     each.home = item.home;
+                    each.validation = item.validation;
+                    each.download_via = item.download_via;
+                    each.etag = item.etag;
+                    each.last_modified = item.last_modified;
+                    each.content_hash = item.content_hash;
+                    each.checked = item.checked;
                     // save the file data
                     // move the field value after save
                     if let Some(file_data) = item.file_data.take() {
@@ -238,6 +483,40 @@ impl IProfiles {
         self.save_file()
     }
 
+    /// the remote profile is checked but unchanged
+    pub fn set_checked(&mut self, uid: &String) -> Result<()> {
+        if let Some(items) = self.items.as_mut() {
+            if let Some(each) = items.iter_mut().find(|e| e.uid.as_ref() == Some(uid)) {
+                each.checked = Some(chrono::Local::now().timestamp() as usize);
+                return self.save_file();
+            }
+        }
+        bail!("failed to find the profile item \"uid:{uid}\"")
+    }
+
+    /// record the time and the error of the update, `None` clears the error
+    pub fn set_update_result(&mut self, uid: &String, error: Option<String>) -> Result<()> {
+        if let Some(items) = self.items.as_mut() {
+            if let Some(each) = items.iter_mut().find(|e| e.uid.as_ref() == Some(uid)) {
+                each.last_update_attempt = Some(chrono::Local::now().timestamp() as usize);
+                each.last_update_error = error;
+                return self.save_file();
+            }
+        }
+        bail!("failed to find the profile item \"uid:{uid}\"")
+    }
+
+    /// record the validation result of the item
+    pub fn set_validation(&mut self, uid: &String, validation: PrfValidation) -> Result<()> {
+        if let Some(items) = self.items.as_mut() {
+            if let Some(each) = items.iter_mut().find(|e| e.uid.as_ref() == Some(uid)) {
+                each.validation = Some(validation);
+                return self.save_file();
+            }
+        }
+        bail!("failed to find the profile item \"uid:{uid}\"")
+    }
+
     /// delete item
     /// if delete the current then return true
     pub fn delete_item(&mut self, uid: String) -> Result<bool> {
@@ -274,14 +553,31 @@ impl IProfiles {
             };
         }
 
+        if let Some(recent) = self.recent.as_mut() {
+            recent.retain(|e| e != &uid);
+        }
+        for item in items.iter_mut() {
+            if let Some(chain) = item.chain.as_mut() {
+                chain.retain(|e| e != &uid);
+            }
+        }
+
         self.items = Some(items);
         self.save_file()?;
         Ok(current == uid)
     }
 
+    /// the current profile used to generate the config
+    fn active(&self) -> Option<&String> {
+        match self.skip_current {
+            true => None,
+            false => self.current.as_ref(),
+        }
+    }
+
     /// 获取current指向的订阅内容
     pub fn current_mapping(&self) -> Result<Mapping> {
-        match (self.current.as_ref(), self.items.as_ref()) {
+        match (self.active(), self.items.as_ref()) {
             (Some(current), Some(items)) => {
                 if let Some(item) = items.iter().find(|e| e.uid.as_ref() == Some(current)) {
                     let file_path = match item.file.as_ref() {
@@ -296,3 +592,281 @@ impl IProfiles {
         }
     }
 }
+
+/// every keyword of the query should match one of the fields
+/// none if not matched
+fn search_score(item: &PrfItem, query: &str) -> Option<u32> {
+    let field = |v: &Option<String>| v.as_deref().unwrap_or_default().to_lowercase();
+    let name = field(&item.name);
+    let desc = field(&item.desc);
+    let group = field(&item.group);
+    let url = field(&item.url);
+
+    let mut score = 0;
+    for keyword in query.to_lowercase().split_whitespace() {
+        score += if name == keyword {
+            100
+        } else if name.starts_with(keyword) {
+            80
+        } else if name.contains(keyword) {
+            60
+        } else if group == keyword {
+            50
+        } else if group.contains(keyword) {
+            40
+        } else if desc.contains(keyword) {
+            30
+        } else if url.contains(keyword) {
+            10
+        } else {
+            return None;
+        };
+    }
+    Some(score)
+}
+
+#[test]
+fn test_search() {
+    let item = |uid: &str, name: &str, desc: &str, group: &str, url: &str| PrfItem {
+        uid: Some(uid.into()),
+        name: Some(name.into()),
+        desc: Some(desc.into()),
+        group: Some(group.into()),
+        url: Some(url.into()),
+        ..PrfItem::default()
+    };
+    let profiles = IProfiles {
+        items: Some(vec![
+            item("a", "Work Backup", "", "office", "https://a.example.com/s"),
+            item("b", "work", "", "", "https://b.example.com/s"),
+            item("c", "Home", "used at work", "", ""),
+            item("d", "Cloud", "", "", "https://work.example.com"),
+            item("e", "Networking", "", "", ""),
+        ]),
+        ..IProfiles::default()
+    };
+
+    // exact name > name prefix > name contains > desc > url
+    assert_eq!(profiles.search("WORK"), vec!["b", "a", "e", "c", "d"]);
+    // all the keywords should match
+    assert_eq!(profiles.search("work office"), vec!["a"]);
+    assert_eq!(profiles.search("example /s"), vec!["a", "b"]);
+    assert!(profiles.search("nothing").is_empty());
+    // the empty query matches all in order
+    assert_eq!(profiles.search("  ").len(), 5);
+}
+
+#[test]
+fn test_recent_items() {
+    let item = |uid: &str, itype: &str| PrfItem {
+        uid: Some(uid.into()),
+        itype: Some(itype.into()),
+        ..PrfItem::default()
+    };
+    let mut profiles = IProfiles {
+        items: Some(vec![
+            item("a", "remote"),
+            item("b", "local"),
+            item("m", "merge"),
+            item("c", "remote"),
+        ]),
+        ..IProfiles::default()
+    };
+    let uids = |profiles: &IProfiles, limit| {
+        profiles
+            .recent_items(limit)
+            .iter()
+            .map(|e| e.uid.clone().unwrap())
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(uids(&profiles, 5), vec!["a", "b", "c"]);
+
+    for uid in ["c", "m", "b"] {
+        let patch = IProfiles {
+            current: Some(uid.into()),
+            ..IProfiles::default()
+        };
+        profiles.patch_config(patch).unwrap();
+    }
+    assert_eq!(uids(&profiles, 5), vec!["b", "c", "a"]);
+    assert_eq!(uids(&profiles, 2), vec!["b", "c"]);
+}
+
+#[test]
+fn test_current_chain() {
+    let item = |uid: &str, itype: &str| PrfItem {
+        uid: Some(uid.into()),
+        itype: Some(itype.into()),
+        ..PrfItem::default()
+    };
+    let mut profiles = IProfiles {
+        current: Some("a".into()),
+        chain: Some(vec!["m1".into()]),
+        items: Some(vec![
+            item("a", "remote"),
+            item("b", "local"),
+            item("m1", "merge"),
+            item("m2", "merge"),
+            item("s1", "script"),
+        ]),
+        ..IProfiles::default()
+    };
+    let uids = |profiles: &IProfiles| {
+        profiles
+            .current_chain()
+            .iter()
+            .map(|e| e.uid.clone().unwrap())
+            .collect::<Vec<String>>()
+    };
+
+    // the global chain
+    assert_eq!(uids(&profiles), vec!["m1"]);
+
+    let base = "a".to_string();
+    let set = |profiles: &mut IProfiles, chain: Vec<&str>| {
+        let item = profiles.get_base_mut(&base).unwrap();
+        item.chain = Some(chain.into_iter().map(String::from).collect());
+    };
+    set(&mut profiles, vec!["s1", "deleted", "b", "m2"]);
+    assert_eq!(uids(&profiles), vec!["s1", "m2"]);
+
+    // 清空后回到全局的chain
+    set(&mut profiles, vec![]);
+    assert!(!profiles.has_own_chain(&base));
+    assert_eq!(uids(&profiles), vec!["m1"]);
+    assert!(profiles.get_base_mut(&"m1".to_string()).is_err());
+
+    // 有自己chain的订阅，挂载的merge要加到它的chain里才生效
+    set(&mut profiles, vec!["s1"]);
+    let mut merge = item("m3", "merge");
+    merge.base = Some(base.clone());
+    profiles.items.as_mut().unwrap().push(merge);
+    profiles.chain.as_mut().unwrap().push("m3".into());
+    assert!(profiles.has_own_chain(&base));
+    assert_eq!(uids(&profiles), vec!["s1"]);
+    set(&mut profiles, vec!["s1", "m3"]);
+    assert_eq!(uids(&profiles), vec!["s1", "m3"]);
+}
+
+#[test]
+fn test_rename_item() {
+    let item = |uid: &str, itype: &str| PrfItem {
+        uid: Some(uid.into()),
+        itype: Some(itype.into()),
+        name: Some(uid.into()),
+        file: Some(format!("{uid}.yaml")),
+        ..PrfItem::default()
+    };
+    let mut profiles = IProfiles {
+        current: Some("a".into()),
+        chain: Some(vec!["m1".into()]),
+        items: Some(vec![
+            item("a", "remote"),
+            item("m1", "merge"),
+            item("s1", "script"),
+        ]),
+        ..IProfiles::default()
+    };
+    profiles.get_base_mut(&"a".to_string()).unwrap().chain = Some(vec!["s1".into(), "m1".into()]);
+
+    profiles
+        .set_item_name(&"m1".into(), "  renamed merge ")
+        .unwrap();
+    profiles.set_item_name(&"a".into(), "renamed").unwrap();
+
+    let m1 = profiles.get_item(&"m1".into()).unwrap();
+    assert_eq!(m1.name.as_deref(), Some("renamed merge"));
+    assert_eq!(m1.file.as_deref(), Some("m1.yaml"));
+    assert_eq!(profiles.current.as_deref(), Some("a"));
+    assert_eq!(profiles.chain, Some(vec!["m1".to_string()]));
+
+    let chain = profiles
+        .current_chain()
+        .iter()
+        .map(|e| e.uid.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(chain, vec!["s1", "m1"]);
+
+    assert!(profiles.set_item_name(&"a".into(), " ").is_err());
+    assert!(profiles
+        .set_item_name(&"a".into(), &"x".repeat(PROFILE_NAME_MAX_LEN + 1))
+        .is_err());
+    assert!(profiles.set_item_name(&"none".into(), "name").is_err());
+    assert_eq!(
+        profiles.get_item(&"a".into()).unwrap().name.as_deref(),
+        Some("renamed")
+    );
+}
+
+#[test]
+fn test_merge_order() {
+    let item = |uid: &str, order: Option<i32>| PrfItem {
+        uid: Some(uid.into()),
+        itype: Some("merge".into()),
+        merge_order: order,
+        ..PrfItem::default()
+    };
+    let profiles = IProfiles {
+        current: Some("a".into()),
+        chain: Some(vec!["m1".into(), "m2".into(), "m3".into(), "m4".into()]),
+        items: Some(vec![
+            PrfItem {
+                uid: Some("a".into()),
+                itype: Some("remote".into()),
+                ..PrfItem::default()
+            },
+            item("m1", Some(1)),
+            item("m2", None),
+            item("m3", Some(-1)),
+            item("m4", Some(0)),
+        ]),
+        ..IProfiles::default()
+    };
+
+    let uids = profiles
+        .current_chain()
+        .iter()
+        .map(|e| e.uid.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(uids, vec!["m3", "m2", "m4", "m1"]);
+}
+
+#[test]
+fn test_skip_current() {
+    let item = |uid: &str, itype: &str| PrfItem {
+        uid: Some(uid.into()),
+        itype: Some(itype.into()),
+        ..PrfItem::default()
+    };
+    let mut profiles = IProfiles {
+        current: Some("a".into()),
+        chain: Some(vec!["m1".into()]),
+        items: Some(vec![
+            item("a", "remote"),
+            item("m1", "merge"),
+            item("m2", "merge"),
+        ]),
+        skip_current: true,
+        ..IProfiles::default()
+    };
+    profiles.get_base_mut(&"a".to_string()).unwrap().chain = Some(vec!["m2".into()]);
+
+    // the file of `a` is never read, the global chain is used
+    assert_eq!(profiles.current_mapping().unwrap(), Mapping::new());
+    assert_eq!(profiles.current_chain()[0].uid.as_deref(), Some("m1"));
+    assert_eq!(profiles.current.as_deref(), Some("a"));
+    assert!(!serde_yaml::to_string(&profiles).unwrap().contains("skip"));
+
+    profiles
+        .patch_config(IProfiles {
+            current: Some("a".into()),
+            ..IProfiles::default()
+        })
+        .unwrap();
+    assert!(!profiles.skip_current);
+    // the own chain of `a` is used again
+    let chain = profiles.current_chain();
+    assert_eq!(chain.len(), 1);
+    assert_eq!(chain[0].uid.as_deref(), Some("m2"));
+}
@@ -1,11 +1,21 @@
+use super::migrate;
+use crate::log_err;
 use crate::utils::{dirs, help};
-use anyhow::Result;
+use anyhow::{bail, Result};
 use log::LevelFilter;
 use serde::{Deserialize, Serialize};
+use serde_yaml::Mapping;
+use std::collections::BTreeMap;
+
+/// the key of the window layout when the monitor name is unknown
+pub const DEFAULT_WINDOW_MONITOR: &str = "default";
 
 /// ### `verge.yaml` schema
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct IVerge {
+    /// the schema version, see `migrate.rs`
+    pub config_version: Option<u64>,
+
     /// app listening port for app singleton
     pub app_singleton_port: Option<u16>,
 
@@ -55,6 +65,18 @@ pub struct IVerge {
     /// tun tray icon
     pub tun_tray_icon: Option<bool>,
 
+    /// show a badge of the global/direct mode on the tray icon
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tray_mode_icon: Option<bool>,
+
+    /// the max number of the profiles in the tray, 0 to hide
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tray_profiles_limit: Option<usize>,
+
+    /// show the `Quick Actions` submenu in the tray, default is true
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tray_quick_actions: Option<bool>,
+
     /// clash tun mode
     pub enable_tun_mode: Option<bool>,
 
@@ -65,9 +87,49 @@ pub struct IVerge {
     /// can the app auto startup
     pub enable_auto_launch: Option<bool>,
 
+    /// the args stored in the auto launch entry, e.g. `--silent`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_launch_args: Option<Vec<String>>,
+
     /// not show the window on launch
     pub enable_silent_start: Option<bool>,
 
+    /// create the window on launch but keep it hidden in the tray
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_minimized: Option<bool>,
+
+    /// enable the local api on the embed server (loopback only)
+    /// the requests should carry the token in `local-api.token`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable_local_api: Option<bool>,
+
+    /// which profile to activate on launch
+    /// `last-selected` | `specific-uid` | `none`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub startup_profile_policy: Option<String>,
+
+    /// the profile uid for the `specific-uid` policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub startup_profile_uid: Option<String>,
+
+    /// the default DoH server to resolve the remote profiles, see `PrfOption::doh_url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile_doh_url: Option<String>,
+
+    /// the max size of the downloaded profile in MB, 10 by default, 0 for no limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile_max_size_mb: Option<u64>,
+
+    /// the window close button behavior
+    /// true: hide the window to the tray; false: exit the app
+    /// none: destroy the window and keep the app running in the tray
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub close_to_tray: Option<bool>,
+
+    /// whether the user has been told the app keeps running in the tray
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub close_to_tray_noticed: Option<bool>,
+
     /// set system proxy
     pub enable_system_proxy: Option<bool>,
 
@@ -77,9 +139,19 @@ pub struct IVerge {
     /// set system proxy bypass
     pub system_proxy_bypass: Option<String>,
 
+    /// `fixed` or `pac`
+    /// the `pac` mode sets the os to the pac file served by the embed server
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_proxy_mode: Option<String>,
+
     /// proxy guard duration
     pub proxy_guard_duration: Option<u64>,
 
+    /// point the system proxy to a refused port if the core exits unexpectedly
+    /// the traffic fails instead of going directly, default is false
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kill_switch: Option<bool>,
+
     /// theme setting
     pub theme_setting: Option<IVergeTheme>,
 
@@ -90,6 +162,19 @@ pub struct IVerge {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub clash_core: Option<String>,
 
+    /// the custom core binary, use the bundled one if none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub core_binary_path: Option<String>,
+
+    /// the extra args passed to the core
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub core_extra_args: Option<Vec<String>>,
+
+    /// what to do when the core exits unexpectedly
+    /// `restart` (default) / `notify`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub core_crash_action: Option<String>,
+
     /// hotkey map
     /// format: {func},{key}
     pub hotkeys: Option<Vec<String>>,
@@ -115,21 +200,85 @@ pub struct IVerge {
     /// 测试网站列表
     pub test_list: Option<Vec<IVergeTestItem>>,
 
+    /// skip the auto updates of the profiles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timer_paused: Option<bool>,
+
     /// 日志清理
     /// 0: 不清理; 1: 7天; 2: 30天; 3: 90天
     pub auto_log_clean: Option<i32>,
 
-    /// window size and position
+    /// window size and position on each monitor, keyed by the monitor name
+    /// `[width, height, x, y]` in logical pixels
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub window_size_position: Option<Vec<f64>>,
+    pub window_layouts: Option<BTreeMap<String, Vec<f64>>>,
+
+    /// the monitor which the window was on when it was saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_monitor: Option<String>,
 
     /// window size and position
     #[serde(skip_serializing_if = "Option::is_none")]
     pub window_is_maximized: Option<bool>,
 
+    /// keep the main window above the others
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_always_on_top: Option<bool>,
+
+    /// windows only, the extra args of WebView2 like `--disable-gpu`
+    /// appended to the default args, takes effect after restarting the app
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webview_extra_args: Option<String>,
+
+    /// render the webview without the gpu, for the broken drivers
+    /// windows: `--disable-gpu` of WebView2, linux: `WEBKIT_DISABLE_COMPOSITING_MODE`
+    /// takes effect after restarting the app
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub software_rendering: Option<bool>,
+
+    /// the download urls of the geodata files, use the defaults if none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geodata_urls: Option<IVergeGeoData>,
+
+    /// the timestamp of the last successful geodata update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geodata_updated: Option<usize>,
+
+    /// switch to this profile when the nodes of the current one all fail
+    /// the health check is disabled if none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback_profile_uid: Option<String>,
+
+    /// detect the captive portal of the public wifi
+    /// offer to pause the system proxy until the network is connected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable_captive_portal_detect: Option<bool>,
+
     /// 是否启用随机端口
     pub enable_random_port: Option<bool>,
 
+    /// merge `dns_override` into the dns of every profile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enable_dns_override: Option<bool>,
+
+    /// the dns config which overrides the profile's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_override: Option<Mapping>,
+
+    /// the rule providers removed from every profile, with the rules using them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled_rule_providers: Option<Vec<String>>,
+
+    /// the `authentication` of the lan connections, `user:salt:sha256` of each user
+    /// the passwords for the core are kept in `lan-auth.yaml`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lan_authentication: Option<Vec<String>>,
+
+    /// the ip version of the local proxy port
+    /// `v4` (default) / `v6` / `dual`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip_version: Option<String>,
+
     /// verge 的各种 port 用于覆盖 clash 的各种 port
     #[cfg(not(target_os = "windows"))]
     pub verge_redir_port: Option<u16>,
@@ -162,6 +311,13 @@ pub struct IVergeTestItem {
     pub url: Option<String>,
 }
 
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct IVergeGeoData {
+    pub geoip: Option<String>,
+    pub geosite: Option<String>,
+    pub mmdb: Option<String>,
+}
+
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct IVergeTheme {
     pub primary_color: Option<String>,
@@ -180,7 +336,7 @@ pub struct IVergeTheme {
 
 impl IVerge {
     pub fn new() -> Self {
-        match dirs::verge_path().and_then(|path| help::read_yaml::<IVerge>(&path)) {
+        match Self::read_file() {
             Ok(config) => config,
             Err(err) => {
                 log::error!(target: "app", "{err}");
@@ -189,8 +345,36 @@ impl IVerge {
         }
     }
 
+    /// 读取前先迁移旧版本的配置
+    /// 未知的新版本先备份，再使用默认配置
+    fn read_file() -> Result<Self> {
+        let path = dirs::verge_path()?;
+        let mut config = help::read_yaml::<Mapping>(&path)?;
+
+        let migrated = match migrate::migrate_verge(&mut config) {
+            Ok(migrated) => migrated,
+            Err(err) => {
+                let backup = path.with_file_name(format!(
+                    "verge.v{}.yaml.bak",
+                    migrate::verge_version(&config)
+                ));
+                std::fs::copy(&path, &backup)?;
+                log::warn!(target: "app", "{err}, use the default config");
+                log::warn!(target: "app", "the verge config is backup at \"{}\"", backup.display());
+                return Ok(Self::template());
+            }
+        };
+
+        let verge: IVerge = serde_yaml::from_value(config.into())?;
+        if migrated {
+            log_err!(verge.save_file());
+        }
+        Ok(verge)
+    }
+
     pub fn template() -> Self {
         Self {
+            config_version: Some(migrate::VERGE_VERSION),
             clash_core: Some("clash-meta".into()),
             language: Some("zh".into()),
             theme_mode: Some("system".into()),
@@ -208,6 +392,9 @@ impl IVerge {
             common_tray_icon: Some(false),
             sysproxy_tray_icon: Some(false),
             tun_tray_icon: Some(false),
+            tray_mode_icon: Some(false),
+            tray_profiles_limit: Some(5),
+            tray_quick_actions: Some(true),
             enable_auto_launch: Some(false),
             enable_silent_start: Some(false),
             enable_system_proxy: Some(false),
@@ -227,6 +414,7 @@ impl IVerge {
             verge_http_enabled: Some(true),
             enable_proxy_guard: Some(false),
             proxy_guard_duration: Some(30),
+            kill_switch: Some(false),
             auto_close_connection: Some(true),
             auto_check_update: Some(true),
             enable_builtin_enhanced: Some(true),
@@ -267,12 +455,28 @@ impl IVerge {
         patch!(common_tray_icon);
         patch!(sysproxy_tray_icon);
         patch!(tun_tray_icon);
+        patch!(tray_mode_icon);
+        patch!(tray_profiles_limit);
+        patch!(tray_quick_actions);
 
         patch!(enable_tun_mode);
         patch!(enable_service_mode);
         patch!(enable_auto_launch);
+        patch!(auto_launch_args);
         patch!(enable_silent_start);
+        patch!(start_minimized);
+        patch!(close_to_tray);
+        patch!(enable_local_api);
+        patch!(startup_profile_policy);
+        patch!(startup_profile_uid);
+        patch!(profile_doh_url);
+        patch!(profile_max_size_mb);
         patch!(enable_random_port);
+        patch!(ip_version);
+        patch!(enable_dns_override);
+        patch!(dns_override);
+        patch!(disabled_rule_providers);
+        patch!(lan_authentication);
         #[cfg(not(target_os = "windows"))]
         patch!(verge_redir_port);
         #[cfg(not(target_os = "windows"))]
@@ -288,12 +492,17 @@ impl IVerge {
         patch!(verge_http_enabled);
         patch!(enable_system_proxy);
         patch!(enable_proxy_guard);
+        patch!(kill_switch);
         patch!(system_proxy_bypass);
+        patch!(system_proxy_mode);
         patch!(proxy_guard_duration);
 
         patch!(theme_setting);
         patch!(web_ui_list);
         patch!(clash_core);
+        patch!(core_binary_path);
+        patch!(core_extra_args);
+        patch!(core_crash_action);
         patch!(hotkeys);
 
         patch!(auto_close_connection);
@@ -304,8 +513,119 @@ impl IVerge {
         patch!(proxy_layout_column);
         patch!(test_list);
         patch!(auto_log_clean);
-        patch!(window_size_position);
+        patch!(timer_paused);
+        patch!(window_layouts);
+        patch!(window_monitor);
         patch!(window_is_maximized);
+        patch!(window_always_on_top);
+        patch!(webview_extra_args);
+        patch!(software_rendering);
+        patch!(geodata_urls);
+        patch!(geodata_updated);
+        patch!(fallback_profile_uid);
+        patch!(enable_captive_portal_detect);
+    }
+
+    /// reset the setting to the value of the template
+    /// only the patchable settings can be reset, return the new value
+    pub fn reset_key(&mut self, key: &str) -> Result<serde_json::Value> {
+        let template = Self::template();
+
+        macro_rules! reset {
+            ($key: tt) => {
+                if key == stringify!($key) {
+                    self.$key = template.$key;
+                    return Ok(serde_json::to_value(&self.$key)?);
+                }
+            };
+        }
+
+        reset!(app_log_level);
+        reset!(language);
+        reset!(theme_mode);
+        reset!(tray_event);
+        reset!(env_type);
+        reset!(start_page);
+        reset!(startup_script);
+        reset!(traffic_graph);
+        reset!(enable_memory_usage);
+        reset!(enable_group_icon);
+        #[cfg(target_os = "macos")]
+        reset!(tray_icon);
+        reset!(menu_icon);
+        reset!(common_tray_icon);
+        reset!(sysproxy_tray_icon);
+        reset!(tun_tray_icon);
+        reset!(tray_mode_icon);
+        reset!(tray_profiles_limit);
+        reset!(tray_quick_actions);
+
+        reset!(enable_tun_mode);
+        reset!(enable_service_mode);
+        reset!(enable_auto_launch);
+        reset!(auto_launch_args);
+        reset!(enable_silent_start);
+        reset!(start_minimized);
+        reset!(close_to_tray);
+        reset!(enable_local_api);
+        reset!(startup_profile_policy);
+        reset!(startup_profile_uid);
+        reset!(profile_doh_url);
+        reset!(profile_max_size_mb);
+        reset!(enable_random_port);
+        reset!(ip_version);
+        reset!(enable_dns_override);
+        reset!(dns_override);
+        reset!(disabled_rule_providers);
+        reset!(lan_authentication);
+        #[cfg(not(target_os = "windows"))]
+        reset!(verge_redir_port);
+        #[cfg(not(target_os = "windows"))]
+        reset!(verge_redir_enabled);
+        #[cfg(target_os = "linux")]
+        reset!(verge_tproxy_port);
+        #[cfg(target_os = "linux")]
+        reset!(verge_tproxy_enabled);
+        reset!(verge_mixed_port);
+        reset!(verge_socks_port);
+        reset!(verge_socks_enabled);
+        reset!(verge_port);
+        reset!(verge_http_enabled);
+        reset!(enable_system_proxy);
+        reset!(enable_proxy_guard);
+        reset!(kill_switch);
+        reset!(system_proxy_bypass);
+        reset!(system_proxy_mode);
+        reset!(proxy_guard_duration);
+
+        reset!(theme_setting);
+        reset!(web_ui_list);
+        reset!(clash_core);
+        reset!(core_binary_path);
+        reset!(core_extra_args);
+        reset!(core_crash_action);
+        reset!(hotkeys);
+
+        reset!(auto_close_connection);
+        reset!(auto_check_update);
+        reset!(default_latency_test);
+        reset!(default_latency_timeout);
+        reset!(enable_builtin_enhanced);
+        reset!(proxy_layout_column);
+        reset!(test_list);
+        reset!(auto_log_clean);
+        reset!(timer_paused);
+        reset!(window_layouts);
+        reset!(window_monitor);
+        reset!(window_is_maximized);
+        reset!(window_always_on_top);
+        reset!(webview_extra_args);
+        reset!(software_rendering);
+        reset!(geodata_urls);
+        reset!(fallback_profile_uid);
+        reset!(enable_captive_portal_detect);
+
+        bail!("the setting \"{key}\" is not found")
     }
 
     /// 在初始化前尝试拿到单例端口的值
@@ -1,87 +1,602 @@
 use crate::config::Config;
-use anyhow::{bail, Result};
-use reqwest::header::HeaderMap;
+use anyhow::{anyhow, bail, Context, Result};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{Method, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
 use serde_yaml::Mapping;
 use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
 
-/// PUT /configs
-/// path 是绝对路径
-pub async fn put_configs(path: &str) -> Result<()> {
-    let (url, headers) = clash_client_info()?;
-    let url = format!("{url}/configs");
-
-    let mut data = HashMap::new();
-    data.insert("path", path);
-
-    let client = reqwest::ClientBuilder::new().no_proxy().build()?;
-    let builder = client.put(&url).headers(headers).json(&data);
-    let response = builder.send().await?;
+/// the non-2xx responses of the controller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClashApiError {
+    /// 401, the secret is missing or wrong
+    Unauthorized,
+    /// 404, the core does not support the endpoint or the resource is missing
+    NotFound,
+    /// the other status with the message of the core
+    Status(u16, String),
+}
 
-    match response.status().as_u16() {
-        204 => Ok(()),
-        status => {
-            bail!("failed to put configs with status \"{status}\"")
+impl fmt::Display for ClashApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unauthorized => write!(f, "the clash controller rejects the secret"),
+            Self::NotFound => write!(f, "the clash controller returns not found"),
+            Self::Status(status, msg) if msg.is_empty() => {
+                write!(f, "the clash controller returns status \"{status}\"")
+            }
+            Self::Status(status, msg) => {
+                write!(f, "the clash controller returns status \"{status}\": {msg}")
+            }
         }
     }
 }
 
-/// PATCH /configs
-pub async fn patch_configs(config: &Mapping) -> Result<()> {
-    let (url, headers) = clash_client_info()?;
-    let url = format!("{url}/configs");
+impl std::error::Error for ClashApiError {}
 
-    let client = reqwest::ClientBuilder::new().no_proxy().build()?;
-    let builder = client.patch(&url).headers(headers.clone()).json(config);
-    builder.send().await?;
-    Ok(())
+impl ClashApiError {
+    /// whether the error is a 404 of the controller
+    pub fn is_not_found(err: &anyhow::Error) -> bool {
+        matches!(err.downcast_ref::<Self>(), Some(Self::NotFound))
+    }
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct DelayRes {
-    delay: u64,
+    pub delay: u64,
+}
+
+/// the delay test of one hop in the chain
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ChainHop {
+    pub name: String,
+    /// the delay through this hop and all the hops before it
+    pub delay: Option<u64>,
+    /// `timeout` or the error of the core, none if not tested
+    pub error: Option<String>,
+}
+
+/// the result of `test_chain`
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ChainResult {
+    pub hops: Vec<ChainHop>,
+    /// the index of the first failed hop
+    pub failed_hop: Option<usize>,
+    /// the delay of the whole chain, none if any hop fails
+    pub delay: Option<u64>,
+}
+
+impl ChainResult {
+    pub fn from_hops(hops: Vec<ChainHop>) -> Self {
+        let failed_hop = hops.iter().position(|hop| hop.delay.is_none());
+        let delay = match failed_hop {
+            Some(_) => None,
+            None => hops.last().and_then(|hop| hop.delay),
+        };
+        Self {
+            hops,
+            failed_hop,
+            delay,
+        }
+    }
+}
+
+/// the normalized `/version` payload
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CoreVersion {
+    pub version: String,
+    /// mihomo (clash meta)
+    pub meta: bool,
+    /// clash premium
+    pub premium: bool,
+    pub features: Vec<String>,
+    /// the path of the core binary, filled by the `CoreManager`
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl CoreVersion {
+    /// mihomo: `{"meta":true,"version":"v1.18.1"}`
+    /// clash premium: `{"premium":true,"version":"2023.08.17"}`
+    /// clash: `{"version":"v1.18.0"}`
+    pub fn parse(value: &serde_json::Value) -> Result<Self> {
+        let version = value
+            .get("version")
+            .and_then(|v| v.as_str())
+            .ok_or(anyhow!("failed to get the core version"))?;
+        let flag = |key: &str| value.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+
+        let lower = version.to_lowercase();
+        let features = value
+            .get("features")
+            .and_then(|v| v.as_array())
+            .map(|list| {
+                list.iter()
+                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            version: version.to_string(),
+            meta: flag("meta") || lower.contains("meta") || lower.contains("mihomo"),
+            premium: flag("premium"),
+            features,
+            path: None,
+        })
+    }
+}
+
+/// the kind of the external providers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Rules,
+    Proxies,
+}
+
+impl ProviderKind {
+    fn path(&self) -> &'static str {
+        match self {
+            ProviderKind::Rules => "rules",
+            ProviderKind::Proxies => "proxies",
+        }
+    }
+}
+
+/// the normalized provider info
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ProviderInfo {
+    pub name: String,
+    pub kind: ProviderKind,
+    /// `HTTP` | `File` | `Inline`
+    pub vehicle_type: String,
+    /// rule provider only: `domain` | `ipcidr` | `classical`
+    pub behavior: Option<String>,
+    pub updated_at: Option<String>,
+    /// the rule count or the proxy count
+    pub count: usize,
+}
+
+impl ProviderInfo {
+    /// proxies: `{"name":"p","vehicleType":"HTTP","proxies":[...],"updatedAt":"..."}`
+    /// rules: `{"name":"r","vehicleType":"HTTP","behavior":"Domain","ruleCount":10,"updatedAt":"..."}`
+    /// return none for the `Compatible` providers which are the proxy groups actually
+    pub fn parse(kind: ProviderKind, value: &serde_json::Value) -> Option<Self> {
+        let field = |key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+
+        let name = field("name")?;
+        let vehicle_type = field("vehicleType").unwrap_or_default();
+        if vehicle_type == "Compatible" {
+            return None;
+        }
+
+        let count = match kind {
+            ProviderKind::Rules => value.get("ruleCount").and_then(|v| v.as_u64()),
+            ProviderKind::Proxies => value
+                .get("proxies")
+                .and_then(|v| v.as_array())
+                .map(|v| v.len() as u64),
+        };
+
+        Some(Self {
+            name,
+            kind,
+            vehicle_type,
+            behavior: field("behavior").map(|s| s.to_lowercase()),
+            updated_at: field("updatedAt"),
+            count: count.unwrap_or(0) as usize,
+        })
+    }
+}
+
+/// the memory of the core, in bytes
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CoreMemory {
+    pub inuse: u64,
+    pub oslimit: u64,
+}
+
+/// the response of `/connections`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ConnectionsSnapshot {
+    /// the bytes since the core started, including the closed connections
+    pub upload_total: u64,
+    pub download_total: u64,
+    pub connections: Vec<ConnectionInfo>,
+}
+
+impl ConnectionsSnapshot {
+    /// `{"downloadTotal":1,"uploadTotal":2,"connections":[..]}`
+    pub fn parse(value: &serde_json::Value) -> Self {
+        let total = |key: &str| value.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+        Self {
+            upload_total: total("uploadTotal"),
+            download_total: total("downloadTotal"),
+            connections: value
+                .get("connections")
+                .and_then(|v| v.as_array())
+                .map(|list| list.iter().filter_map(ConnectionInfo::parse).collect())
+                .unwrap_or_default(),
+        }
+    }
 }
 
-/// GET /proxies/{name}/delay
-/// 获取代理延迟
-pub async fn get_proxy_delay(
-    name: String,
-    test_url: Option<String>,
-    timeout: i32,
-) -> Result<DelayRes> {
-    let (url, headers) = clash_client_info()?;
-    let url = format!("{url}/proxies/{name}/delay");
+/// the normalized connection of `/connections`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub id: String,
+    /// `tcp` | `udp`
+    pub network: String,
+    /// `ip:port`
+    pub source: String,
+    /// `host:port`, the ip if no host
+    pub destination: String,
+    /// the rule with the payload, `DomainSuffix(example.com)`
+    pub rule: String,
+    pub chains: Vec<String>,
+    /// in bytes
+    pub upload: u64,
+    pub download: u64,
+    pub start: Option<String>,
+}
 
-    let default_url = "http://1.1.1.1";
-    let test_url = test_url
-        .map(|s| if s.is_empty() { default_url.into() } else { s })
-        .unwrap_or(default_url.into());
+impl ConnectionInfo {
+    /// `{"id":"..","metadata":{"network":"tcp","sourceIP":"..","sourcePort":"..","host":"..",
+    /// "destinationIP":"..","destinationPort":".."},"upload":1,"download":2,"start":"..",
+    /// "chains":["DIRECT"],"rule":"Match","rulePayload":""}`
+    pub fn parse(value: &serde_json::Value) -> Option<Self> {
+        let text = |key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+        let id = text("id")?;
+        let meta = value.get("metadata");
+        // 端口有的内核是字符串有的是数字
+        let field = |key: &str| match meta.and_then(|m| m.get(key)) {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(serde_json::Value::Number(n)) => n.to_string(),
+            _ => String::new(),
+        };
+        let addr = |host: String, port: String| match port.is_empty() {
+            true => host,
+            false if host.contains(':') => format!("[{host}]:{port}"),
+            false => format!("{host}:{port}"),
+        };
 
-    let client = reqwest::ClientBuilder::new().no_proxy().build()?;
-    let builder = client
-        .get(&url)
-        .headers(headers)
-        .query(&[("timeout", &format!("{timeout}")), ("url", &test_url)]);
-    let response = builder.send().await?;
+        let host = Some(field("host"))
+            .filter(|h| !h.is_empty())
+            .unwrap_or(field("destinationIP"));
+        let rule = text("rule").unwrap_or_default();
+        let rule = match text("rulePayload").filter(|p| !p.is_empty()) {
+            Some(payload) => format!("{rule}({payload})"),
+            None => rule,
+        };
+        let chains = value
+            .get("chains")
+            .and_then(|v| v.as_array())
+            .map(|list| {
+                list.iter()
+                    .filter_map(|v| v.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let bytes = |key: &str| value.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
 
-    Ok(response.json::<DelayRes>().await?)
+        Some(Self {
+            id,
+            network: field("network"),
+            source: addr(field("sourceIP"), field("sourcePort")),
+            destination: addr(host, field("destinationPort")),
+            rule,
+            chains,
+            upload: bytes("upload"),
+            download: bytes("download"),
+            start: text("start"),
+        })
+    }
 }
 
-/// 根据clash info获取clash服务地址和请求头
-fn clash_client_info() -> Result<(String, HeaderMap)> {
-    let client = { Config::clash().data().get_client_info() };
+/// the client of the clash controller
+/// 每次从配置中读取当前的地址和secret，不要长期持有
+pub struct ClashApi {
+    server: String,
+    headers: HeaderMap,
+    client: reqwest::Client,
+}
+
+impl ClashApi {
+    /// `server` is the `external-controller`, the scheme is optional
+    pub fn new(server: &str, secret: Option<&str>) -> Result<Self> {
+        let server = server.trim().trim_end_matches('/');
+        if server.is_empty() {
+            bail!("the clash controller is not set");
+        }
+        let server = match server.contains("://") {
+            true => server.to_string(),
+            false => format!("http://{server}"),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if let Some(secret) = secret.map(str::trim).filter(|s| !s.is_empty()) {
+            let value = HeaderValue::from_str(&format!("Bearer {secret}"))
+                .map_err(|_| anyhow!("the clash secret contains invalid characters"))?;
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let client = reqwest::ClientBuilder::new().no_proxy().build()?;
+        Ok(Self {
+            server,
+            headers,
+            client,
+        })
+    }
+
+    /// 根据clash info获取clash服务地址和secret
+    pub fn from_config() -> Result<Self> {
+        let info = { Config::clash().data().get_client_info() };
+        Self::new(&info.server, info.secret.as_deref())
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}{path}", self.server);
+        self.client
+            .request(method, url)
+            .headers(self.headers.clone())
+    }
+
+    /// send the request and map the non-2xx status to `ClashApiError`
+    async fn send(&self, builder: RequestBuilder) -> Result<Response> {
+        let response = builder.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let error = match status.as_u16() {
+            401 => ClashApiError::Unauthorized,
+            404 => ClashApiError::NotFound,
+            code => {
+                let text = response.text().await.unwrap_or_default();
+                // 内核返回 {"message":"..."}
+                let msg = serde_json::from_str::<serde_json::Value>(&text)
+                    .ok()
+                    .and_then(|v| v.get("message")?.as_str().map(String::from))
+                    .unwrap_or(text);
+                ClashApiError::Status(code, msg.trim().to_string())
+            }
+        };
+        Err(error.into())
+    }
+
+    /// PUT /configs
+    /// path 是绝对路径
+    pub async fn put_configs(&self, path: &str) -> Result<()> {
+        let mut data = HashMap::new();
+        data.insert("path", path);
+
+        let builder = self.request(Method::PUT, "/configs").json(&data);
+        self.send(builder).await?;
+        Ok(())
+    }
+
+    /// PATCH /configs
+    pub async fn patch_configs(&self, config: &Mapping) -> Result<()> {
+        let builder = self.request(Method::PATCH, "/configs").json(config);
+        self.send(builder).await?;
+        Ok(())
+    }
+
+    /// GET /configs
+    /// 内核当前的配置
+    pub async fn get_configs(&self) -> Result<Mapping> {
+        let response = self.send(self.request(Method::GET, "/configs")).await?;
+        Ok(response.json::<Mapping>().await?)
+    }
+
+    /// GET /proxies/{name}/delay
+    /// 获取代理延迟
+    pub async fn get_proxy_delay(
+        &self,
+        name: &str,
+        test_url: Option<String>,
+        timeout: i32,
+    ) -> Result<DelayRes> {
+        let default_url = "http://1.1.1.1";
+        let test_url = test_url
+            .map(|s| if s.is_empty() { default_url.into() } else { s })
+            .unwrap_or(default_url.into());
+
+        let builder = self
+            .request(Method::GET, &format!("/proxies/{name}/delay"))
+            .query(&[("timeout", &format!("{timeout}")), ("url", &test_url)]);
+        let response = self.send(builder).await?;
+        Ok(response.json::<DelayRes>().await?)
+    }
+
+    /// 第 i 跳测试 `relays[i]`，即前 i 跳组成的 relay 组
+    /// 某一跳不通时后面的链路也不通，不再测试
+    pub async fn test_chain(
+        &self,
+        names: &[String],
+        relays: &[String],
+        test_url: Option<String>,
+        timeout: i32,
+    ) -> Result<ChainResult> {
+        if names.is_empty() {
+            bail!("the proxy chain is empty");
+        }
+        if names.len() != relays.len() {
+            bail!("the relays do not match the proxy chain");
+        }
 
-    let server = format!("http://{}", client.server);
+        let mut hops = vec![];
+        let mut failed = false;
+        for (i, (name, relay)) in names.iter().zip(relays).enumerate() {
+            let (delay, error) = match failed {
+                true => (None, None),
+                false => {
+                    // 超时按经过的跳数算
+                    let timeout = timeout.saturating_mul(i as i32 + 1);
+                    match self.delay_with_timeout(relay, &test_url, timeout).await {
+                        Ok(delay) => (Some(delay), None),
+                        Err(err) => (None, Some(err)),
+                    }
+                }
+            };
+            failed = delay.is_none();
+            hops.push(ChainHop {
+                name: name.clone(),
+                delay,
+                error,
+            });
+        }
+        Ok(ChainResult::from_hops(hops))
+    }
 
-    let mut headers = HeaderMap::new();
-    headers.insert("Content-Type", "application/json".parse()?);
+    /// the core may hang on a dead proxy, so limit the request too
+    pub async fn delay_with_timeout(
+        &self,
+        name: &str,
+        test_url: &Option<String>,
+        timeout: i32,
+    ) -> std::result::Result<u64, String> {
+        let limit = Duration::from_millis(timeout.max(0) as u64 + 1000);
+        // 代理名是路径的一部分，需要编码
+        let encoded = utf8_percent_encode(name, NON_ALPHANUMERIC).to_string();
+        let request = self.get_proxy_delay(&encoded, test_url.clone(), timeout);
+        match tokio::time::timeout(limit, request).await {
+            Ok(Ok(res)) => Ok(res.delay),
+            Ok(Err(err)) => match err.downcast_ref::<ClashApiError>() {
+                Some(ClashApiError::Status(408 | 504, _)) => Err("timeout".into()),
+                Some(ClashApiError::NotFound) => Err(format!("the proxy \"{name}\" is not found")),
+                _ => Err(format!("{err}")),
+            },
+            Err(_) => Err("timeout".into()),
+        }
+    }
 
-    if let Some(secret) = client.secret {
-        let secret = format!("Bearer {}", secret).parse()?;
-        headers.insert("Authorization", secret);
+    /// GET /version
+    /// 获取内核版本
+    pub async fn get_version(&self) -> Result<CoreVersion> {
+        let response = self.send(self.request(Method::GET, "/version")).await?;
+        let value = response.json::<serde_json::Value>().await?;
+        CoreVersion::parse(&value)
     }
 
-    Ok((server, headers))
+    /// GET /providers/{kind}
+    /// 获取外部资源列表，内核不支持时返回空
+    pub async fn get_providers(&self, kind: ProviderKind) -> Result<Vec<ProviderInfo>> {
+        let path = format!("/providers/{}", kind.path());
+        let response = match self.send(self.request(Method::GET, &path)).await {
+            Ok(response) => response,
+            Err(err) if ClashApiError::is_not_found(&err) => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+
+        let value = response.json::<serde_json::Value>().await?;
+        let mut list = value
+            .get("providers")
+            .and_then(|v| v.as_object())
+            .map(|map| {
+                map.values()
+                    .filter_map(|v| ProviderInfo::parse(kind, v))
+                    .collect::<Vec<ProviderInfo>>()
+            })
+            .unwrap_or_default();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(list)
+    }
+
+    /// PUT /providers/{kind}/{name}
+    /// 强制更新外部资源，返回更新后的信息
+    pub async fn update_provider(&self, kind: ProviderKind, name: &str) -> Result<ProviderInfo> {
+        let mut url = reqwest::Url::parse(&self.server)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("invalid clash server url"))?
+            .extend(["providers", kind.path(), name]);
+        let path = url.path().to_string();
+
+        match self.send(self.request(Method::PUT, &path)).await {
+            Ok(_) => {}
+            Err(err) if ClashApiError::is_not_found(&err) => {
+                bail!("the {} provider \"{name}\" is not found", kind.path())
+            }
+            Err(err) => return Err(err),
+        }
+
+        let response = self.send(self.request(Method::GET, &path)).await?;
+        let value = response.json::<serde_json::Value>().await?;
+        ProviderInfo::parse(kind, &value).ok_or(anyhow!("failed to parse the provider \"{name}\""))
+    }
+
+    /// POST /cache/fakeip/flush
+    /// 清空fake-ip缓存，内核不支持时返回false
+    pub async fn flush_fakeip(&self) -> Result<bool> {
+        let builder = self.request(Method::POST, "/cache/fakeip/flush");
+        match self.send(builder).await {
+            Ok(_) => Ok(true),
+            Err(err) if ClashApiError::is_not_found(&err) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// GET /connections
+    /// 内核当前的连接
+    pub async fn get_connections(&self) -> Result<Vec<ConnectionInfo>> {
+        Ok(self.get_connections_snapshot().await?.connections)
+    }
+
+    /// GET /connections
+    /// 带上内核启动以来的总流量
+    pub async fn get_connections_snapshot(&self) -> Result<ConnectionsSnapshot> {
+        let response = self.send(self.request(Method::GET, "/connections")).await?;
+        let value = response
+            .json::<serde_json::Value>()
+            .await
+            .context("the connection to the clash core is dropped")?;
+
+        Ok(ConnectionsSnapshot::parse(&value))
+    }
+
+    /// DELETE /connections/{id}
+    pub async fn close_connection(&self, id: &str) -> Result<()> {
+        let mut url = reqwest::Url::parse(&self.server)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("invalid clash server url"))?
+            .extend(["connections", id]);
+
+        match self.send(self.request(Method::DELETE, url.path())).await {
+            Ok(_) => Ok(()),
+            Err(err) if ClashApiError::is_not_found(&err) => {
+                bail!("the connection \"{id}\" is not found")
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// 订阅内核的内存占用，每行一个json
+    /// return false if the core does not support the `/memory` endpoint
+    pub async fn stream_memory<F: FnMut(CoreMemory)>(&self, mut on_memory: F) -> Result<bool> {
+        let mut response = match self.send(self.request(Method::GET, "/memory")).await {
+            Ok(response) => response,
+            Err(err) if ClashApiError::is_not_found(&err) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+
+        let mut buf = vec![];
+        while let Some(chunk) = response.chunk().await? {
+            buf.extend_from_slice(&chunk);
+            while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                let line = buf.drain(..=pos).collect::<Vec<u8>>();
+                if let Ok(memory) = serde_json::from_slice::<CoreMemory>(&line) {
+                    on_memory(memory);
+                }
+            }
+        }
+        Ok(true)
+    }
 }
 
 /// 缩短clash的日志
@@ -143,3 +658,195 @@ fn test_parse_check_output() {
 
     assert_eq!(res1, res3);
 }
+
+#[test]
+fn test_parse_version() {
+    let parse = |s: &str| CoreVersion::parse(&serde_json::from_str(s).unwrap()).unwrap();
+
+    let meta = parse(r#"{"meta":true,"version":"v1.18.1"}"#);
+    assert_eq!(meta.version, "v1.18.1");
+    assert!(meta.meta && !meta.premium);
+
+    let premium = parse(r#"{"premium":true,"version":"2023.08.17"}"#);
+    assert!(!premium.meta && premium.premium);
+
+    let clash = parse(r#"{"version":"v1.18.0"}"#);
+    assert!(!clash.meta && !clash.premium);
+    assert!(clash.features.is_empty());
+
+    let alpha = parse(r#"{"version":"alpha-g1a2b3c","features":["tun","sniffer"]}"#);
+    assert_eq!(alpha.features, vec!["tun", "sniffer"]);
+
+    assert!(CoreVersion::parse(&serde_json::from_str("{}").unwrap()).is_err());
+}
+
+#[test]
+fn test_parse_provider() {
+    let rule = serde_json::json!({
+        "behavior": "Domain",
+        "format": "YamlRule",
+        "name": "reject",
+        "ruleCount": 120,
+        "type": "Rule",
+        "updatedAt": "2024-03-01T10:00:00.000+08:00",
+        "vehicleType": "HTTP"
+    });
+    let info = ProviderInfo::parse(ProviderKind::Rules, &rule).unwrap();
+    assert_eq!(info.name, "reject");
+    assert_eq!(info.behavior.as_deref(), Some("domain"));
+    assert_eq!(info.count, 120);
+    assert_eq!(
+        info.updated_at.as_deref(),
+        Some("2024-03-01T10:00:00.000+08:00")
+    );
+
+    let proxy = serde_json::json!({
+        "name": "nodes",
+        "proxies": [{"name": "a"}, {"name": "b"}],
+        "type": "Proxy",
+        "vehicleType": "HTTP"
+    });
+    let info = ProviderInfo::parse(ProviderKind::Proxies, &proxy).unwrap();
+    assert_eq!(info.count, 2);
+    assert_eq!(info.behavior, None);
+    assert_eq!(info.updated_at, None);
+
+    let compatible = serde_json::json!({
+        "name": "default",
+        "proxies": [],
+        "type": "Proxy",
+        "vehicleType": "Compatible"
+    });
+    assert!(ProviderInfo::parse(ProviderKind::Proxies, &compatible).is_none());
+}
+
+#[tokio::test]
+async fn test_clash_api() {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // a mock controller with the secret `abc`
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    std::thread::spawn(move || {
+        for mut stream in server.incoming().flatten() {
+            let mut buf = [0; 1024];
+            let size = stream.read(&mut buf).unwrap_or(0);
+            let req = String::from_utf8_lossy(&buf[..size]).to_lowercase();
+            let (status, body) = if !req.contains("authorization: bearer abc\r\n") {
+                ("401 Unauthorized", r#"{"message":"Unauthorized"}"#)
+            } else if req.starts_with("get /version ") {
+                ("200 OK", r#"{"meta":true,"version":"v1.18.1"}"#)
+            } else if req.starts_with("patch /configs ") {
+                ("400 Bad Request", r#"{"message":"Body invalid"}"#)
+            } else if req.starts_with("get /connections ") {
+                // 内核中途断开，body不完整
+                let resp = "HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n{\"connections\":[";
+                let _ = stream.write_all(resp.as_bytes());
+                continue;
+            } else {
+                ("404 Not Found", r#"{"message":"Resource not found"}"#)
+            };
+            let resp = format!(
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = stream.write_all(resp.as_bytes());
+        }
+    });
+    let server = addr.to_string();
+
+    let api = ClashApi::new(&server, Some(" abc ")).unwrap();
+    assert_eq!(api.get_version().await.unwrap().version, "v1.18.1");
+    let providers = api.get_providers(ProviderKind::Rules).await.unwrap();
+    assert!(providers.is_empty());
+    assert!(!api.flush_fakeip().await.unwrap());
+    assert!(api.close_connection("abc").await.is_err());
+    let err = api.get_connections().await.unwrap_err();
+    assert!(err.to_string().contains("dropped"));
+
+    let err = api.patch_configs(&Mapping::new()).await.unwrap_err();
+    let expect = ClashApiError::Status(400, "Body invalid".into());
+    assert_eq!(err.downcast_ref::<ClashApiError>(), Some(&expect));
+
+    for secret in [None, Some(""), Some("xyz")] {
+        let api = ClashApi::new(&format!("http://{server}/"), secret).unwrap();
+        let err = api.get_version().await.unwrap_err();
+        let err = err.downcast_ref::<ClashApiError>();
+        assert_eq!(err, Some(&ClashApiError::Unauthorized));
+    }
+
+    assert!(ClashApi::new(" ", None).is_err());
+    assert!(ClashApi::new(&server, Some("a\nb")).is_err());
+}
+
+#[test]
+fn test_parse_connection() {
+    let conn = serde_json::json!({
+        "id": "5f0a",
+        "metadata": {
+            "network": "tcp",
+            "sourceIP": "127.0.0.1",
+            "sourcePort": "52000",
+            "destinationIP": "1.2.3.4",
+            "destinationPort": 443,
+            "host": "example.com"
+        },
+        "upload": 120,
+        "download": 4096,
+        "start": "2024-03-01T10:00:00.000+08:00",
+        "chains": ["node", "Proxy"],
+        "rule": "DomainSuffix",
+        "rulePayload": "example.com"
+    });
+    let info = ConnectionInfo::parse(&conn).unwrap();
+    assert_eq!(info.source, "127.0.0.1:52000");
+    assert_eq!(info.destination, "example.com:443");
+    assert_eq!(info.rule, "DomainSuffix(example.com)");
+    assert_eq!(info.chains, vec!["node", "Proxy"]);
+    assert_eq!((info.upload, info.download), (120, 4096));
+
+    let conn = serde_json::json!({
+        "id": "5f0b",
+        "metadata": {"network": "udp", "destinationIP": "::1", "destinationPort": "53"},
+        "rule": "Match",
+        "rulePayload": ""
+    });
+    let info = ConnectionInfo::parse(&conn).unwrap();
+    assert_eq!(info.destination, "[::1]:53");
+    assert_eq!(info.rule, "Match");
+    assert!(ConnectionInfo::parse(&serde_json::json!({})).is_none());
+
+    let snapshot = ConnectionsSnapshot::parse(&serde_json::json!({
+        "uploadTotal": 10,
+        "downloadTotal": 20,
+        "connections": [conn, {}]
+    }));
+    assert_eq!((snapshot.upload_total, snapshot.download_total), (10, 20));
+    assert_eq!(snapshot.connections, vec![info]);
+    assert_eq!(
+        ConnectionsSnapshot::parse(&serde_json::json!({"connections": null})),
+        ConnectionsSnapshot::default()
+    );
+}
+
+#[test]
+fn test_chain_result() {
+    let hop = |name: &str, delay: Option<u64>, tested: bool| ChainHop {
+        name: name.into(),
+        delay,
+        error: (tested && delay.is_none()).then(|| "timeout".into()),
+    };
+
+    let result = ChainResult::from_hops(vec![hop("a", Some(50), true), hop("b", Some(130), true)]);
+    assert_eq!((result.delay, result.failed_hop), (Some(130), None));
+
+    let hops = vec![
+        hop("a", Some(50), true),
+        hop("b", None, true),
+        hop("c", None, false),
+    ];
+    let result = ChainResult::from_hops(hops);
+    assert_eq!((result.delay, result.failed_hop), (None, Some(1)));
+    assert_eq!(result.hops[2].error, None);
+}
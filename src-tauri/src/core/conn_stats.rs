@@ -0,0 +1,365 @@
+use super::clash_api::{ClashApi, ConnectionsSnapshot};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+
+/// 轮询 `/connections` 的间隔
+/// 两次轮询之间关闭的连接，最后这段流量只计入总量，统计不到各个表里
+const POLL_SECS: u64 = 2;
+
+/// the max entries of each table, the rest is counted in `OTHERS`
+const MAX_ENTRIES: usize = 5000;
+const OTHERS: &str = "(others)";
+
+pub const DEFAULT_TOP_LIMIT: usize = 20;
+pub const MAX_TOP_LIMIT: usize = 200;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Traffic {
+    upload: u64,
+    download: u64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ConnStatsItem {
+    pub name: String,
+    pub upload: u64,
+    pub download: u64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ConnStatsReport {
+    /// the timestamp when the core started, or the stats were reset
+    pub since: i64,
+    pub upload: u64,
+    pub download: u64,
+    /// sorted by the total bytes
+    pub hosts: Vec<ConnStatsItem>,
+    /// the node actually used, the first of `chains`
+    pub proxies: Vec<ConnStatsItem>,
+    /// the policy matched by the rule, the last of `chains`
+    pub groups: Vec<ConnStatsItem>,
+    pub rules: Vec<ConnStatsItem>,
+}
+
+/// the bytes accumulated since `since`
+#[derive(Debug)]
+struct ConnStatsData {
+    /// bumped by `reset`, the polls started before it are dropped
+    epoch: u64,
+    since: i64,
+    /// the sum of the deltas, used when the core doesn't report the totals
+    total: Traffic,
+    /// the `uploadTotal` / `downloadTotal` of the core at the first poll
+    baseline: Option<Traffic>,
+    /// the core totals at the last poll
+    core: Traffic,
+    /// the bytes of the previous core runs, if it restarted without `reset`
+    carried: Traffic,
+    hosts: HashMap<String, Traffic>,
+    proxies: HashMap<String, Traffic>,
+    groups: HashMap<String, Traffic>,
+    rules: HashMap<String, Traffic>,
+    /// the bytes of the alive connections at the last poll
+    alive: HashMap<String, Traffic>,
+}
+
+impl ConnStatsData {
+    fn new(epoch: u64) -> Self {
+        Self {
+            epoch,
+            since: chrono::Local::now().timestamp(),
+            total: Traffic::default(),
+            baseline: None,
+            core: Traffic::default(),
+            carried: Traffic::default(),
+            hosts: HashMap::new(),
+            proxies: HashMap::new(),
+            groups: HashMap::new(),
+            rules: HashMap::new(),
+            alive: HashMap::new(),
+        }
+    }
+
+    /// add the delta of each connection since the last poll
+    fn update(&mut self, snapshot: &ConnectionsSnapshot) {
+        let conns = &snapshot.connections;
+        let mut alive = HashMap::with_capacity(conns.len());
+        let core = Traffic {
+            upload: snapshot.upload_total,
+            download: snapshot.download_total,
+        };
+
+        match self.baseline {
+            // 第一次轮询之前的已关闭连接不算，还活着的连接由下面的增量计入
+            None => {
+                let mut alive_sum = Traffic::default();
+                conns.iter().for_each(|conn| {
+                    let traffic = Traffic {
+                        upload: conn.upload,
+                        download: conn.download,
+                    };
+                    add(&mut alive_sum, traffic);
+                });
+                self.baseline = Some(Traffic {
+                    upload: core.upload.saturating_sub(alive_sum.upload),
+                    download: core.download.saturating_sub(alive_sum.download),
+                });
+            }
+            // 总量变小说明内核重启过
+            Some(_) if core.upload < self.core.upload || core.download < self.core.download => {
+                self.carried = self.core_total();
+                self.baseline = Some(Traffic::default());
+            }
+            Some(_) => {}
+        }
+        self.core = core;
+
+        for conn in conns {
+            let current = Traffic {
+                upload: conn.upload,
+                download: conn.download,
+            };
+            let last = self.alive.get(&conn.id).copied().unwrap_or_default();
+            // 计数变小说明是id相同的新连接
+            let delta = Traffic {
+                upload: match current.upload >= last.upload {
+                    true => current.upload - last.upload,
+                    false => current.upload,
+                },
+                download: match current.download >= last.download {
+                    true => current.download - last.download,
+                    false => current.download,
+                },
+            };
+            alive.insert(conn.id.clone(), current);
+
+            if delta == Traffic::default() {
+                continue;
+            }
+            add(&mut self.total, delta);
+
+            // mihomo 的 chains 是从节点到策略组
+            let proxy = conn.chains.first().cloned().unwrap_or_default();
+            let group = conn.chains.last().cloned().unwrap_or_default();
+            accumulate(&mut self.hosts, strip_port(&conn.destination), delta);
+            accumulate(&mut self.proxies, &proxy, delta);
+            accumulate(&mut self.groups, &group, delta);
+            accumulate(&mut self.rules, &conn.rule, delta);
+        }
+
+        self.alive = alive;
+    }
+
+    /// the bytes counted by the core since the first poll
+    fn core_total(&self) -> Traffic {
+        let baseline = self.baseline.unwrap_or_default();
+        let mut total = self.carried;
+        add(
+            &mut total,
+            Traffic {
+                upload: self.core.upload.saturating_sub(baseline.upload),
+                download: self.core.download.saturating_sub(baseline.download),
+            },
+        );
+        total
+    }
+
+    fn report(&self, limit: usize) -> ConnStatsReport {
+        let core = self.core_total();
+        ConnStatsReport {
+            since: self.since,
+            upload: self.total.upload.max(core.upload),
+            download: self.total.download.max(core.download),
+            hosts: top(&self.hosts, limit),
+            proxies: top(&self.proxies, limit),
+            groups: top(&self.groups, limit),
+            rules: top(&self.rules, limit),
+        }
+    }
+}
+
+fn add(traffic: &mut Traffic, delta: Traffic) {
+    traffic.upload = traffic.upload.saturating_add(delta.upload);
+    traffic.download = traffic.download.saturating_add(delta.download);
+}
+
+fn accumulate(table: &mut HashMap<String, Traffic>, name: &str, delta: Traffic) {
+    let name = match name.is_empty() {
+        true => "-",
+        false => name,
+    };
+    let name = match table.contains_key(name) || table.len() < MAX_ENTRIES {
+        true => name,
+        false => OTHERS,
+    };
+    add(table.entry(name.to_string()).or_default(), delta);
+}
+
+fn top(table: &HashMap<String, Traffic>, limit: usize) -> Vec<ConnStatsItem> {
+    let mut items = table
+        .iter()
+        .map(|(name, traffic)| ConnStatsItem {
+            name: name.clone(),
+            upload: traffic.upload,
+            download: traffic.download,
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| {
+        let total = |i: &ConnStatsItem| i.upload.saturating_add(i.download);
+        total(b).cmp(&total(a)).then_with(|| a.name.cmp(&b.name))
+    });
+    items.truncate(limit);
+    items
+}
+
+/// `example.com:443` -> `example.com`, `[::1]:53` -> `::1`
+fn strip_port(destination: &str) -> &str {
+    if let Some(rest) = destination.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match destination.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') && port.parse::<u16>().is_ok() => host,
+        _ => destination,
+    }
+}
+
+pub struct ConnStats {
+    data: Arc<Mutex<ConnStatsData>>,
+}
+
+impl ConnStats {
+    pub fn global() -> &'static ConnStats {
+        static CONN_STATS: OnceCell<ConnStats> = OnceCell::new();
+
+        CONN_STATS.get_or_init(|| ConnStats {
+            data: Arc::new(Mutex::new(ConnStatsData::new(0))),
+        })
+    }
+
+    /// 在后台轮询内核的连接，内核不可用时等下一轮
+    pub fn init(&'static self) {
+        tauri::async_runtime::spawn(async move {
+            loop {
+                sleep(Duration::from_secs(POLL_SECS)).await;
+
+                let epoch = self.data.lock().epoch;
+                let result =
+                    async { ClashApi::from_config()?.get_connections_snapshot().await }.await;
+                match result {
+                    Ok(snapshot) => {
+                        let mut data = self.data.lock();
+                        // 请求期间内核重启了，这是旧内核的数据
+                        if data.epoch == epoch {
+                            data.update(&snapshot);
+                        }
+                    }
+                    Err(err) => {
+                        log::debug!(target: "app", "failed to get the connections: {err}");
+                    }
+                }
+            }
+        });
+    }
+
+    /// called when the core (re)starts, after the old one is stopped
+    pub fn reset(&self) {
+        let mut data = self.data.lock();
+        *data = ConnStatsData::new(data.epoch + 1);
+    }
+
+    pub fn report(&self, limit: Option<usize>) -> ConnStatsReport {
+        let limit = limit.unwrap_or(DEFAULT_TOP_LIMIT).clamp(1, MAX_TOP_LIMIT);
+        self.data.lock().report(limit)
+    }
+}
+
+#[test]
+fn test_conn_stats() {
+    use super::clash_api::ConnectionInfo;
+
+    let conn = |id: &str, dest: &str, chains: &[&str], upload: u64, download: u64| ConnectionInfo {
+        id: id.into(),
+        network: "tcp".into(),
+        source: "127.0.0.1:50000".into(),
+        destination: dest.into(),
+        rule: "Match".into(),
+        chains: chains.iter().map(|c| c.to_string()).collect(),
+        upload,
+        download,
+        start: None,
+    };
+    let snapshot =
+        |connections: Vec<ConnectionInfo>, upload_total, download_total| ConnectionsSnapshot {
+            upload_total,
+            download_total,
+            connections,
+        };
+    let mut data = ConnStatsData::new(0);
+
+    // 内核没有给总量
+    data.update(&snapshot(
+        vec![
+            conn("a", "example.com:443", &["node1", "Proxy"], 10, 100),
+            conn("b", "[::1]:53", &["DIRECT"], 1, 2),
+        ],
+        0,
+        0,
+    ));
+    // a 增长，b 关闭，c 新建
+    data.update(&snapshot(
+        vec![
+            conn("a", "example.com:443", &["node1", "Proxy"], 15, 300),
+            conn("c", "example.com:80", &["node2", "Proxy"], 5, 50),
+        ],
+        0,
+        0,
+    ));
+    // a 的id被复用
+    data.update(&snapshot(
+        vec![conn("a", "other.com:443", &["DIRECT"], 1, 1)],
+        0,
+        0,
+    ));
+
+    let report = data.report(10);
+    assert_eq!((report.upload, report.download), (22, 353));
+    assert_eq!(
+        report.hosts[0],
+        ConnStatsItem {
+            name: "example.com".into(),
+            upload: 20,
+            download: 350,
+        }
+    );
+    assert_eq!(report.hosts.len(), 3);
+    assert!(report.hosts.iter().any(|h| h.name == "::1"));
+    assert_eq!(report.groups[0].name, "Proxy");
+    assert_eq!(report.proxies[0].name, "node1");
+    assert_eq!(report.rules[0].download, 353);
+    assert_eq!(data.report(1).hosts.len(), 1);
+
+    // 第一次轮询前关闭的连接不算，两次轮询之间关闭的连接算进总量
+    let mut data = ConnStatsData::new(1);
+    data.update(&snapshot(
+        vec![conn("a", "example.com:443", &["DIRECT"], 10, 100)],
+        1000,
+        5000,
+    ));
+    assert_eq!(data.report(10).download, 100);
+    data.update(&snapshot(vec![], 1030, 5400));
+    let report = data.report(10);
+    assert_eq!((report.upload, report.download), (40, 500));
+    assert_eq!(report.hosts[0].download, 100);
+    // 内核在外部重启过
+    data.update(&snapshot(vec![], 5, 10));
+    let report = data.report(10);
+    assert_eq!((report.upload, report.download), (45, 510));
+
+    assert_eq!(strip_port("1.2.3.4:80"), "1.2.3.4");
+    assert_eq!(strip_port("2001:db8::1"), "2001:db8::1");
+    assert_eq!(strip_port("example.com"), "example.com");
+}
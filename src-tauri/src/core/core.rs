@@ -1,22 +1,88 @@
 use super::service;
-use super::{clash_api, logger::Logger};
+use super::{clash_api, conn_stats::ConnStats, handle, logger::Logger, sysopt::Sysopt};
 use crate::log_err;
-use crate::{config::*, utils::dirs};
+use crate::{
+    config::*,
+    utils::{dirs, help},
+};
 use anyhow::{bail, Context, Result};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
+use serde::Serialize;
 use serde_yaml::Mapping;
-use std::{fs, io::Write, sync::Arc, time::Duration};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::Write,
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use sysinfo::{Pid, System};
 use tauri::api::process::{Command, CommandChild, CommandEvent};
 use tokio::time::sleep;
 
+/// the result of flushing the fake-ip cache
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum FlushResult {
+    Flushed,
+    /// fake-ip is not enabled or the core does not support it
+    NotApplicable {
+        reason: String,
+    },
+}
+
+/// 时间窗口内崩溃超过这个次数就不再自动重启
+const CRASH_LOOP_LIMIT: usize = 5;
+const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(60);
+
+/// the unexpected exits of the core since the app started
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct CoreCrashState {
+    pub count: usize,
+    pub last_code: Option<i32>,
+    pub last_signal: Option<i32>,
+    /// the timestamp of the last crash
+    pub last_time: Option<i64>,
+    /// the auto restart is stopped by the crash loop limit
+    pub restart_blocked: bool,
+    #[serde(skip)]
+    recent: VecDeque<Instant>,
+}
+
+impl CoreCrashState {
+    /// record a crash, return whether it is still allowed to restart
+    fn record(&mut self, code: Option<i32>, signal: Option<i32>, now: Instant) -> bool {
+        self.count += 1;
+        self.last_code = code;
+        self.last_signal = signal;
+        self.last_time = Some(chrono::Local::now().timestamp());
+
+        self.recent.push_back(now);
+        while let Some(first) = self.recent.front() {
+            match now.duration_since(*first) > CRASH_LOOP_WINDOW {
+                true => self.recent.pop_front(),
+                false => break,
+            };
+        }
+        self.restart_blocked = self.recent.len() > CRASH_LOOP_LIMIT;
+        !self.restart_blocked
+    }
+}
+
 #[derive(Debug)]
 pub struct CoreManager {
     sidecar: Arc<Mutex<Option<CommandChild>>>,
 
     #[allow(unused)]
     use_service_mode: Arc<Mutex<bool>>,
+
+    /// cache the version of the running core
+    version: Arc<Mutex<Option<clash_api::CoreVersion>>>,
+
+    crash: Arc<Mutex<CoreCrashState>>,
 }
 
 impl CoreManager {
@@ -26,19 +92,26 @@ impl CoreManager {
         CORE_MANAGER.get_or_init(|| CoreManager {
             sidecar: Arc::new(Mutex::new(None)),
             use_service_mode: Arc::new(Mutex::new(false)),
+            version: Arc::new(Mutex::new(None)),
+            crash: Arc::new(Mutex::new(CoreCrashState::default())),
         })
     }
 
     pub fn init(&self) -> Result<()> {
         // kill old clash process
+        // pid可能已被复用，只结束当前内核文件的进程
+        let core_path = Self::core_path();
         let _ = dirs::clash_pid_path()
             .and_then(|path| fs::read(path).map(|p| p.to_vec()).context(""))
             .and_then(|pid| String::from_utf8_lossy(&pid).parse().context(""))
             .map(|pid| {
+                let Ok(core_path) = core_path.as_ref() else {
+                    return;
+                };
                 let mut system = System::new();
                 system.refresh_all();
                 if let Some(proc) = system.process(Pid::from_u32(pid)) {
-                    if proc.name().contains("clash") {
+                    if is_core_process(proc.name(), proc.exe(), core_path) {
                         log::debug!(target: "app", "kill old clash process");
                         proc.kill();
                     }
@@ -46,6 +119,13 @@ impl CoreManager {
             });
 
         tauri::async_runtime::spawn(async {
+            // 旧的内核结束后再检查控制端口，等待端口释放不阻塞启动
+            match Self::resolve_controller().await {
+                Ok(true) => log_err!(Config::generate()),
+                Ok(false) => {}
+                Err(err) => log::error!(target: "app", "{err}"),
+            }
+
             // 启动clash
             log_err!(Self::global().run_core().await);
         });
@@ -53,18 +133,90 @@ impl CoreManager {
         Ok(())
     }
 
+    /// 外部控制端口被占用时换一个空闲端口，没有secret时生成一个
+    /// return true if the clash config is changed
+    async fn resolve_controller() -> Result<bool> {
+        let (server, secret) = {
+            let clash = Config::clash();
+            let clash = clash.data();
+            let info = clash.get_client_info();
+            (IClashTemp::guard_server_ctrl(&clash.0), info.secret)
+        };
+
+        let mut patch = Mapping::new();
+        let addr: SocketAddr = server.parse()?;
+        // 被杀掉的旧内核可能还没释放端口，等一会再试
+        let mut available = TcpListener::bind(addr).is_ok();
+        if !available {
+            sleep(Duration::from_millis(500)).await;
+            available = TcpListener::bind(addr).is_ok();
+        }
+        if !available {
+            let port = TcpListener::bind((addr.ip(), 0))?.local_addr()?.port();
+            let server = SocketAddr::new(addr.ip(), port).to_string();
+            log::warn!(target: "app", "the controller {addr} is in use, change to {server}");
+            patch.insert("external-controller".into(), server.into());
+        }
+
+        if secret.map_or(true, |s| s.trim().is_empty()) {
+            if !addr.ip().is_loopback() {
+                log::warn!(target: "app", "the controller {addr} is exposed without a secret");
+            }
+            log::info!(target: "app", "generate the controller secret");
+            patch.insert("secret".into(), help::gen_secret().into());
+        }
+
+        if patch.is_empty() {
+            return Ok(false);
+        }
+        Config::clash().data().patch_config(patch);
+        Config::clash().data().save_config()?;
+        Ok(true)
+    }
+
+    /// 内核的路径，设置了自定义内核时使用自定义的路径
+    /// 自定义的路径无效时直接报错，不回退到内置的内核
+    pub fn core_path() -> Result<PathBuf> {
+        let clash_core = { Config::verge().latest().clash_core.clone() };
+        Self::core_path_of(&clash_core.unwrap_or("clash".into()))
+    }
+
+    /// 指定内核的路径，设置了自定义内核时都是自定义的路径
+    pub fn core_path_of(clash_core: &str) -> Result<PathBuf> {
+        let custom = { Config::verge().latest().core_binary_path.clone() };
+
+        if let Some(path) = custom.filter(|p| !p.trim().is_empty()) {
+            let path = PathBuf::from(path.trim());
+            check_executable(&path)?;
+            return Ok(path);
+        }
+
+        let bin_ext = if cfg!(windows) { ".exe" } else { "" };
+        Ok(std::env::current_exe()?.with_file_name(format!("{clash_core}{bin_ext}")))
+    }
+
+    /// 内置的内核使用sidecar
+    fn core_command() -> Result<Command> {
+        let custom = { Config::verge().latest().core_binary_path.clone() };
+        if custom.is_some_and(|p| !p.trim().is_empty()) {
+            let path = Self::core_path()?;
+            return Ok(Command::new(dirs::path_to_str(&path)?));
+        }
+
+        let clash_core = { Config::verge().latest().clash_core.clone() };
+        let clash_core = clash_core.unwrap_or("clash".into());
+        Ok(Command::new_sidecar(clash_core)?)
+    }
+
     /// 检查订阅是否正确
     pub fn check_config(&self) -> Result<()> {
         let config_path = Config::generate_file(ConfigType::Check)?;
         let config_path = dirs::path_to_str(&config_path)?;
 
-        let clash_core = { Config::verge().latest().clash_core.clone() };
-        let clash_core = clash_core.unwrap_or("clash".into());
-
         let app_dir = dirs::app_home_dir()?;
         let app_dir = dirs::path_to_str(&app_dir)?;
 
-        let output = Command::new_sidecar(clash_core)?
+        let output = Self::core_command()?
             .args(["-t", "-d", app_dir, "-f", config_path])
             .output()?;
 
@@ -81,10 +233,71 @@ impl CoreManager {
         Ok(())
     }
 
+    /// 生成并检查订阅
+    /// script的输出导致订阅不可用时，跳过script重新生成，避免内核无法使用
+    fn generate_checked(&self) -> Result<()> {
+        Config::generate()?;
+        Self::notice_script_errors();
+
+        let err = match self.check_config() {
+            Ok(_) => return Ok(()),
+            Err(err) => err,
+        };
+        let has_script = { !Config::runtime().latest().chain_logs.is_empty() };
+        if !has_script {
+            return Err(err);
+        }
+
+        log::error!(target: "app", "the config transformed by the scripts is invalid, {err}");
+        Config::generate_with(false)?;
+        if self.check_config().is_err() {
+            return Err(err);
+        }
+        handle::Handle::notice_message(
+            "set_config::error",
+            format!("the scripts are skipped, {err}"),
+        );
+        Ok(())
+    }
+
+    /// 通知script的执行错误，出错的script不会修改订阅
+    fn notice_script_errors() {
+        let errors = {
+            let runtime = Config::runtime();
+            let runtime = runtime.latest();
+            runtime
+                .chain_logs
+                .iter()
+                .flat_map(|(uid, logs)| {
+                    logs.iter()
+                        .filter(|(level, _)| level == "exception")
+                        .map(move |(_, msg)| (uid.clone(), msg.clone()))
+                })
+                .collect::<Vec<(String, String)>>()
+        };
+
+        for (uid, msg) in errors {
+            let name = {
+                let profiles = Config::profiles();
+                let profiles = profiles.latest();
+                profiles
+                    .get_item(&uid)
+                    .ok()
+                    .and_then(|item| item.name.clone())
+            };
+            let name = name.unwrap_or(uid);
+            log::error!(target: "app", "the script \"{name}\" failed, {msg}");
+            handle::Handle::notice_message("set_config::error", format!("{name}: {msg}"));
+        }
+    }
+
     /// 启动核心
     pub async fn run_core(&self) -> Result<()> {
         let config_path = Config::generate_file(ConfigType::Run)?;
 
+        // 内核可能被切换，启动后重新获取版本
+        *self.version.lock() = None;
+
         #[allow(unused_mut)]
         let mut should_kill = match self.sidecar.lock().take() {
             Some(child) => {
@@ -106,6 +319,9 @@ impl CoreManager {
             sleep(Duration::from_millis(500)).await;
         }
 
+        // 旧内核停了再清空统计，免得把旧内核的数据算进来
+        ConnStats::global().reset();
+
         // 服务模式
         let enable = { Config::verge().latest().enable_service_mode };
         let enable = enable.unwrap_or(false);
@@ -122,7 +338,11 @@ impl CoreManager {
             })()
             .await
             {
-                Ok(_) => return Ok(()),
+                Ok(_) => {
+                    self.prefetch_version();
+                    log_err!(Sysopt::global().release_kill_switch());
+                    return Ok(());
+                }
                 Err(err) => {
                     // 修改这个值，免得stop出错
                     *self.use_service_mode.lock() = false;
@@ -134,19 +354,30 @@ impl CoreManager {
         let app_dir = dirs::app_home_dir()?;
         let app_dir = dirs::path_to_str(&app_dir)?;
 
-        let clash_core = { Config::verge().latest().clash_core.clone() };
+        let (clash_core, extra_args) = {
+            let verge = Config::verge();
+            let verge = verge.latest();
+            (verge.clash_core.clone(), verge.core_extra_args.clone())
+        };
         let clash_core = clash_core.unwrap_or("clash".into());
         let is_clash = clash_core == "clash";
 
         let config_path = dirs::path_to_str(&config_path)?;
 
-        let args = match clash_core.as_str() {
+        let mut args = match clash_core.as_str() {
             "clash-meta" => vec!["-d", app_dir, "-f", config_path],
             "clash-meta-alpha" => vec!["-d", app_dir, "-f", config_path],
             _ => vec!["-d", app_dir, "-f", config_path],
         };
-
-        let cmd = Command::new_sidecar(clash_core)?;
+        let extra_args = extra_args.unwrap_or_default();
+        args.extend(
+            extra_args
+                .iter()
+                .map(|arg| arg.as_str())
+                .filter(|arg| !arg.trim().is_empty()),
+        );
+
+        let cmd = Self::core_command()?;
         let (mut rx, cmd_child) = cmd.args(args).spawn()?;
 
         // 将pid写入文件中
@@ -160,6 +391,7 @@ impl CoreManager {
             <Result<()>>::Ok(())
         })());
 
+        let pid = cmd_child.pid();
         let mut sidecar = self.sidecar.lock();
         *sidecar = Some(cmd_child);
         drop(sidecar);
@@ -179,15 +411,25 @@ impl CoreManager {
                     CommandEvent::Stderr(err) => {
                         // let stdout = clash_api::parse_log(err.clone());
                         log::error!(target: "app", "[clash]: {err}");
-                        Logger::global().set_log(err);
+                        Logger::global().set_log_with_level(err, "error");
                     }
                     CommandEvent::Error(err) => {
                         log::error!(target: "app", "[clash]: {err}");
-                        Logger::global().set_log(err);
+                        Logger::global().set_log_with_level(err, "error");
                     }
-                    CommandEvent::Terminated(_) => {
-                        log::info!(target: "app", "clash core terminated");
-                        let _ = CoreManager::global().recover_core();
+                    CommandEvent::Terminated(payload) => {
+                        log::info!(target: "app", "clash core terminated, {payload:?}");
+                        // 停止和重启内核时 sidecar 已经被取走了
+                        let unexpected = {
+                            let sidecar = CoreManager::global().sidecar.lock();
+                            sidecar.as_ref().map_or(false, |child| child.pid() == pid)
+                        };
+                        let manager = CoreManager::global();
+                        if !unexpected || manager.on_core_crashed(payload.code, payload.signal) {
+                            let _ = manager.recover_core();
+                        } else {
+                            manager.sidecar.lock().take();
+                        }
                         break;
                     }
                     _ => {}
@@ -195,9 +437,110 @@ impl CoreManager {
             }
         });
 
+        self.prefetch_version();
+        log_err!(Sysopt::global().release_kill_switch());
         Ok(())
     }
 
+    /// record the crash, engage the kill switch and notify
+    /// return whether to restart by `core_crash_action` and the crash loop limit
+    fn on_core_crashed(&self, code: Option<i32>, signal: Option<i32>) -> bool {
+        log::error!(target: "app", "the core exits unexpectedly, code {code:?}, signal {signal:?}");
+
+        let allowed = self.crash.lock().record(code, signal, Instant::now());
+        let action = { Config::verge().latest().core_crash_action.clone() };
+        let restart = allowed && action.as_deref() != Some("notify");
+
+        let exit = match (code, signal) {
+            (Some(code), _) => format!("code {code}"),
+            (None, Some(signal)) => format!("signal {signal}"),
+            _ => "unknown".into(),
+        };
+        if restart {
+            let msg = format!("the core exits unexpectedly ({exit}), restarting");
+            handle::Handle::notice_message("set_config::warn", msg);
+        } else {
+            let msg = match allowed {
+                true => format!("the core exits unexpectedly ({exit})"),
+                false => format!(
+                    "the core crashed too many times in {}s ({exit}), stop restarting",
+                    CRASH_LOOP_WINDOW.as_secs()
+                ),
+            };
+            handle::Handle::notice_message("set_config::error", &msg);
+            log_err!(tauri::api::notification::Notification::new(dirs::APP_ID)
+                .title("Clash Verge")
+                .body(msg)
+                .show());
+        }
+
+        match Sysopt::global().engage_kill_switch() {
+            Ok(true) => {
+                let msg = "the core exits unexpectedly, the kill switch blocks the traffic";
+                handle::Handle::notice_message("set_config::error", msg);
+                log_err!(tauri::api::notification::Notification::new(dirs::APP_ID)
+                    .title("Clash Verge")
+                    .body(msg)
+                    .show());
+            }
+            Ok(false) => {}
+            Err(err) => log::error!(target: "app", "failed to engage the kill switch: {err}"),
+        }
+        restart
+    }
+
+    pub fn crash_state(&self) -> CoreCrashState {
+        self.crash.lock().clone()
+    }
+
+    /// 获取内核版本，优先使用缓存
+    pub async fn get_version(&self) -> Result<clash_api::CoreVersion> {
+        if let Some(version) = self.version.lock().clone() {
+            return Ok(version);
+        }
+
+        let mut version = clash_api::ClashApi::from_config()?.get_version().await?;
+        version.path = Self::core_path()
+            .ok()
+            .map(|path| path.to_string_lossy().to_string());
+        *self.version.lock() = Some(version.clone());
+        Ok(version)
+    }
+
+    /// 清空内核的fake-ip缓存，不用重启内核
+    pub async fn flush_fakeip(&self) -> Result<FlushResult> {
+        let enabled = {
+            let runtime = Config::runtime();
+            let runtime = runtime.latest();
+            runtime.config.as_ref().map(fakeip_enabled)
+        };
+        match enabled {
+            None => bail!("the core is not running"),
+            Some(false) => {
+                return Ok(FlushResult::NotApplicable {
+                    reason: "fake-ip is not enabled in the dns config".into(),
+                })
+            }
+            Some(true) => {}
+        }
+
+        if clash_api::ClashApi::from_config()?.flush_fakeip().await? {
+            Ok(FlushResult::Flushed)
+        } else {
+            Ok(FlushResult::NotApplicable {
+                reason: "the core does not support flushing the fake-ip cache".into(),
+            })
+        }
+    }
+
+    /// 等内核启动后缓存版本
+    fn prefetch_version(&self) {
+        tauri::async_runtime::spawn(async {
+            sleep(Duration::from_millis(1000)).await;
+            log_err!(Self::global().get_version().await);
+        });
+    }
+
     /// 重启内核
     pub fn recover_core(&'static self) -> Result<()> {
         // 服务模式不管
@@ -240,7 +583,9 @@ impl CoreManager {
             tun.insert("enable".into(), false.into());
             disable.insert("tun".into(), tun.into());
             log::debug!(target: "app", "disable tun mode");
-            let _ = clash_api::patch_configs(&disable).await;
+            if let Ok(api) = clash_api::ClashApi::from_config() {
+                let _ = api.patch_configs(&disable).await;
+            }
         });
 
         if *self.use_service_mode.lock() {
@@ -300,11 +645,8 @@ impl CoreManager {
     pub async fn update_config(&self) -> Result<()> {
         log::debug!(target: "app", "try to update clash config");
 
-        // 更新订阅
-        Config::generate()?;
-
-        // 检查订阅是否正常
-        self.check_config()?;
+        // 更新订阅并检查订阅是否正常
+        self.generate_checked()?;
 
         // 更新运行时订阅
         let path = Config::generate_file(ConfigType::Run)?;
@@ -312,7 +654,8 @@ impl CoreManager {
 
         // 发送请求 发送5次
         for i in 0..5 {
-            match clash_api::put_configs(path).await {
+            let api = clash_api::ClashApi::from_config()?;
+            match api.put_configs(path).await {
                 Ok(_) => break,
                 Err(err) => {
                     if i < 4 {
@@ -328,3 +671,92 @@ impl CoreManager {
         Ok(())
     }
 }
+
+/// 检查自定义的内核是否存在且可执行
+fn check_executable(path: &Path) -> Result<()> {
+    let display = path.display();
+    let meta = fs::metadata(path)
+        .with_context(|| format!("the custom core \"{display}\" does not exist"))?;
+    if !meta.is_file() {
+        bail!("the custom core \"{display}\" is not a file");
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if meta.permissions().mode() & 0o111 == 0 {
+            bail!("the custom core \"{display}\" is not executable");
+        }
+    }
+    Ok(())
+}
+
+/// 按可执行文件判断进程是否为内核，取不到时比较进程名
+/// linux 的进程名最多15个字节
+fn is_core_process(name: &str, exe: Option<&Path>, core_path: &Path) -> bool {
+    if let Some(exe) = exe {
+        return exe == core_path;
+    }
+    let Some(stem) = core_path.file_stem().and_then(|s| s.to_str()) else {
+        return false;
+    };
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+    name == stem || (name.len() == 15 && stem.starts_with(name))
+}
+
+/// dns.enable 且 dns.enhanced-mode 为 fake-ip
+fn fakeip_enabled(config: &Mapping) -> bool {
+    let dns = match config.get("dns").and_then(|v| v.as_mapping()) {
+        Some(dns) => dns,
+        None => return false,
+    };
+    let enable = dns.get("enable").and_then(|v| v.as_bool()).unwrap_or(false);
+    let mode = dns.get("enhanced-mode").and_then(|v| v.as_str());
+    enable && mode == Some("fake-ip")
+}
+
+#[test]
+fn test_fakeip_enabled() {
+    let enabled = |s: &str| fakeip_enabled(&serde_yaml::from_str::<Mapping>(s).unwrap());
+
+    assert!(enabled("dns: {enable: true, enhanced-mode: fake-ip}"));
+    assert!(!enabled("dns: {enable: false, enhanced-mode: fake-ip}"));
+    assert!(!enabled("dns: {enable: true, enhanced-mode: redir-host}"));
+    assert!(!enabled("mode: rule"));
+}
+
+#[test]
+fn test_is_core_process() {
+    let core = Path::new("/opt/cores/mihomo");
+    assert!(is_core_process("mihomo", Some(core), core));
+    let other = Path::new("/usr/bin/mihomo");
+    assert!(!is_core_process("mihomo", Some(other), core));
+    assert!(is_core_process("mihomo", None, core));
+    assert!(!is_core_process("clash-verge", None, core));
+
+    let core = Path::new("/usr/bin/verge-mihomo-alpha");
+    assert!(is_core_process("verge-mihomo-al", None, core));
+    assert!(!is_core_process("verge-mihomo", None, core));
+    let core = Path::new("C:/Program Files/Clash Verge/clash-meta.exe");
+    assert!(is_core_process("clash-meta.exe", None, core));
+}
+
+#[test]
+fn test_crash_loop_limit() {
+    let mut state = CoreCrashState::default();
+    let start = Instant::now();
+    for i in 0..CRASH_LOOP_LIMIT {
+        let now = start + Duration::from_secs(i as u64);
+        assert!(state.record(Some(1), None, now));
+    }
+    assert!(!state.record(Some(2), None, start + Duration::from_secs(10)));
+    assert!(state.restart_blocked);
+    assert_eq!(state.count, CRASH_LOOP_LIMIT + 1);
+    assert_eq!(state.last_code, Some(2));
+
+    // 窗口外的崩溃不再计入
+    let later = start + CRASH_LOOP_WINDOW + Duration::from_secs(11);
+    assert!(state.record(None, Some(9), later));
+    assert!(!state.restart_blocked);
+    assert_eq!(state.last_signal, Some(9));
+}
@@ -3,6 +3,7 @@ use crate::log_err;
 use anyhow::{bail, Result};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
+use serde::Serialize;
 use std::sync::Arc;
 use tauri::{AppHandle, Manager, Window};
 
@@ -50,6 +51,20 @@ impl Handle {
         }
     }
 
+    /// emit the event with the payload to the frontend
+    pub fn emit<S: Serialize + Clone>(event: &str, payload: S) {
+        if let Some(window) = Self::global().get_window() {
+            log_err!(window.emit(event, payload));
+        }
+    }
+
+    /// the mixed port in use is changed, e.g. after the random port is resolved
+    pub fn notice_port_changed(port: u16) {
+        if let Some(app_handle) = Self::global().app_handle.lock().as_ref() {
+            log_err!(app_handle.emit_all("verge://mixed-port-changed", port));
+        }
+    }
+
     pub fn notice_message<S: Into<String>, M: Into<String>>(status: S, msg: M) {
         if let Some(window) = Self::global().get_window() {
             log_err!(window.emit("verge://notice-message", (status.into(), msg.into())));
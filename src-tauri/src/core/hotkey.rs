@@ -2,9 +2,42 @@ use crate::{config::Config, feat, log_err};
 use anyhow::{bail, Result};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, sync::Arc};
+use tao::accelerator::Accelerator;
 use tauri::{AppHandle, GlobalShortcutManager};
 
+/// the functions which can be bound to a hotkey
+pub const HOTKEY_FUNCS: [(&str, fn()); 9] = [
+    ("open_or_close_dashboard", feat::open_or_close_dashboard),
+    ("toggle_window", feat::toggle_window),
+    ("clash_mode_rule", || feat::change_clash_mode("rule".into())),
+    ("clash_mode_global", || {
+        feat::change_clash_mode("global".into())
+    }),
+    ("clash_mode_direct", || {
+        feat::change_clash_mode("direct".into())
+    }),
+    ("toggle_system_proxy", feat::toggle_system_proxy),
+    ("toggle_tun_mode", feat::toggle_tun_mode),
+    ("open_config_dir", feat::open_config_dir),
+    ("open_logs_dir", feat::open_logs_dir),
+];
+
+fn hotkey_func(func: &str) -> Option<fn()> {
+    HOTKEY_FUNCS
+        .iter()
+        .find(|(name, _)| *name == func)
+        .map(|(_, f)| *f)
+}
+
+/// a hotkey binding in the exported json
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HotkeyBinding {
+    pub func: String,
+    pub key: String,
+}
+
 pub struct Hotkey {
     current: Arc<Mutex<Vec<String>>>, // 保存当前的热键设置
 
@@ -58,23 +91,19 @@ impl Hotkey {
     }
 
     fn register(&self, hotkey: &str, func: &str) -> Result<()> {
+        let f = match hotkey_func(func.trim()) {
+            Some(f) => f,
+            None => bail!("invalid function \"{func}\""),
+        };
+        // 解析不了的快捷键在注册时会直接panic
+        Self::check_key(hotkey)?;
+
         let mut manager = self.get_manager()?;
 
         if manager.is_registered(hotkey)? {
             manager.unregister(hotkey)?;
         }
 
-        let f = match func.trim() {
-            "open_or_close_dashboard" => feat::open_or_close_dashboard,
-            "clash_mode_rule" => || feat::change_clash_mode("rule".into()),
-            "clash_mode_global" => || feat::change_clash_mode("global".into()),
-            "clash_mode_direct" => || feat::change_clash_mode("direct".into()),
-            "toggle_system_proxy" => feat::toggle_system_proxy,
-            "toggle_tun_mode" => feat::toggle_tun_mode,
-
-            _ => bail!("invalid function \"{func}\""),
-        };
-
         manager.register(hotkey, f)?;
         log::info!(target: "app", "register hotkey {hotkey} {func}");
         Ok(())
@@ -105,6 +134,69 @@ impl Hotkey {
         Ok(())
     }
 
+    /// 用和注册时相同的解析器检查快捷键，返回规范化后的形式(大写，修饰键排序)
+    /// 例如 `shift+CmdOrCtrl+a` => `CMDORCTRL+SHIFT+A`
+    pub fn check_key(key: &str) -> Result<String> {
+        if key.parse::<Accelerator>().is_err() {
+            bail!("invalid hotkey \"{key}\"");
+        }
+
+        let parts = key.split('+').map(|k| k.trim().to_uppercase());
+        let parts = parts.collect::<Vec<_>>();
+        let (last, modifiers) = match parts.split_last() {
+            Some(split) => split,
+            None => bail!("the hotkey is empty"),
+        };
+
+        let mut modifiers = modifiers.to_vec();
+        modifiers.sort();
+        modifiers.dedup();
+
+        modifiers.push(last.clone());
+        Ok(modifiers.join("+"))
+    }
+
+    /// 解析并校验导入的热键，返回保存到配置中的 `func,key` 列表
+    pub fn parse_bindings(bindings: &[HotkeyBinding]) -> Result<Vec<String>> {
+        let mut keys: HashMap<String, &str> = HashMap::new();
+        let mut hotkeys = vec![];
+
+        for binding in bindings.iter() {
+            let func = binding.func.trim();
+            if hotkey_func(func).is_none() {
+                bail!("invalid function \"{func}\"");
+            }
+            let key = binding.key.trim();
+            let normalized = Self::check_key(key)?;
+
+            match keys.get(&normalized) {
+                Some(&exist) if exist == func => continue,
+                Some(exist) => {
+                    bail!("the hotkey \"{key}\" is bound to both \"{exist}\" and \"{func}\"")
+                }
+                None => {
+                    keys.insert(normalized, func);
+                    hotkeys.push(format!("{func},{key}"));
+                }
+            }
+        }
+        Ok(hotkeys)
+    }
+
+    /// 把配置中的 `func,key` 列表转成导出的格式
+    pub fn to_bindings(hotkeys: &[String]) -> Vec<HotkeyBinding> {
+        hotkeys
+            .iter()
+            .filter_map(|hotkey| {
+                let (func, key) = hotkey.split_once(',')?;
+                Some(HotkeyBinding {
+                    func: func.trim().into(),
+                    key: key.trim().into(),
+                })
+            })
+            .collect()
+    }
+
     fn get_map_from_vec(hotkeys: &Vec<String>) -> HashMap<&str, &str> {
         let mut map = HashMap::new();
 
@@ -158,3 +250,45 @@ impl Drop for Hotkey {
         }
     }
 }
+
+#[test]
+fn test_hotkey_bindings() {
+    assert_eq!(
+        Hotkey::check_key("shift + CmdOrCtrl+a").unwrap(),
+        "CMDORCTRL+SHIFT+A"
+    );
+    assert_eq!(Hotkey::check_key("F12").unwrap(), "F12");
+    assert_eq!(Hotkey::check_key("alt+space").unwrap(), "ALT+SPACE");
+    assert!(Hotkey::check_key("").is_err());
+    assert!(Hotkey::check_key("CTRL+").is_err());
+    assert!(Hotkey::check_key("HYPER+A").is_err());
+    assert!(Hotkey::check_key("CTRL+F25").is_err());
+    assert!(Hotkey::check_key("CTRL+AB").is_err());
+
+    let binding = |func: &str, key: &str| HotkeyBinding {
+        func: func.into(),
+        key: key.into(),
+    };
+
+    let hotkeys = Hotkey::parse_bindings(&[
+        binding("toggle_window", "CMD+SHIFT+A"),
+        binding("toggle_window", "shift+cmd+a"),
+        binding("clash_mode_rule", "CTRL+R"),
+    ])
+    .unwrap();
+    assert_eq!(
+        hotkeys,
+        vec!["toggle_window,CMD+SHIFT+A", "clash_mode_rule,CTRL+R"]
+    );
+    assert_eq!(
+        Hotkey::to_bindings(&hotkeys)[1],
+        binding("clash_mode_rule", "CTRL+R")
+    );
+
+    assert!(Hotkey::parse_bindings(&[binding("unknown", "CTRL+A")]).is_err());
+    assert!(Hotkey::parse_bindings(&[
+        binding("toggle_window", "CTRL+A"),
+        binding("toggle_tun_mode", "ctrl+a"),
+    ])
+    .is_err());
+}
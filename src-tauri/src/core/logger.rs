@@ -1,11 +1,57 @@
+use super::handle;
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
+use serde::Serialize;
 use std::{collections::VecDeque, sync::Arc};
 
 const LOGS_QUEUE_LEN: usize = 100;
 
+/// a line of the core log
+/// the payload of `verge://core-log`
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct CoreLog {
+    pub time: String,
+    /// `debug` / `info` / `warning` / `error` / ...
+    pub level: String,
+    pub payload: String,
+    #[serde(skip)]
+    raw: String,
+}
+
+impl CoreLog {
+    /// parse the line of mihomo, the level is `default_level` if not found
+    /// - `time="2024-01-01T00:00:00+08:00" level=info msg="xxx"`
+    /// - `2024-01-01T00:00:00+08:00 INF xxx`
+    pub fn parse(line: String, default_level: &str) -> Self {
+        let mut log = CoreLog {
+            time: String::new(),
+            level: default_level.into(),
+            payload: line.clone(),
+            raw: line,
+        };
+
+        let fields = parse_logfmt(&log.raw);
+        if let (Some(level), Some(msg)) = (fields.get("level"), fields.get("msg")) {
+            log.time = fields.get("time").cloned().unwrap_or_default();
+            log.level = normalize_level(level);
+            log.payload = msg.clone();
+            return log;
+        }
+
+        let mut parts = log.raw.splitn(3, ' ');
+        if let (Some(time), Some(level), Some(msg)) = (parts.next(), parts.next(), parts.next()) {
+            if is_level(level) {
+                log.time = time.into();
+                log.level = normalize_level(level);
+                log.payload = msg.into();
+            }
+        }
+        log
+    }
+}
+
 pub struct Logger {
-    log_data: Arc<Mutex<VecDeque<String>>>,
+    log_data: Arc<Mutex<VecDeque<CoreLog>>>,
 }
 
 impl Logger {
@@ -18,15 +64,33 @@ impl Logger {
     }
 
     pub fn get_log(&self) -> VecDeque<String> {
-        self.log_data.lock().clone()
+        self.log_data
+            .lock()
+            .iter()
+            .map(|log| log.raw.clone())
+            .collect()
+    }
+
+    /// the parsed logs
+    pub fn get_core_logs(&self) -> Vec<CoreLog> {
+        self.log_data.lock().iter().cloned().collect()
     }
 
     pub fn set_log(&self, text: String) {
-        let mut logs = self.log_data.lock();
-        if logs.len() > LOGS_QUEUE_LEN {
-            logs.pop_front();
+        self.set_log_with_level(text, "info");
+    }
+
+    /// `level` is used if the line has no level, e.g. stderr
+    pub fn set_log_with_level(&self, text: String, level: &str) {
+        let log = CoreLog::parse(text, level);
+        {
+            let mut logs = self.log_data.lock();
+            if logs.len() > LOGS_QUEUE_LEN {
+                logs.pop_front();
+            }
+            logs.push_back(log.clone());
         }
-        logs.push_back(text);
+        handle::Handle::emit("verge://core-log", log);
     }
 
     pub fn clear_log(&self) {
@@ -34,3 +98,83 @@ impl Logger {
         logs.clear();
     }
 }
+
+fn is_level(level: &str) -> bool {
+    let level = normalize_level(level);
+    matches!(
+        level.as_str(),
+        "debug" | "info" | "warning" | "error" | "fatal" | "silent"
+    )
+}
+
+fn normalize_level(level: &str) -> String {
+    match level.to_lowercase().as_str() {
+        "dbg" => "debug".into(),
+        "inf" => "info".into(),
+        "warn" | "wrn" => "warning".into(),
+        "err" => "error".into(),
+        "ftl" => "fatal".into(),
+        level => level.into(),
+    }
+}
+
+/// parse `key=value key="quoted value"`
+fn parse_logfmt(line: &str) -> std::collections::HashMap<String, String> {
+    let mut fields = std::collections::HashMap::new();
+    let mut rest = line.trim();
+
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().to_string();
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                // the escaped quote `\"` is not the end
+                let mut end = None;
+                let mut escaped = false;
+                for (i, c) in quoted.char_indices() {
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => {
+                            end = Some(i);
+                            break;
+                        }
+                        _ => escaped = false,
+                    }
+                }
+                match end {
+                    Some(end) => (quoted[..end].replace("\\\"", "\""), &quoted[end + 1..]),
+                    None => (quoted.to_string(), ""),
+                }
+            }
+            None => match value.split_once(' ') {
+                Some((value, next)) => (value.to_string(), next),
+                None => (value.to_string(), ""),
+            },
+        };
+        if key.is_empty() || key.contains(' ') {
+            break;
+        }
+        fields.insert(key, value);
+        rest = next.trim_start();
+    }
+    fields
+}
+
+#[test]
+fn test_parse_core_log() {
+    let log = CoreLog::parse(
+        r#"time="2024-01-01T00:00:00+08:00" level=warning msg="[TCP] dial \"a\" failed""#.into(),
+        "info",
+    );
+    assert_eq!(log.time, "2024-01-01T00:00:00+08:00");
+    assert_eq!(log.level, "warning");
+    assert_eq!(log.payload, r#"[TCP] dial "a" failed"#);
+
+    let log = CoreLog::parse("2024-01-01T00:00:00+08:00 ERR start failed".into(), "info");
+    assert_eq!(log.level, "error");
+    assert_eq!(log.payload, "start failed");
+
+    let log = CoreLog::parse("panic: runtime error".into(), "error");
+    assert_eq!(log.level, "error");
+    assert_eq!(log.payload, "panic: runtime error");
+    assert!(log.time.is_empty());
+}
@@ -1,10 +1,13 @@
+use super::{service, CoreManager};
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+
 /// 给clash内核的tun模式授权
 #[cfg(any(target_os = "macos", target_os = "linux"))]
 pub fn grant_permission(core: String) -> anyhow::Result<()> {
     use std::process::Command;
-    use tauri::utils::platform::current_exe;
 
-    let path = current_exe()?.with_file_name(core).canonicalize()?;
+    let path = CoreManager::core_path_of(&core)?.canonicalize()?;
     let path = path.display().to_string();
 
     log::debug!("grant_permission path: {path}");
@@ -45,3 +48,147 @@ pub fn grant_permission(core: String) -> anyhow::Result<()> {
         anyhow::bail!("{stderr}");
     }
 }
+
+/// 以管理员权限启动新的app进程
+/// 返回时新进程已经通过授权，取消授权则返回错误
+/// macos/linux 不以root运行界面，内核通过授权或服务模式获得权限
+#[cfg(target_os = "windows")]
+pub fn spawn_elevated(args: &[String]) -> anyhow::Result<()> {
+    use std::os::windows::process::CommandExt;
+    use std::process::Command;
+    use tauri::utils::platform::current_exe;
+
+    let exe = current_exe()?.display().to_string();
+    log::info!(target: "app", "spawn elevated: {exe} {args:?}");
+
+    // powershell 的单引号字符串，Start-Process 用空格拼接参数，带空格的再包一层双引号
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let quote_arg = |s: &str| match s.is_empty() || s.contains(' ') {
+        true => quote(&format!("\"{s}\"")),
+        false => quote(s),
+    };
+
+    let mut command = format!("Start-Process -FilePath {} -Verb RunAs", quote(&exe));
+    if !args.is_empty() {
+        let list = args.iter().map(|a| quote_arg(a)).collect::<Vec<_>>();
+        command.push_str(&format!(" -ArgumentList {}", list.join(",")));
+    }
+
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &command])
+        .creation_flags(0x08000000)
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("failed to relaunch as administrator, {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// 开启tun模式前的检查结果
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TunCapability {
+    /// whether tun mode can be enabled
+    pub available: bool,
+
+    /// what is missing, e.g. `wintun driver` `privilege`
+    pub missing: Vec<String>,
+}
+
+/// 检查tun模式所需的驱动和权限
+/// 服务模式下内核以高权限运行，不需要额外授权
+pub async fn check_tun_capability() -> TunCapability {
+    let mut missing = vec![];
+
+    let service_mode = { Config::verge().latest().enable_service_mode };
+    let service_mode =
+        service_mode.unwrap_or(false) && service::check_service().await.is_ok_and(|r| r.code == 0);
+
+    #[cfg(target_os = "windows")]
+    {
+        if !has_wintun_driver() {
+            missing.push("wintun driver".to_string());
+        }
+        if !service_mode && !is_elevated() {
+            missing.push("administrator privilege".to_string());
+        }
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    if !service_mode && !core_has_permission() {
+        missing.push("core privilege".to_string());
+    }
+
+    TunCapability {
+        available: missing.is_empty(),
+        missing,
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn has_wintun_driver() -> bool {
+    use crate::utils::dirs;
+    use tauri::utils::platform::current_exe;
+
+    let mut paths = vec![];
+    if let Ok(exe) = current_exe() {
+        paths.push(exe.with_file_name("wintun.dll"));
+    }
+    if let Ok(res_dir) = dirs::app_resources_dir() {
+        paths.push(res_dir.join("wintun.dll"));
+    }
+    if let Ok(system_root) = std::env::var("SystemRoot") {
+        paths.push(
+            std::path::PathBuf::from(system_root)
+                .join("System32")
+                .join("wintun.dll"),
+        );
+    }
+    paths.iter().any(|p| p.exists())
+}
+
+/// 当前进程是否以管理员(windows)/root(macos/linux)权限运行
+#[cfg(target_os = "windows")]
+pub fn is_elevated() -> bool {
+    use deelevate::{PrivilegeLevel, Token};
+
+    Token::with_current_process()
+        .and_then(|token| token.privilege_level())
+        .map_or(false, |level| !matches!(level, PrivilegeLevel::NotPrivileged))
+}
+
+#[cfg(unix)]
+pub fn is_elevated() -> bool {
+    users::get_effective_uid() == 0
+}
+
+/// 内核是否已经通过 `grant_permission` 授权
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn core_has_permission() -> bool {
+    // 和启动内核时的路径一致，包括自定义的内核
+    let path = match CoreManager::core_path() {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // chown root:admin && chmod +sx
+        std::fs::metadata(path).map_or(false, |m| m.uid() == 0 && m.mode() & 0o4000 != 0)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if is_elevated() {
+            return true;
+        }
+        std::process::Command::new("getcap")
+            .arg(path)
+            .output()
+            .map_or(false, |output| {
+                String::from_utf8_lossy(&output.stdout).contains("cap_net_admin")
+            })
+    }
+}
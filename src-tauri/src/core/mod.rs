@@ -1,9 +1,11 @@
 pub mod clash_api;
+pub mod conn_stats;
 mod core;
 pub mod handle;
 pub mod hotkey;
 pub mod logger;
 pub mod manager;
+pub mod monitor;
 pub mod sysopt;
 pub mod timer;
 pub mod tray;
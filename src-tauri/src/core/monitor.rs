@@ -0,0 +1,142 @@
+use super::{clash_api, handle};
+use crate::{config::Config, feat};
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+
+/// 多少个内存数据刷新一次托盘
+const TRAY_REFRESH_TICKS: usize = 10;
+
+/// 同步内核模式的间隔
+const MODE_SYNC_SECS: u64 = 5;
+
+/// the latest memory of the core
+#[derive(Debug, Default, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum MemoryState {
+    /// not received yet or the core is not running
+    #[default]
+    Unknown,
+    /// the core has no `/memory` endpoint
+    Unsupported,
+    Ok {
+        inuse: u64,
+        oslimit: u64,
+    },
+}
+
+#[derive(Debug)]
+pub struct Monitor {
+    memory: Arc<Mutex<MemoryState>>,
+}
+
+impl Monitor {
+    pub fn global() -> &'static Monitor {
+        static MONITOR: OnceCell<Monitor> = OnceCell::new();
+
+        MONITOR.get_or_init(|| Monitor {
+            memory: Arc::new(Mutex::new(MemoryState::Unknown)),
+        })
+    }
+
+    pub fn memory(&self) -> MemoryState {
+        *self.memory.lock()
+    }
+
+    /// the core api is reachable
+    pub fn core_running(&self) -> bool {
+        self.memory() != MemoryState::Unknown
+    }
+
+    /// 更新状态，内核启停时刷新托盘图标
+    fn set_memory(&self, state: MemoryState) {
+        let was_running = self.core_running();
+        *self.memory.lock() = state;
+        if was_running != self.core_running() {
+            let _ = handle::Handle::update_systray_part();
+        }
+    }
+
+    /// 在后台订阅内核的内存，断开后重连 (内核重启等)
+    /// 并定时同步内核的模式
+    pub fn init(&'static self) {
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let mut ticks = 0;
+                let result = async {
+                    let api = clash_api::ClashApi::from_config()?;
+                    api.stream_memory(|memory| {
+                        self.set_memory(MemoryState::Ok {
+                            inuse: memory.inuse,
+                            oslimit: memory.oslimit,
+                        });
+
+                        ticks += 1;
+                        if ticks % TRAY_REFRESH_TICKS == 0 && Self::show_in_tray() {
+                            let _ = handle::Handle::update_systray_part();
+                        }
+                    })
+                    .await
+                }
+                .await;
+
+                let retry = match result {
+                    Ok(true) => 3,
+                    Ok(false) => {
+                        self.set_memory(MemoryState::Unsupported);
+                        // 可能切换了内核，过一会再看
+                        30
+                    }
+                    Err(err) => {
+                        log::debug!(target: "app", "failed to get the core memory: {err}");
+                        self.set_memory(MemoryState::Unknown);
+                        3
+                    }
+                };
+                sleep(Duration::from_secs(retry)).await;
+            }
+        });
+
+        // 外部控制 (如 web ui) 修改的模式也要反映到托盘
+        tauri::async_runtime::spawn(async move {
+            loop {
+                sleep(Duration::from_secs(MODE_SYNC_SECS)).await;
+                if let Err(err) = feat::sync_clash_mode().await {
+                    log::debug!(target: "app", "failed to sync the clash mode: {err}");
+                }
+            }
+        });
+    }
+
+    fn show_in_tray() -> bool {
+        Config::verge()
+            .latest()
+            .enable_memory_usage
+            .unwrap_or(true)
+    }
+}
+
+/// e.g. `12.3 MB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{value:.1} {}", UNITS[unit]),
+    }
+}
+
+#[test]
+fn test_format_bytes() {
+    assert_eq!(format_bytes(0), "0 B");
+    assert_eq!(format_bytes(1023), "1023 B");
+    assert_eq!(format_bytes(1536), "1.5 KB");
+    assert_eq!(format_bytes(32 * 1024 * 1024), "32.0 MB");
+}
@@ -4,8 +4,8 @@ use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::process::Command as StdCommand;
 use std::time::Duration;
-use std::{env::current_exe, process::Command as StdCommand};
 use tokio::time::sleep;
 
 // Windows only
@@ -239,9 +239,7 @@ pub(super) async fn run_core_by_service(config_file: &PathBuf) -> Result<()> {
     let clash_core = { Config::verge().latest().clash_core.clone() };
     let clash_core = clash_core.unwrap_or("clash".into());
 
-    let bin_ext = if cfg!(windows) { ".exe" } else { "" };
-    let clash_bin = format!("{clash_core}{bin_ext}");
-    let bin_path = current_exe()?.with_file_name(clash_bin);
+    let bin_path = super::CoreManager::core_path()?;
     let bin_path = dirs::path_to_str(&bin_path)?;
 
     let config_dir = dirs::app_home_dir()?;
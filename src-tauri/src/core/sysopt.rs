@@ -1,9 +1,16 @@
-use crate::{config::Config, log_err};
-use anyhow::{anyhow, Result};
+use crate::{
+    config::{Config, IVerge},
+    core::handle,
+    log_err,
+    utils::{dirs, help, pac, resolve},
+};
+use anyhow::{anyhow, bail, Result};
 use auto_launch::{AutoLaunch, AutoLaunchBuilder};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
 use std::env::current_exe;
+use std::fs;
 use std::sync::Arc;
 use sysproxy::Sysproxy;
 use tauri::async_runtime::Mutex as TokioMutex;
@@ -21,14 +28,49 @@ pub struct Sysopt {
 
     /// record whether the guard async is running or not
     guard_state: Arc<TokioMutex<bool>>,
+
+    /// the system proxy points to `KILL_SWITCH_PORT` after the core crashed
+    kill_switch: Arc<Mutex<bool>>,
+
+    /// the pac url set by the app in the `pac` mode
+    cur_autoproxy: Arc<Mutex<Option<String>>>,
+
+    /// the system proxy is paused for the captive portal
+    portal_paused: Arc<Mutex<bool>>,
+}
+
+/// 没有服务监听的端口，连接会被直接拒绝
+const KILL_SWITCH_PORT: u16 = 1;
+
+/// the marker of the system proxy set by the app
+/// it is left behind if the app crashes without resetting the proxy
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct SysproxyMarker {
+    host: String,
+    port: u16,
+    /// the time when the proxy was set
+    timestamp: i64,
+    /// the pac url in the `pac` mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pac_url: Option<String>,
+}
+
+/// the system proxy set by other tools before the app
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ExistingProxy {
+    pub host: String,
+    pub port: u16,
+    pub bypass: String,
+    /// no profiles yet, the onboarding should offer to keep it
+    pub first_run: bool,
 }
 
 #[cfg(target_os = "windows")]
-static DEFAULT_BYPASS: &str = "localhost;127.*;192.168.*;10.*;172.16.*;172.17.*;172.18.*;172.19.*;172.20.*;172.21.*;172.22.*;172.23.*;172.24.*;172.25.*;172.26.*;172.27.*;172.28.*;172.29.*;172.30.*;172.31.*;<local>";
+pub static DEFAULT_BYPASS: &str = "localhost;127.*;192.168.*;10.*;172.16.*;172.17.*;172.18.*;172.19.*;172.20.*;172.21.*;172.22.*;172.23.*;172.24.*;172.25.*;172.26.*;172.27.*;172.28.*;172.29.*;172.30.*;172.31.*;<local>";
 #[cfg(target_os = "linux")]
-static DEFAULT_BYPASS: &str = "localhost,127.0.0.1,192.168.0.0/16,10.0.0.0/8,172.16.0.0/12,::1";
+pub static DEFAULT_BYPASS: &str = "localhost,127.0.0.1,192.168.0.0/16,10.0.0.0/8,172.16.0.0/12,::1";
 #[cfg(target_os = "macos")]
-static DEFAULT_BYPASS: &str =
+pub static DEFAULT_BYPASS: &str =
     "127.0.0.1,192.168.0.0/16,10.0.0.0/8,172.16.0.0/12,localhost,*.local,*.crashlytics.com,<local>";
 
 impl Sysopt {
@@ -40,6 +82,9 @@ impl Sysopt {
             old_sysproxy: Arc::new(Mutex::new(None)),
             auto_launch: Arc::new(Mutex::new(None)),
             guard_state: Arc::new(TokioMutex::new(false)),
+            kill_switch: Arc::new(Mutex::new(false)),
+            cur_autoproxy: Arc::new(Mutex::new(None)),
+            portal_paused: Arc::new(Mutex::new(false)),
         })
     }
 
@@ -59,9 +104,10 @@ impl Sysopt {
             )
         };
 
+        let host = sysproxy_host();
         let current = Sysproxy {
             enable,
-            host: String::from("127.0.0.1"),
+            host,
             port,
             bypass: match bypass {
                 Some(bypass) => {
@@ -77,7 +123,13 @@ impl Sysopt {
 
         if enable {
             let old = Sysproxy::get_system_proxy().ok();
-            current.set_system_proxy()?;
+            if !self.apply_pac(&current) {
+                current.set_system_proxy()?;
+            }
+            log_err!(Self::write_marker(
+                &current,
+                self.cur_autoproxy.lock().clone()
+            ));
 
             *self.old_sysproxy.lock() = old;
             *self.cur_sysproxy.lock() = Some(current);
@@ -89,7 +141,10 @@ impl Sysopt {
     }
 
     /// update the system proxy
+    /// it also releases the kill switch and the pause for the captive portal
     pub fn update_sysproxy(&self) -> Result<()> {
+        *self.kill_switch.lock() = false;
+        *self.portal_paused.lock() = false;
         let mut cur_sysproxy = self.cur_sysproxy.lock();
         let old_sysproxy = self.old_sysproxy.lock();
 
@@ -126,15 +181,28 @@ impl Sysopt {
             .verge_mixed_port
             .unwrap_or(Config::clash().data().get_mixed_port());
         sysproxy.port = port;
+        sysproxy.host = sysproxy_host();
 
-        sysproxy.set_system_proxy()?;
+        if !self.apply_pac(&sysproxy) {
+            sysproxy.set_system_proxy()?;
+        }
+        match sysproxy.enable {
+            true => log_err!(Self::write_marker(
+                &sysproxy,
+                self.cur_autoproxy.lock().clone()
+            )),
+            false => Self::remove_marker(),
+        };
         *cur_sysproxy = Some(sysproxy);
 
         Ok(())
     }
 
     /// reset the sysproxy
+    /// the kill switch is bypassed, the original proxy is restored
     pub fn reset_sysproxy(&self) -> Result<()> {
+        *self.kill_switch.lock() = false;
+        self.disable_pac();
         let mut cur_sysproxy = self.cur_sysproxy.lock();
         let mut old_sysproxy = self.old_sysproxy.lock();
 
@@ -162,9 +230,239 @@ impl Sysopt {
             log::info!(target: "app", "reset proxy with no action");
         }
 
+        Self::remove_marker();
+        Ok(())
+    }
+
+    /// 内核意外退出时把系统代理指向拒绝连接的端口，流量失败而不是直连
+    /// return false if the kill switch or the system proxy is disabled
+    pub fn engage_kill_switch(&self) -> Result<bool> {
+        let (kill_switch, enable) = {
+            let verge = Config::verge();
+            let verge = verge.latest();
+            (
+                verge.kill_switch.unwrap_or(false),
+                verge.enable_system_proxy.unwrap_or(false),
+            )
+        };
+        let mut sysproxy = match self.cur_sysproxy.lock().clone() {
+            Some(sysproxy) if kill_switch && enable => sysproxy,
+            _ => return Ok(false),
+        };
+
+        // pac 模式下换成指向 `KILL_SWITCH_PORT` 的pac文件
+        let mut autoproxy = self.cur_autoproxy.lock();
+        if autoproxy.is_some() {
+            let url = Self::pac_url(KILL_SWITCH_PORT, &sysproxy.bypass);
+            autoproxy::set(&url)?;
+            *autoproxy = Some(url);
+        } else {
+            sysproxy.enable = true;
+            sysproxy.port = KILL_SWITCH_PORT;
+            sysproxy.set_system_proxy()?;
+        }
+        log_err!(Self::write_marker(&sysproxy, autoproxy.clone()));
+        drop(autoproxy);
+        *self.kill_switch.lock() = true;
+        log::warn!(target: "app", "the kill switch is engaged");
+        Ok(true)
+    }
+
+    /// restore the normal system proxy, or disable it
+    pub fn release_kill_switch(&self) -> Result<()> {
+        if !*self.kill_switch.lock() {
+            return Ok(());
+        }
+        log::info!(target: "app", "the kill switch is released");
+        self.update_sysproxy()
+    }
+
+    pub fn is_kill_switch_engaged(&self) -> bool {
+        *self.kill_switch.lock()
+    }
+
+    /// 暂时关闭系统代理，让认证页面能直连打开，不修改配置
+    /// 不恢复原来的代理，原来的代理同样打不开认证页面
+    pub fn pause_for_portal(&self) -> Result<()> {
+        *self.kill_switch.lock() = false;
+        *self.portal_paused.lock() = true;
+        self.disable_pac();
+
+        let mut sysproxy = match self.cur_sysproxy.lock().clone() {
+            Some(sysproxy) => sysproxy,
+            None => Sysproxy::get_system_proxy()?,
+        };
+        sysproxy.enable = false;
+        sysproxy.set_system_proxy()?;
+        Self::remove_marker();
+
+        log::info!(target: "app", "the system proxy is paused for the captive portal");
+        Ok(())
+    }
+
+    /// set the system proxy again if it is still paused
+    pub fn resume_after_portal(&self) -> Result<()> {
+        if !self.is_portal_paused() {
+            return Ok(());
+        }
+        log::info!(target: "app", "the system proxy is resumed after the captive portal");
+        self.update_sysproxy()
+    }
+
+    pub fn is_portal_paused(&self) -> bool {
+        *self.portal_paused.lock()
+    }
+
+    /// the pac file served by the embed server
+    /// it points to `KILL_SWITCH_PORT` while the kill switch is engaged
+    pub fn pac_content(&self) -> String {
+        let port = match self.is_kill_switch_engaged() {
+            true => KILL_SWITCH_PORT,
+            false => Config::verge()
+                .latest()
+                .verge_mixed_port
+                .unwrap_or(Config::clash().data().get_mixed_port()),
+        };
+        let bypass = { Config::verge().latest().system_proxy_bypass.clone() };
+        let bypass = bypass
+            .filter(|bypass| !bypass.is_empty())
+            .unwrap_or(DEFAULT_BYPASS.into());
+        pac::generate(resolve::loopback_url_host(), port, &bypass)
+    }
+
+    fn pac_url(port: u16, bypass: &str) -> String {
+        let pac = pac::generate(resolve::loopback_url_host(), port, bypass);
+        pac::pac_url(IVerge::get_singleton_port(), &pac)
+    }
+
+    /// 在 `pac` 模式下关闭固定的代理，改为设置pac地址
+    /// return false to set the fixed proxy, also if the os does not support pac
+    fn apply_pac(&self, sysproxy: &Sysproxy) -> bool {
+        let pac_mode = { Config::verge().latest().system_proxy_mode.as_deref() == Some("pac") };
+        if !sysproxy.enable || !pac_mode {
+            self.disable_pac();
+            return false;
+        }
+
+        let url = Self::pac_url(sysproxy.port, &sysproxy.bypass);
+        let mut fixed = sysproxy.clone();
+        fixed.enable = false;
+        let result = fixed
+            .set_system_proxy()
+            .map_err(anyhow::Error::from)
+            .and_then(|_| autoproxy::set(&url));
+
+        match result {
+            Ok(_) => {
+                *self.cur_autoproxy.lock() = Some(url);
+                true
+            }
+            Err(err) => {
+                log::error!(target: "app", "failed to set the pac url, {err}");
+                self.disable_pac();
+                handle::Handle::notice_message(
+                    "set_config::warn",
+                    format!("PAC is not supported, use the fixed proxy instead, {err}"),
+                );
+                false
+            }
+        }
+    }
+
+    fn disable_pac(&self) {
+        if self.cur_autoproxy.lock().take().is_some() {
+            log_err!(autoproxy::disable());
+        }
+    }
+
+    /// disable the stale system proxy left by the crashed app
+    /// only if it still points to the host and port recorded in the marker
+    /// should be called before `init_sysproxy`, which sets it again if enabled
+    pub fn recover_sysproxy(&self) -> Result<()> {
+        let path = dirs::sysproxy_marker_path()?;
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let marker = help::read_yaml::<SysproxyMarker>(&path);
+        let _ = fs::remove_file(&path);
+        let marker = marker?;
+
+        if let Some(url) = marker.pac_url.as_ref() {
+            if autoproxy::current().ok().flatten().as_ref() == Some(url) {
+                log::warn!(target: "app", "reset the stale pac url {url}");
+                autoproxy::disable()?;
+            }
+        }
+
+        let mut current = Sysproxy::get_system_proxy()?;
+        if current.enable && current.host == marker.host && current.port == marker.port {
+            log::warn!(
+                target: "app",
+                "reset the stale system proxy {}:{} set at {}",
+                marker.host,
+                marker.port,
+                marker.timestamp
+            );
+            current.enable = false;
+            current.set_system_proxy()?;
+        } else {
+            log::info!(target: "app", "the system proxy is changed since the app crashed");
+        }
+
         Ok(())
     }
 
+    /// detect the system proxy which is not set by the app
+    /// use the original one if the app has replaced it
+    pub fn detect_existing_proxy(&self) -> Result<Option<ExistingProxy>> {
+        let proxy = match self.cur_sysproxy.lock().is_some() {
+            true => self.old_sysproxy.lock().clone(),
+            false => Some(Sysproxy::get_system_proxy()?),
+        };
+        let proxy = match proxy {
+            Some(proxy) if proxy.enable => proxy,
+            _ => return Ok(None),
+        };
+
+        let own_port = Config::verge()
+            .latest()
+            .verge_mixed_port
+            .unwrap_or(Config::clash().data().get_mixed_port());
+        if is_own_proxy(&proxy.host, proxy.port, own_port) {
+            return Ok(None);
+        }
+
+        let first_run = {
+            let profiles = Config::profiles();
+            let profiles = profiles.latest();
+            profiles.get_items().map_or(true, |items| items.is_empty())
+        };
+
+        Ok(Some(ExistingProxy {
+            host: proxy.host,
+            port: proxy.port,
+            bypass: proxy.bypass,
+            first_run,
+        }))
+    }
+
+    fn write_marker(sysproxy: &Sysproxy, pac_url: Option<String>) -> Result<()> {
+        let marker = SysproxyMarker {
+            host: sysproxy.host.clone(),
+            port: sysproxy.port,
+            timestamp: chrono::Local::now().timestamp(),
+            pac_url,
+        };
+        help::save_yaml(&dirs::sysproxy_marker_path()?, &marker, None)
+    }
+
+    fn remove_marker() {
+        if let Ok(path) = dirs::sysproxy_marker_path() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
     /// init the auto launch
     pub fn init_launch(&self) -> Result<()> {
         let app_exe = current_exe()?;
@@ -215,9 +513,14 @@ impl Sysopt {
             }
         };
 
+        // 开机启动时附带的参数，如 --silent
+        let args = { Config::verge().latest().auto_launch_args.clone() };
+        let args = args.unwrap_or_default();
+
         let auto = AutoLaunchBuilder::new()
             .set_app_name(app_name)
             .set_app_path(&app_path)
+            .set_args(&args)
             .build()?;
 
         *self.auto_launch.lock() = Some(auto);
@@ -237,6 +540,15 @@ impl Sysopt {
         let enable = enable.unwrap_or(false);
         let auto_launch = auto_launch.as_ref().unwrap();
 
+        // macOS 13+ 使用系统的登录项，并移除旧的脚本方式
+        #[cfg(target_os = "macos")]
+        if login_item::is_supported() {
+            if auto_launch.is_enabled().unwrap_or(false) {
+                log_err!(auto_launch.disable());
+            }
+            return login_item::set_enabled(enable);
+        }
+
         match enable {
             true => auto_launch.enable()?,
             false => log_err!(auto_launch.disable()), // 忽略关闭的错误
@@ -245,6 +557,53 @@ impl Sysopt {
         Ok(())
     }
 
+    /// rebuild the launch entry with the new args and write it to the os
+    pub fn reload_launch(&self) -> Result<()> {
+        self.init_launch()?;
+        self.update_launch()
+    }
+
+    /// the actual autostart state of the os rather than the stored flag
+    pub fn is_autostart_enabled(&self) -> Result<bool> {
+        #[cfg(target_os = "macos")]
+        if let Some(enabled) = login_item::status() {
+            return Ok(enabled);
+        }
+
+        match self.auto_launch.lock().as_ref() {
+            Some(auto_launch) => Ok(auto_launch.is_enabled()?),
+            None => bail!("the auto launch is not initialized"),
+        }
+    }
+
+    /// sync the stored flag with the os state on startup
+    /// the user may change the login items outside the app
+    pub fn reconcile_launch(&self) -> Result<()> {
+        // migrate the login item created by the script
+        #[cfg(target_os = "macos")]
+        if login_item::is_supported() {
+            let auto_launch = self.auto_launch.lock();
+            if let Some(auto_launch) = auto_launch.as_ref() {
+                if auto_launch.is_enabled().unwrap_or(false) {
+                    log_err!(auto_launch.disable());
+                    login_item::set_enabled(true)?;
+                }
+            }
+        }
+
+        let enabled = self.is_autostart_enabled()?;
+        let stored = { Config::verge().latest().enable_auto_launch };
+        if stored.unwrap_or(false) != enabled {
+            log::info!(target: "app", "sync the auto launch flag with the os state \"{enabled}\"");
+            Config::verge().data().patch_config(IVerge {
+                enable_auto_launch: Some(enabled),
+                ..IVerge::default()
+            });
+            Config::verge().data().save_file()?;
+        }
+        Ok(())
+    }
+
     /// launch a system proxy guard
     /// read config from file directly
     pub fn guard_proxy(&self) {
@@ -286,8 +645,20 @@ impl Sysopt {
                 // update duration
                 wait_secs = guard_duration;
 
+                // 不能覆盖 kill switch 的代理，也不能在认证期间恢复代理
+                if Sysopt::global().is_kill_switch_engaged() || Sysopt::global().is_portal_paused()
+                {
+                    continue;
+                }
+
                 log::debug!(target: "app", "try to guard the system proxy");
 
+                let autoproxy = Sysopt::global().cur_autoproxy.lock().clone();
+                if let Some(url) = autoproxy {
+                    log_err!(autoproxy::set(&url));
+                    continue;
+                }
+
                 let port = {
                     Config::verge()
                         .latest()
@@ -295,9 +666,10 @@ impl Sysopt {
                         .unwrap_or(Config::clash().data().get_mixed_port())
                 };
 
+                let host = sysproxy_host();
                 let sysproxy = Sysproxy {
                     enable: true,
-                    host: "127.0.0.1".into(),
+                    host,
                     port,
                     bypass: match bypass {
                         Some(bypass) => {
@@ -320,3 +692,257 @@ impl Sysopt {
         });
     }
 }
+
+/// the proxy auto-config url of the os
+/// `sysproxy` only supports the fixed proxy
+#[cfg(target_os = "windows")]
+mod autoproxy {
+    use anyhow::Result;
+    use winreg::enums::*;
+    use winreg::RegKey;
+
+    const INTERNET_SETTINGS: &str =
+        "Software\\Microsoft\\Windows\\CurrentVersion\\Internet Settings";
+
+    pub fn set(url: &str) -> Result<()> {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let (settings, _) = hkcu.create_subkey(INTERNET_SETTINGS)?;
+        settings.set_value("AutoConfigURL", &url)?;
+        refresh();
+        Ok(())
+    }
+
+    pub fn disable() -> Result<()> {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let settings = hkcu.open_subkey_with_flags(INTERNET_SETTINGS, KEY_SET_VALUE)?;
+        match settings.delete_value("AutoConfigURL") {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => {
+                refresh();
+                Ok(())
+            }
+        }
+    }
+
+    /// 通知系统重新读取代理设置，不然要等浏览器重启才生效
+    fn refresh() {
+        use std::ptr::null_mut;
+        use winapi::um::wininet::{
+            InternetSetOptionW, INTERNET_OPTION_REFRESH, INTERNET_OPTION_SETTINGS_CHANGED,
+        };
+
+        unsafe {
+            InternetSetOptionW(null_mut(), INTERNET_OPTION_SETTINGS_CHANGED, null_mut(), 0);
+            InternetSetOptionW(null_mut(), INTERNET_OPTION_REFRESH, null_mut(), 0);
+        }
+    }
+
+    pub fn current() -> Result<Option<String>> {
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let settings = hkcu.open_subkey(INTERNET_SETTINGS)?;
+        Ok(settings.get_value::<String, _>("AutoConfigURL").ok())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod autoproxy {
+    use anyhow::{bail, Result};
+    use std::process::Command;
+
+    fn networksetup(args: &[&str]) -> Result<String> {
+        let output = Command::new("networksetup").args(args).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("failed to run networksetup, {}", stderr.trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// 第一行是说明，`*` 开头的是禁用的服务
+    fn services() -> Result<Vec<String>> {
+        let output = networksetup(&["-listallnetworkservices"])?;
+        Ok(output
+            .lines()
+            .skip(1)
+            .filter(|line| !line.is_empty() && !line.starts_with('*'))
+            .map(|line| line.to_string())
+            .collect())
+    }
+
+    pub fn set(url: &str) -> Result<()> {
+        for service in services()? {
+            networksetup(&["-setautoproxyurl", &service, url])?;
+            networksetup(&["-setautoproxystate", &service, "on"])?;
+        }
+        Ok(())
+    }
+
+    pub fn disable() -> Result<()> {
+        for service in services()? {
+            networksetup(&["-setautoproxystate", &service, "off"])?;
+        }
+        Ok(())
+    }
+
+    pub fn current() -> Result<Option<String>> {
+        for service in services()? {
+            let output = networksetup(&["-getautoproxyurl", &service])?;
+            let value = |key: &str| {
+                output
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key))
+                    .map(|v| v.trim().to_string())
+            };
+            if value("Enabled:").as_deref() == Some("Yes") {
+                return Ok(value("URL:"));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// only the desktops using the gnome settings, others fall back to the fixed proxy
+#[cfg(target_os = "linux")]
+mod autoproxy {
+    use anyhow::{bail, Result};
+    use std::process::Command;
+
+    const SCHEMA: &str = "org.gnome.system.proxy";
+
+    fn gsettings(args: &[&str]) -> Result<String> {
+        let output = Command::new("gsettings").args(args).output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("failed to run gsettings, {}", stderr.trim());
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout.trim().trim_matches('\'').to_string())
+    }
+
+    pub fn set(url: &str) -> Result<()> {
+        gsettings(&["set", SCHEMA, "autoconfig-url", url])?;
+        gsettings(&["set", SCHEMA, "mode", "auto"])?;
+        Ok(())
+    }
+
+    pub fn disable() -> Result<()> {
+        if gsettings(&["get", SCHEMA, "mode"])? == "auto" {
+            gsettings(&["set", SCHEMA, "mode", "none"])?;
+        }
+        Ok(())
+    }
+
+    pub fn current() -> Result<Option<String>> {
+        match gsettings(&["get", SCHEMA, "mode"])? == "auto" {
+            true => Ok(Some(gsettings(&["get", SCHEMA, "autoconfig-url"])?)),
+            false => Ok(None),
+        }
+    }
+}
+
+/// the login item of macOS 13+ by `SMAppService`
+/// the older systems fall back to the script of `auto_launch`
+#[cfg(target_os = "macos")]
+mod login_item {
+    use anyhow::{anyhow, bail, Result};
+    use objc::runtime::{Class, Object, BOOL, YES};
+    use objc::{msg_send, sel, sel_impl};
+    use std::{ffi::CStr, os::raw::c_char, ptr};
+
+    #[link(name = "ServiceManagement", kind = "framework")]
+    extern "C" {}
+
+    /// `SMAppServiceStatusEnabled`
+    const STATUS_ENABLED: isize = 1;
+    /// `SMAppServiceStatusRequiresApproval`
+    const STATUS_REQUIRES_APPROVAL: isize = 2;
+
+    /// `SMAppService.mainAppService`, none before macOS 13
+    fn main_app_service() -> Option<*mut Object> {
+        let class = Class::get("SMAppService")?;
+        let service: *mut Object = unsafe { msg_send![class, mainAppService] };
+        (!service.is_null()).then_some(service)
+    }
+
+    pub fn is_supported() -> bool {
+        main_app_service().is_some()
+    }
+
+    pub fn status() -> Option<bool> {
+        let service = main_app_service()?;
+        let status: isize = unsafe { msg_send![service, status] };
+        Some(status == STATUS_ENABLED)
+    }
+
+    pub fn set_enabled(enable: bool) -> Result<()> {
+        let service = main_app_service().ok_or(anyhow!("SMAppService is not supported"))?;
+        let status: isize = unsafe { msg_send![service, status] };
+        let registered = status == STATUS_ENABLED || status == STATUS_REQUIRES_APPROVAL;
+        if registered == enable {
+            return Ok(());
+        }
+
+        let mut error: *mut Object = ptr::null_mut();
+        let ok: BOOL = unsafe {
+            match enable {
+                true => msg_send![service, registerAndReturnError: &mut error],
+                false => msg_send![service, unregisterAndReturnError: &mut error],
+            }
+        };
+        if ok != YES {
+            bail!("failed to update the login item, {}", describe_error(error));
+        }
+
+        // the user should allow it in the system settings
+        let status: isize = unsafe { msg_send![service, status] };
+        if enable && status == STATUS_REQUIRES_APPROVAL {
+            log::warn!(target: "app", "the login item requires the approval of the user");
+            if let Some(class) = Class::get("SMAppService") {
+                let _: () = unsafe { msg_send![class, openSystemSettingsLoginItems] };
+            }
+        }
+        Ok(())
+    }
+
+    /// `NSError.localizedDescription`
+    fn describe_error(error: *mut Object) -> String {
+        if error.is_null() {
+            return "unknown error".into();
+        }
+        unsafe {
+            let desc: *mut Object = msg_send![error, localizedDescription];
+            let chars: *const c_char = msg_send![desc, UTF8String];
+            match chars.is_null() {
+                true => "unknown error".into(),
+                false => CStr::from_ptr(chars).to_string_lossy().into_owned(),
+            }
+        }
+    }
+}
+
+/// the host of the system proxy, the ipv6 address is bracketed on windows
+fn sysproxy_host() -> String {
+    #[cfg(target_os = "windows")]
+    return resolve::loopback_url_host().into();
+    #[cfg(not(target_os = "windows"))]
+    return resolve::loopback_host().into();
+}
+
+/// the proxy points to the app itself, e.g. left by the last run
+fn is_own_proxy(host: &str, port: u16, own_port: u16) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let loopback = host == "localhost"
+        || host
+            .parse::<std::net::IpAddr>()
+            .map_or(false, |ip| ip.is_loopback());
+    loopback && port == own_port
+}
+
+#[test]
+fn test_is_own_proxy() {
+    assert!(is_own_proxy("127.0.0.1", 7897, 7897));
+    assert!(is_own_proxy("localhost", 7897, 7897));
+    assert!(is_own_proxy("[::1]", 7897, 7897));
+    assert!(!is_own_proxy("127.0.0.1", 7890, 7897));
+    assert!(!is_own_proxy("192.168.1.2", 7897, 7897));
+}
@@ -4,11 +4,21 @@ use anyhow::{Context, Result};
 use delay_timer::prelude::{DelayTimer, DelayTimerBuilder, TaskBuilder};
 use once_cell::sync::OnceCell;
 use parking_lot::Mutex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 type TaskID = u64;
 
+/// the profile tasks start from 1
+const HEALTH_CHECK_TASK_ID: TaskID = 0;
+
+/// 检查当前订阅是否可用的间隔
+const HEALTH_CHECK_MINUTES: u64 = 5;
+
+/// the profile tasks increase from 1, use the other end
+const CAPTIVE_PORTAL_TASK_ID: TaskID = TaskID::MAX;
+
 pub struct Timer {
     /// cron manager
     delay_timer: Arc<Mutex<DelayTimer>>,
@@ -18,6 +28,12 @@ pub struct Timer {
 
     /// increment id
     timer_count: Arc<Mutex<TaskID>>,
+
+    /// the tasks keep scheduled but skip the updates when paused
+    paused: AtomicBool,
+
+    /// the tasks skipped while paused, run them on resume
+    missed: Arc<Mutex<HashSet<String>>>,
 }
 
 impl Timer {
@@ -28,12 +44,18 @@ impl Timer {
             delay_timer: Arc::new(Mutex::new(DelayTimerBuilder::default().build())),
             timer_map: Arc::new(Mutex::new(HashMap::new())),
             timer_count: Arc::new(Mutex::new(1)),
+            paused: AtomicBool::new(false),
+            missed: Arc::new(Mutex::new(HashSet::new())),
         })
     }
 
     /// restore timer
     pub fn init(&self) -> Result<()> {
+        let paused = { Config::verge().latest().timer_paused.unwrap_or(false) };
+        self.paused.store(paused, Ordering::SeqCst);
         self.refresh()?;
+        crate::log_err!(self.refresh_health_check());
+        crate::log_err!(self.refresh_captive_portal());
 
         let cur_timestamp = chrono::Local::now().timestamp();
 
@@ -94,6 +116,104 @@ impl Timer {
         Ok(())
     }
 
+    /// 暂停自动更新，定时任务照常计时
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        log::info!(target: "app", "the timer tasks are paused");
+    }
+
+    /// 恢复自动更新，暂停期间错过的任务马上执行一次
+    pub fn resume(&self) -> Result<()> {
+        self.paused.store(false, Ordering::SeqCst);
+        log::info!(target: "app", "the timer tasks are resumed");
+
+        let missed = std::mem::take(&mut *self.missed.lock());
+        let timer_map = self.timer_map.lock();
+        let delay_timer = self.delay_timer.lock();
+        for uid in missed.iter() {
+            if let Some((task_id, _)) = timer_map.get(uid) {
+                crate::log_err!(delay_timer.advance_task(*task_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// 手动更新之后重新计时，下次从现在开始算
+    pub fn reset_task(&self, uid: &str) -> Result<()> {
+        let timer_map = self.timer_map.lock();
+        let mut delay_timer = self.delay_timer.lock();
+        if let Some((tid, val)) = timer_map.get(uid) {
+            crate::log_err!(delay_timer.remove_task(*tid));
+            self.add_task(&mut delay_timer, uid.to_string(), *tid, *val)?;
+        }
+        Ok(())
+    }
+
+    /// 设置了备用订阅时，定时检查当前订阅的节点是否都不可用
+    pub fn refresh_health_check(&self) -> Result<()> {
+        let enable = {
+            let verge = Config::verge();
+            let verge = verge.latest();
+            verge
+                .fallback_profile_uid
+                .as_ref()
+                .is_some_and(|uid| !uid.is_empty())
+        };
+
+        let mut delay_timer = self.delay_timer.lock();
+        let _ = delay_timer.remove_task(HEALTH_CHECK_TASK_ID);
+        if !enable {
+            return Ok(());
+        }
+
+        let task = TaskBuilder::default()
+            .set_task_id(HEALTH_CHECK_TASK_ID)
+            .set_maximum_parallel_runnable_num(1)
+            .set_frequency_repeated_by_minutes(HEALTH_CHECK_MINUTES)
+            .spawn_async_routine(|| async {
+                crate::log_err!(feat::check_profile_health().await);
+            })
+            .context("failed to create the health check task")?;
+
+        delay_timer
+            .add_task(task)
+            .context("failed to add the health check task")?;
+        Ok(())
+    }
+
+    /// 开启后每分钟检测一次网络是否被认证页面拦截
+    pub fn refresh_captive_portal(&self) -> Result<()> {
+        let enable = {
+            let verge = Config::verge();
+            let verge = verge.latest();
+            verge.enable_captive_portal_detect.unwrap_or(false)
+        };
+
+        let mut delay_timer = self.delay_timer.lock();
+        let _ = delay_timer.remove_task(CAPTIVE_PORTAL_TASK_ID);
+        if !enable {
+            return Ok(());
+        }
+
+        let task = TaskBuilder::default()
+            .set_task_id(CAPTIVE_PORTAL_TASK_ID)
+            .set_maximum_parallel_runnable_num(1)
+            .set_frequency_repeated_by_minutes(1)
+            .spawn_async_routine(|| async {
+                crate::log_err!(feat::check_captive_portal().await);
+            })
+            .context("failed to create the captive portal task")?;
+
+        delay_timer
+            .add_task(task)
+            .context("failed to add the captive portal task")?;
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
     /// generate a uid -> update_interval map
     fn gen_map(&self) -> HashMap<String, u64> {
         let mut new_map = HashMap::new();
@@ -171,8 +291,25 @@ impl Timer {
 
     /// the task runner
     async fn async_task(uid: String) {
+        let timer = Self::global();
+        if timer.is_paused() {
+            log::info!(target: "app", "skip the timer task `{uid}`, paused");
+            timer.missed.lock().insert(uid);
+            return;
+        }
+
         log::info!(target: "app", "running timer task `{uid}`");
-        crate::log_err!(feat::update_profile(uid, None).await);
+        if let Err(err) = feat::update_profile(uid.clone(), None).await {
+            // 超时等网络问题等下次定时再试
+            let retryable = err
+                .downcast_ref::<reqwest::Error>()
+                .map_or(false, |e| e.is_timeout() || e.is_connect());
+            if retryable {
+                log::warn!(target: "app", "failed to update `{uid}`, retry next time: {err}");
+            } else {
+                log::error!(target: "app", "failed to update `{uid}`: {err}");
+            }
+        }
     }
 }
 
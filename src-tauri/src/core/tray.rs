@@ -1,3 +1,5 @@
+use super::monitor::{self, MemoryState, Monitor};
+use super::{CoreManager, FlushResult};
 use crate::{
     cmds,
     config::Config,
@@ -10,12 +12,23 @@ use tauri::{
     SystemTraySubmenu,
 };
 
+/// the menu id of the profile item is `profile_{uid}`
+const PROFILE_PREFIX: &str = "profile_";
+
+/// the menu id of the quick action is `quick_{action}`
+const QUICK_PREFIX: &str = "quick_";
+
+/// the max chars of the profile name in the tray
+const PROFILE_LABEL_LEN: usize = 32;
+
 pub struct Tray {}
 
 impl Tray {
     pub fn tray_menu(app_handle: &AppHandle) -> SystemTrayMenu {
         let zh = { Config::verge().latest().language == Some("zh".into()) };
 
+        let quick_actions = { Config::verge().latest().tray_quick_actions.unwrap_or(true) };
+
         let version = app_handle.package_info().version.to_string();
 
         macro_rules! t {
@@ -28,67 +41,134 @@ impl Tray {
             };
         }
 
-        SystemTrayMenu::new()
+        let menu = SystemTrayMenu::new()
             .add_item(CustomMenuItem::new(
                 "open_window",
                 t!("Dashboard", "打开面板"),
             ))
-            .add_native_item(SystemTrayMenuItem::Separator)
-            .add_item(CustomMenuItem::new(
-                "rule_mode",
-                t!("Rule Mode", "规则模式"),
-            ))
-            .add_item(CustomMenuItem::new(
-                "global_mode",
-                t!("Global Mode", "全局模式"),
-            ))
-            .add_item(CustomMenuItem::new(
-                "direct_mode",
-                t!("Direct Mode", "直连模式"),
-            ))
-            .add_native_item(SystemTrayMenuItem::Separator)
-            .add_item(CustomMenuItem::new(
-                "system_proxy",
-                t!("System Proxy", "系统代理"),
-            ))
-            .add_item(CustomMenuItem::new("tun_mode", t!("TUN Mode", "Tun 模式")))
-            .add_item(CustomMenuItem::new(
-                "copy_env",
-                t!("Copy Env", "复制环境变量"),
-            ))
-            .add_submenu(SystemTraySubmenu::new(
-                t!("Open Dir", "打开目录"),
-                SystemTrayMenu::new()
-                    .add_item(CustomMenuItem::new(
-                        "open_app_dir",
-                        t!("App Dir", "应用目录"),
-                    ))
-                    .add_item(CustomMenuItem::new(
-                        "open_core_dir",
-                        t!("Core Dir", "内核目录"),
-                    ))
-                    .add_item(CustomMenuItem::new(
-                        "open_logs_dir",
-                        t!("Logs Dir", "日志目录"),
-                    )),
-            ))
-            .add_submenu(SystemTraySubmenu::new(
-                t!("More", "更多"),
-                SystemTrayMenu::new()
-                    .add_item(CustomMenuItem::new(
-                        "restart_clash",
-                        t!("Restart Clash", "重启 Clash"),
-                    ))
-                    .add_item(CustomMenuItem::new(
-                        "restart_app",
-                        t!("Restart App", "重启应用"),
-                    ))
-                    .add_item(
-                        CustomMenuItem::new("app_version", format!("Version {version}")).disabled(),
-                    ),
-            ))
-            .add_native_item(SystemTrayMenuItem::Separator)
-            .add_item(CustomMenuItem::new("quit", t!("Quit", "退出")).accelerator("CmdOrControl+Q"))
+            .add_native_item(SystemTrayMenuItem::Separator);
+        let menu = match Tray::profiles_menu() {
+            Some(profiles) => menu
+                .add_submenu(SystemTraySubmenu::new(t!("Profiles", "订阅"), profiles))
+                .add_native_item(SystemTrayMenuItem::Separator),
+            None => menu,
+        };
+
+        // 菜单的id不能重复，快捷操作加上前缀
+        let menu = match quick_actions {
+            true => menu
+                .add_submenu(SystemTraySubmenu::new(
+                    t!("Quick Actions", "快捷操作"),
+                    SystemTrayMenu::new()
+                        .add_item(CustomMenuItem::new(
+                            "quick_copy_env",
+                            t!("Copy Proxy Command", "复制代理命令"),
+                        ))
+                        .add_item(CustomMenuItem::new(
+                            "quick_open_config_dir",
+                            t!("Open Config Dir", "打开配置目录"),
+                        ))
+                        .add_item(CustomMenuItem::new(
+                            "quick_open_logs_dir",
+                            t!("Open Logs Dir", "打开日志目录"),
+                        ))
+                        .add_item(CustomMenuItem::new(
+                            "quick_flush_fakeip",
+                            t!("Flush Fake-IP", "清空 Fake-IP"),
+                        ))
+                        .add_item(CustomMenuItem::new(
+                            "quick_restart_core",
+                            t!("Restart Core", "重启内核"),
+                        )),
+                ))
+                .add_native_item(SystemTrayMenuItem::Separator),
+            false => menu,
+        };
+
+        menu.add_item(CustomMenuItem::new(
+            "rule_mode",
+            t!("Rule Mode", "规则模式"),
+        ))
+        .add_item(CustomMenuItem::new(
+            "global_mode",
+            t!("Global Mode", "全局模式"),
+        ))
+        .add_item(CustomMenuItem::new(
+            "direct_mode",
+            t!("Direct Mode", "直连模式"),
+        ))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(
+            "system_proxy",
+            t!("System Proxy", "系统代理"),
+        ))
+        .add_item(CustomMenuItem::new("tun_mode", t!("TUN Mode", "Tun 模式")))
+        .add_item(CustomMenuItem::new(
+            "copy_env",
+            t!("Copy Env", "复制环境变量"),
+        ))
+        .add_submenu(SystemTraySubmenu::new(
+            t!("Open Dir", "打开目录"),
+            SystemTrayMenu::new()
+                .add_item(CustomMenuItem::new(
+                    "open_app_dir",
+                    t!("App Dir", "应用目录"),
+                ))
+                .add_item(CustomMenuItem::new(
+                    "open_core_dir",
+                    t!("Core Dir", "内核目录"),
+                ))
+                .add_item(CustomMenuItem::new(
+                    "open_logs_dir",
+                    t!("Logs Dir", "日志目录"),
+                )),
+        ))
+        .add_submenu(SystemTraySubmenu::new(
+            t!("More", "更多"),
+            SystemTrayMenu::new()
+                .add_item(CustomMenuItem::new(
+                    "restart_clash",
+                    t!("Restart Clash", "重启 Clash"),
+                ))
+                .add_item(CustomMenuItem::new(
+                    "restart_app",
+                    t!("Restart App", "重启应用"),
+                ))
+                .add_item(
+                    CustomMenuItem::new("app_version", format!("Version {version}")).disabled(),
+                ),
+        ))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new("quit", t!("Quit", "退出")).accelerator("CmdOrControl+Q"))
+    }
+
+    /// the recent profiles, rebuild the whole menu when the profiles change
+    fn profiles_menu() -> Option<SystemTrayMenu> {
+        let limit = { Config::verge().latest().tray_profiles_limit };
+        let limit = limit.unwrap_or(5);
+        if limit == 0 {
+            return None;
+        }
+
+        let profiles = Config::profiles();
+        let profiles = profiles.latest();
+        let current = profiles.get_current();
+        let items = profiles.recent_items(limit);
+        if items.is_empty() {
+            return None;
+        }
+
+        let menu = items.into_iter().fold(SystemTrayMenu::new(), |menu, item| {
+            let uid = item.uid.clone().unwrap_or_default();
+            let name = item.name.clone().unwrap_or(uid.clone());
+            let label = profile_label(&name, item.group.as_deref());
+            let mut menu_item = CustomMenuItem::new(format!("{PROFILE_PREFIX}{uid}"), label);
+            if current.as_ref() == Some(&uid) {
+                menu_item = menu_item.selected();
+            }
+            menu.add_item(menu_item)
+        });
+        Some(menu)
     }
 
     pub fn update_systray(app_handle: &AppHandle) -> Result<()> {
@@ -99,6 +179,20 @@ impl Tray {
         Ok(())
     }
 
+    /// the embedded icon with the badge of the mode, none for the rule mode
+    fn mode_icon(mode: &str, tun_mode: bool, system_proxy: bool) -> Option<Vec<u8>> {
+        let icon: &[u8] = match (mode, tun_mode, system_proxy) {
+            ("global", true, _) => include_bytes!("../../icons/tray-icon-tun-global.ico"),
+            ("direct", true, _) => include_bytes!("../../icons/tray-icon-tun-direct.ico"),
+            ("global", false, true) => include_bytes!("../../icons/tray-icon-sys-global.ico"),
+            ("direct", false, true) => include_bytes!("../../icons/tray-icon-sys-direct.ico"),
+            ("global", false, false) => include_bytes!("../../icons/tray-icon-global.ico"),
+            ("direct", false, false) => include_bytes!("../../icons/tray-icon-direct.ico"),
+            _ => return None,
+        };
+        Some(icon.to_vec())
+    }
+
     pub fn update_part(app_handle: &AppHandle) -> Result<()> {
         let zh = { Config::verge().latest().language == Some("zh".into()) };
 
@@ -177,6 +271,7 @@ impl Tray {
         let common_tray_icon = verge.common_tray_icon.as_ref().unwrap_or(&false);
         let sysproxy_tray_icon = verge.sysproxy_tray_icon.as_ref().unwrap_or(&false);
         let tun_tray_icon = verge.tun_tray_icon.as_ref().unwrap_or(&false);
+        let tray_mode_icon = verge.tray_mode_icon.unwrap_or(false);
         #[cfg(target_os = "macos")]
         match tray_icon.as_str() {
             "monochrome" => {
@@ -252,6 +347,47 @@ impl Tray {
             indication_icon = icon
         }
 
+        // 全局/直连模式加上角标，自定义的图标和macOS的单色图标不加
+        #[cfg(target_os = "macos")]
+        let badge_supported = tray_icon == "colorful";
+        #[cfg(not(target_os = "macos"))]
+        let badge_supported = true;
+        let custom_icon = match (*tun_mode, *system_proxy) {
+            (true, _) => *tun_tray_icon,
+            (false, true) => *sysproxy_tray_icon,
+            (false, false) => *common_tray_icon,
+        };
+        if tray_mode_icon && badge_supported && !custom_icon {
+            if let Some(icon) = Self::mode_icon(&mode, *tun_mode, *system_proxy) {
+                indication_icon = icon;
+            }
+        }
+
+        // 内核没有运行时显示灰色的图标
+        if !Monitor::global().core_running() {
+            #[cfg(target_os = "macos")]
+            let mut icon = match tray_icon.as_str() {
+                "colorful" => include_bytes!("../../icons/tray-icon-stop.ico").to_vec(),
+                _ => include_bytes!("../../icons/tray-icon-stop-mono.ico").to_vec(),
+            };
+            #[cfg(not(target_os = "macos"))]
+            let mut icon = include_bytes!("../../icons/tray-icon-stop.ico").to_vec();
+            let icon_dir_path = dirs::app_home_dir()?.join("icons");
+            let png_path = icon_dir_path.join("stop.png");
+            let ico_path = icon_dir_path.join("stop.ico");
+            // 读不了就用内置的图标
+            if ico_path.exists() {
+                if let Ok(bytes) = std::fs::read(ico_path) {
+                    icon = bytes;
+                }
+            } else if png_path.exists() {
+                if let Ok(bytes) = std::fs::read(png_path) {
+                    icon = bytes;
+                }
+            }
+            indication_icon = icon
+        }
+
         let _ = tray.set_icon(tauri::Icon::Raw(indication_icon));
 
         let _ = tray.get_item("system_proxy").set_selected(*system_proxy);
@@ -295,19 +431,91 @@ impl Tray {
                 None => current_profile_name,
             };
         };
-        let _ = tray.set_tooltip(&format!(
-            "Clash Verge {version}\n{}: {}\n{}: {}\n{}: {}",
+        let mode_name = match mode.as_str() {
+            "rule" => t!("Rule", "规则"),
+            "global" => t!("Global", "全局"),
+            "direct" => t!("Direct", "直连"),
+            mode => mode,
+        };
+        let mut tooltip = format!(
+            "Clash Verge {version}\n{}: {}\n{}: {}\n{}: {}\n{}: {}",
+            t!("Mode", "模式"),
+            mode_name,
             t!("System Proxy", "系统代理"),
             switch_map[system_proxy],
             t!("TUN Mode", "Tun 模式"),
             switch_map[tun_mode],
             t!("Curent Profile", "当前订阅"),
             current_profile_name
-        ));
+        );
+        if let MemoryState::Ok { inuse, .. } = Monitor::global().memory() {
+            if verge.enable_memory_usage.unwrap_or(true) {
+                tooltip.push_str(&format!(
+                    "\n{}: {}",
+                    t!("Memory", "内存"),
+                    monitor::format_bytes(inuse)
+                ));
+            }
+        }
+        let _ = tray.set_tooltip(&tooltip);
 
         Ok(())
     }
 
+    /// run the quick action in the tray, notify when it is done
+    fn quick_action(app_handle: &AppHandle, action: &str) {
+        let zh = { Config::verge().latest().language == Some("zh".into()) };
+        let app_handle = app_handle.clone();
+        let action = action.to_string();
+
+        macro_rules! t {
+            ($en: expr, $zh: expr) => {
+                if zh {
+                    $zh
+                } else {
+                    $en
+                }
+            };
+        }
+
+        tauri::async_runtime::spawn(async move {
+            let result: Result<String> = match action.as_str() {
+                "copy_env" => feat::copy_clash_env(&app_handle)
+                    .map(|_| t!("The proxy command is copied", "代理命令已复制").into()),
+                "open_config_dir" => dirs::app_home_dir()
+                    .and_then(feat::open_dir)
+                    .map(|_| t!("The config dir is opened", "已打开配置目录").into()),
+                "open_logs_dir" => dirs::app_logs_dir()
+                    .and_then(feat::open_dir)
+                    .map(|_| t!("The logs dir is opened", "已打开日志目录").into()),
+                "flush_fakeip" => match CoreManager::global().flush_fakeip().await {
+                    Ok(FlushResult::Flushed) => {
+                        Ok(t!("The fake-ip cache is flushed", "Fake-IP 缓存已清空").into())
+                    }
+                    Ok(FlushResult::NotApplicable { reason }) => Ok(reason),
+                    Err(err) => Err(err),
+                },
+                // 结果由 restart_clash_core 通知前端
+                "restart_core" => {
+                    feat::restart_clash_core();
+                    Ok(t!("The core is restarting", "正在重启内核").into())
+                }
+                _ => return,
+            };
+            let body = match result {
+                Ok(message) => message,
+                Err(err) => {
+                    log::error!(target: "app", "failed to run the quick action {action}: {err}");
+                    format!("{}: {err}", t!("Failed", "失败"))
+                }
+            };
+            crate::log_err!(api::notification::Notification::new(dirs::APP_ID)
+                .title("Clash Verge")
+                .body(body)
+                .show());
+        });
+    }
+
     pub fn on_click(app_handle: &AppHandle) {
         let tray_event = { Config::verge().latest().tray_event.clone() };
         let tray_event = tray_event.unwrap_or("main_window".into());
@@ -333,13 +541,19 @@ impl Tray {
                 "open_window" => resolve::create_window(app_handle),
                 "system_proxy" => feat::toggle_system_proxy(),
                 "tun_mode" => feat::toggle_tun_mode(),
-                "copy_env" => feat::copy_clash_env(app_handle),
+                "copy_env" => crate::log_err!(feat::copy_clash_env(app_handle)),
                 "open_app_dir" => crate::log_err!(cmds::open_app_dir()),
                 "open_core_dir" => crate::log_err!(cmds::open_core_dir()),
                 "open_logs_dir" => crate::log_err!(cmds::open_logs_dir()),
                 "restart_clash" => feat::restart_clash_core(),
                 "restart_app" => api::process::restart(&app_handle.env()),
                 "quit" => cmds::exit_app(app_handle.clone()),
+                id if id.starts_with(QUICK_PREFIX) => {
+                    Tray::quick_action(app_handle, &id[QUICK_PREFIX.len()..])
+                }
+                id if id.starts_with(PROFILE_PREFIX) => {
+                    feat::switch_profile(id[PROFILE_PREFIX.len()..].into())
+                }
 
                 _ => {}
             },
@@ -347,3 +561,30 @@ impl Tray {
         }
     }
 }
+
+/// `group / name`, the long name is cut with `…`
+fn profile_label(name: &str, group: Option<&str>) -> String {
+    let label = match group.map(|g| g.trim()).filter(|g| !g.is_empty()) {
+        Some(group) => format!("{group} / {name}"),
+        None => name.to_string(),
+    };
+    if label.chars().count() <= PROFILE_LABEL_LEN {
+        return label;
+    }
+    let cut = label
+        .chars()
+        .take(PROFILE_LABEL_LEN - 1)
+        .collect::<String>();
+    format!("{cut}…")
+}
+
+#[test]
+fn test_profile_label() {
+    assert_eq!(profile_label("Home", None), "Home");
+    assert_eq!(profile_label("Home", Some(" ")), "Home");
+    assert_eq!(profile_label("Home", Some("work")), "work / Home");
+
+    let label = profile_label(&"订".repeat(40), None);
+    assert_eq!(label.chars().count(), PROFILE_LABEL_LEN);
+    assert!(label.ends_with('…'));
+}
@@ -0,0 +1,57 @@
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+
+/// 本机的连接不认证，系统代理带不上用户名密码
+const SKIP_AUTH_PREFIXES: [&str; 2] = ["127.0.0.1/8", "::1/128"];
+
+/// write the `authentication` only when lan is allowed
+/// `auth` is the plain `user:pass` list, turn off the lan when it can't be resolved
+pub fn use_lan_auth(mut config: Mapping, auth: Result<Vec<String>>) -> Mapping {
+    let allow_lan = config
+        .get("allow-lan")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    if !allow_lan {
+        return config;
+    }
+
+    let auth = match auth {
+        Ok(auth) if auth.is_empty() => return config,
+        Ok(auth) => auth,
+        Err(err) => {
+            log::error!(target: "app", "disable the lan access, {err}");
+            config.insert("allow-lan".into(), false.into());
+            return config;
+        }
+    };
+
+    config.insert("authentication".into(), auth.into());
+    if !config.contains_key("skip-auth-prefixes") {
+        config.insert(
+            "skip-auth-prefixes".into(),
+            SKIP_AUTH_PREFIXES.to_vec().into(),
+        );
+    }
+    config
+}
+
+#[test]
+fn test_lan_auth() {
+    let entries = || Ok(vec!["alice:pass".to_string()]);
+
+    let mut config = Mapping::new();
+    config.insert("allow-lan".into(), false.into());
+    assert_eq!(use_lan_auth(config.clone(), entries()), config);
+
+    config.insert("allow-lan".into(), true.into());
+    assert_eq!(use_lan_auth(config.clone(), Ok(vec![])), config);
+
+    let result = use_lan_auth(config.clone(), Err(anyhow::anyhow!("no password")));
+    assert_eq!(result.get("allow-lan"), Some(&Value::from(false)));
+    assert!(!result.contains_key("authentication"));
+
+    let result = use_lan_auth(config, entries());
+    let auth = result.get("authentication").unwrap().as_sequence().unwrap();
+    assert_eq!(auth, &vec![Value::from("alice:pass")]);
+    assert!(result.contains_key("skip-auth-prefixes"));
+}
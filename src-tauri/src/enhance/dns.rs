@@ -0,0 +1,57 @@
+use crate::config::IClashDNS;
+use serde_yaml::{Mapping, Value};
+
+/// merge the dns override into the `dns` of the config
+/// the override is skipped if the merged dns is not a valid dns config
+pub fn use_dns_override(mut config: Mapping, dns: Option<Mapping>) -> Mapping {
+    let dns = match dns {
+        Some(dns) if !dns.is_empty() => dns,
+        _ => return config,
+    };
+
+    let dns_key = Value::from("dns");
+    let mut dns_val = config
+        .get(&dns_key)
+        .and_then(|val| val.as_mapping().cloned())
+        .unwrap_or_default();
+    for (key, value) in dns.into_iter() {
+        dns_val.insert(key, value);
+    }
+
+    // 检查合并后的结果，override 可以只写部分字段
+    let checked = serde_yaml::from_value::<IClashDNS>(Value::Mapping(dns_val.clone()))
+        .map_err(anyhow::Error::from)
+        .and_then(|dns| dns.validate());
+    if let Err(err) = checked {
+        log::error!(target: "app", "invalid dns override, skipped: {err}");
+        return config;
+    }
+
+    config.insert(dns_key, dns_val.into());
+    config
+}
+
+#[test]
+fn test_dns_override() {
+    let config: Mapping =
+        serde_yaml::from_str("dns:\n  enable: true\n  nameserver: [1.1.1.1]\n  ipv6: true\n")
+            .unwrap();
+
+    let patch = serde_yaml::from_str("nameserver: [8.8.8.8]\nenhanced-mode: fake-ip\n").unwrap();
+    let result = use_dns_override(config.clone(), Some(patch));
+    let expected: Mapping = serde_yaml::from_str(
+        "dns:\n  enable: true\n  nameserver: [8.8.8.8]\n  ipv6: true\n  enhanced-mode: fake-ip\n",
+    )
+    .unwrap();
+    assert_eq!(result, expected);
+
+    // the malformed override is skipped
+    let patch = serde_yaml::from_str("nameserver: 8.8.8.8\n").unwrap();
+    assert_eq!(use_dns_override(config.clone(), Some(patch)), config);
+
+    // the override failed to pass `IClashDNS::validate` is skipped
+    let patch = serde_yaml::from_str("enhanced-mode: bogus\n").unwrap();
+    assert_eq!(use_dns_override(config.clone(), Some(patch)), config);
+    let patch = serde_yaml::from_str("nameserver: [\"udp://\"]\n").unwrap();
+    assert_eq!(use_dns_override(config.clone(), Some(patch)), config);
+}
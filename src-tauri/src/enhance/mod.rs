@@ -1,15 +1,24 @@
+mod auth;
 mod chain;
+mod dns;
 pub mod field;
 mod merge;
+mod rule_provider;
 mod script;
 mod tun;
 
+use self::auth::*;
 use self::chain::*;
+use self::dns::*;
 use self::field::*;
 use self::merge::*;
+use self::rule_provider::*;
 use self::script::*;
 use self::tun::*;
 use crate::config::Config;
+use crate::utils::lan_auth;
+use crate::utils::resolve;
+use anyhow::{anyhow, bail, Context, Result};
 use serde_yaml::Mapping;
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -19,6 +28,72 @@ type ResultLog = Vec<(String, String)>;
 /// Enhance mode
 /// 返回最终订阅、该订阅包含的键、和script执行的结果
 pub fn enhance() -> (Mapping, Vec<String>, HashMap<String, ResultLog>) {
+    enhance_with(true)
+}
+
+/// 同 `enhance`，`use_scripts` 为假时跳过用户的script
+pub fn enhance_with(use_scripts: bool) -> (Mapping, Vec<String>, HashMap<String, ResultLog>) {
+    // 从profiles里拿东西
+    let (config, chain) = {
+        let profiles = Config::profiles();
+        let profiles = profiles.latest();
+
+        let current = profiles.current_mapping().unwrap_or_default();
+
+        let chain = profiles
+            .current_chain()
+            .into_iter()
+            .filter_map(<Option<ChainItem>>::from)
+            .collect::<Vec<ChainItem>>();
+
+        (current, chain)
+    };
+
+    enhance_chain(config, chain, use_scripts)
+}
+
+/// 生成指定订阅的最终配置，不切换当前订阅，也不影响内核
+/// 订阅或chain读取失败、script出错时返回错误
+pub fn enhance_dry_run(uid: &str) -> Result<Mapping> {
+    let (config, chain) = {
+        let mut profiles = { Config::profiles().latest().clone() };
+        let uid = uid.to_string();
+        if profiles.get_item(&uid)?.is_enhance() {
+            bail!("the profile \"uid:{uid}\" is a merge or script");
+        }
+        profiles.current = Some(uid.clone());
+
+        let config = profiles
+            .current_mapping()
+            .with_context(|| format!("failed to read the profile \"uid:{uid}\""))?;
+        let chain = profiles
+            .current_chain()
+            .into_iter()
+            .map(|item| {
+                let uid = item.uid.clone().unwrap_or_default();
+                <Option<ChainItem>>::from(item)
+                    .ok_or_else(|| anyhow!("failed to read the chain item \"uid:{uid}\""))
+            })
+            .collect::<Result<Vec<ChainItem>>>()?;
+
+        (config, chain)
+    };
+
+    let (config, _, logs) = enhance_chain(config, chain, true);
+    for (uid, logs) in logs {
+        if let Some((_, err)) = logs.iter().find(|(level, _)| level == "exception") {
+            bail!("failed to run the script \"uid:{uid}\": {err}");
+        }
+    }
+    Ok(config)
+}
+
+/// 在订阅上依次应用chain，再合并 `config.yaml` 和内建的处理
+fn enhance_chain(
+    mut config: Mapping,
+    chain: Vec<ChainItem>,
+    use_scripts: bool,
+) -> (Mapping, Vec<String>, HashMap<String, ResultLog>) {
     // config.yaml 的订阅
     let clash_config = { Config::clash().latest().0.clone() };
 
@@ -33,6 +108,26 @@ pub fn enhance() -> (Mapping, Vec<String>, HashMap<String, ResultLog>) {
             verge.verge_http_enabled.unwrap_or(true),
         )
     };
+    let dns_override = {
+        let verge = Config::verge();
+        let verge = verge.latest();
+        match verge.enable_dns_override.unwrap_or(false) {
+            true => verge.dns_override.clone(),
+            false => None,
+        }
+    };
+    let disabled_providers = {
+        let verge = Config::verge();
+        let verge = verge.latest();
+        verge.disabled_rule_providers.clone().unwrap_or_default()
+    };
+    let ip_version = { Config::verge().latest().ip_version.clone() };
+    let lan_auth = {
+        let verge = Config::verge();
+        let verge = verge.latest();
+        let entries = verge.lan_authentication.clone().unwrap_or_default();
+        lan_auth::read_secrets().and_then(|secrets| lan_auth::core_auth(&entries, &secrets))
+    };
     #[cfg(not(target_os = "windows"))]
     let redir_enabled = {
         let verge = Config::verge();
@@ -46,25 +141,6 @@ pub fn enhance() -> (Mapping, Vec<String>, HashMap<String, ResultLog>) {
         verge.verge_tproxy_enabled.unwrap_or(true)
     };
 
-    // 从profiles里拿东西
-    let (mut config, chain) = {
-        let profiles = Config::profiles();
-        let profiles = profiles.latest();
-
-        let current = profiles.current_mapping().unwrap_or_default();
-
-        let chain = match profiles.chain.as_ref() {
-            Some(chain) => chain
-                .iter()
-                .filter_map(|uid| profiles.get_item(uid).ok())
-                .filter_map(<Option<ChainItem>>::from)
-                .collect::<Vec<ChainItem>>(),
-            None => vec![],
-        };
-
-        (current, chain)
-    };
-
     let mut result_map = HashMap::new(); // 保存脚本日志
     let mut exists_keys = use_keys(&config); // 保存出现过的keys
 
@@ -74,6 +150,9 @@ pub fn enhance() -> (Mapping, Vec<String>, HashMap<String, ResultLog>) {
             exists_keys.extend(use_keys(&merge));
             config = use_merge(merge, config.to_owned());
         }
+        ChainType::Script(_) if !use_scripts => {
+            log::debug!(target: "app", "skip the script {}", item.uid);
+        }
         ChainType::Script(script) => {
             let mut logs = vec![];
 
@@ -151,6 +230,25 @@ pub fn enhance() -> (Mapping, Vec<String>, HashMap<String, ResultLog>) {
             });
     }
 
+    // 认证不全时关掉 allow-lan，下面的监听地址跟着变
+    config = use_lan_auth(config, lan_auth);
+
+    // 按ip版本覆盖监听地址，不写入 config.yaml
+    if let Some(ip_version) = ip_version {
+        let allow_lan = config
+            .get("allow-lan")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        match resolve::listen_config(&ip_version, allow_lan) {
+            Ok(listen) => listen.into_iter().for_each(|(key, value)| {
+                config.insert(key, value);
+            }),
+            Err(err) => log::error!(target: "app", "{err}"),
+        }
+    }
+
+    config = use_dns_override(config, dns_override);
+    config = use_rule_providers(config, &disabled_providers);
     config = use_tun(config, enable_tun);
     config = use_sort(config);
 
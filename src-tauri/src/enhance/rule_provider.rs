@@ -0,0 +1,82 @@
+use serde_yaml::{Mapping, Value};
+
+/// remove the disabled rule providers and the rules using them
+/// 订阅里没有的 provider 直接忽略
+pub fn use_rule_providers(mut config: Mapping, disabled: &[String]) -> Mapping {
+    if disabled.is_empty() {
+        return config;
+    }
+    let is_disabled = |name: &str| disabled.iter().any(|d| d == name);
+
+    if let Some(providers) = config
+        .get_mut("rule-providers")
+        .and_then(|v| v.as_mapping_mut())
+    {
+        providers.retain(|key, _| !key.as_str().map_or(false, is_disabled));
+    }
+
+    if let Some(rules) = config.get_mut("rules").and_then(|v| v.as_sequence_mut()) {
+        rules.retain(|rule| match rule.as_str() {
+            Some(rule) => !disabled.iter().any(|name| use_provider(rule, name)),
+            None => true,
+        });
+    }
+    config
+}
+
+/// `RULE-SET,name,Proxy` or the logic rule `AND,((RULE-SET,name),(NETWORK,UDP)),REJECT`
+fn use_provider(rule: &str, name: &str) -> bool {
+    let rule = rule.replace(' ', "");
+    let parts = rule
+        .split(|c| c == '(' || c == ')')
+        .filter(|part| !part.is_empty() && *part != ",");
+    for part in parts {
+        let mut fields = part.trim_matches(',').split(',');
+        if fields.next() == Some("RULE-SET") && fields.next() == Some(name) {
+            return true;
+        }
+    }
+    false
+}
+
+#[test]
+fn test_rule_providers() {
+    let config: Mapping = serde_yaml::from_str(
+        r#"
+rule-providers:
+  ads: {type: http, behavior: domain, url: "https://a.com/ads.yaml"}
+  cn: {type: http, behavior: ipcidr, url: "https://a.com/cn.yaml"}
+rules:
+  - RULE-SET,ads,REJECT
+  - RULE-SET, cn, DIRECT, no-resolve
+  - AND,((RULE-SET,ads),(NETWORK,UDP)),REJECT
+  - DOMAIN-SUFFIX,ads.com,Proxy
+  - MATCH,Proxy
+"#,
+    )
+    .unwrap();
+
+    let result = use_rule_providers(config.clone(), &["ads".into(), "missing".into()]);
+    let providers = result.get("rule-providers").unwrap().as_mapping().unwrap();
+    assert_eq!(providers.len(), 1);
+    assert!(providers.contains_key("cn"));
+    let rules = result.get("rules").unwrap().as_sequence().unwrap();
+    let rules = rules
+        .iter()
+        .filter_map(Value::as_str)
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        rules,
+        vec![
+            "RULE-SET, cn, DIRECT, no-resolve",
+            "DOMAIN-SUFFIX,ads.com,Proxy",
+            "MATCH,Proxy"
+        ]
+    );
+
+    let result = use_rule_providers(config.clone(), &["cn".into()]);
+    let rules = result.get("rules").unwrap().as_sequence().unwrap();
+    assert_eq!(rules.len(), 4);
+
+    assert_eq!(use_rule_providers(config.clone(), &[]), config);
+}
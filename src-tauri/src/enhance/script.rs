@@ -6,6 +6,10 @@ pub fn use_script(script: String, config: Mapping) -> Result<(Mapping, Vec<(Stri
     use boa_engine::{native_function::NativeFunction, Context, JsValue, Source};
     use std::sync::{Arc, Mutex};
     let mut context = Context::default();
+    // 避免死循环卡住
+    context
+        .runtime_limits_mut()
+        .set_loop_iteration_limit(10_000_000);
 
     let outputs = Arc::new(Mutex::new(vec![]));
 
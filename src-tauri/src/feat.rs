@@ -7,9 +7,22 @@
 use crate::config::*;
 use crate::core::*;
 use crate::log_err;
-use crate::utils::resolve;
-use anyhow::{bail, Result};
+use crate::utils::{
+    backup,
+    captive_portal::{self, Connectivity},
+    dirs, geodata, help, lan_auth,
+    proxy_env::{self, ShellKind},
+    resolve,
+    rule_match::{self, RuleMatch},
+};
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
 use serde_yaml::{Mapping, Value};
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering},
+    Arc,
+};
 use tauri::{AppHandle, ClipboardManager, Manager};
 
 // 打开面板
@@ -27,6 +40,71 @@ pub fn open_or_close_dashboard() {
     }
 }
 
+/// 按住热键会连续触发，间隔内的重复忽略
+const TOGGLE_WINDOW_INTERVAL_MS: i64 = 300;
+static LAST_TOGGLE_WINDOW: AtomicI64 = AtomicI64::new(0);
+
+/// 显示或隐藏面板 (hotkey)
+/// 隐藏而不是关闭，不受 `close_to_tray` 影响；静默启动没有窗口时创建
+pub fn toggle_window() {
+    let now = chrono::Local::now().timestamp_millis();
+    let last = LAST_TOGGLE_WINDOW.swap(now, Ordering::SeqCst);
+    if now - last < TOGGLE_WINDOW_INTERVAL_MS {
+        return;
+    }
+
+    let handle = handle::Handle::global();
+    let app_handle = handle.app_handle.lock();
+    if let Some(app_handle) = app_handle.as_ref() {
+        if let Some(window) = app_handle.get_window("main") {
+            let visible = window.is_visible().unwrap_or(false);
+            let minimized = window.is_minimized().unwrap_or(false);
+            if visible && !minimized {
+                let _ = window.hide();
+                return;
+            }
+        }
+        resolve::create_window(app_handle);
+    }
+}
+
+/// 窗口置顶，保存设置
+pub async fn set_always_on_top(enable: bool) -> Result<()> {
+    patch_verge(IVerge {
+        window_always_on_top: Some(enable),
+        ..IVerge::default()
+    })
+    .await
+}
+
+/// 重启应用后生效
+pub async fn set_software_rendering(enable: bool) -> Result<()> {
+    patch_verge(IVerge {
+        software_rendering: Some(enable),
+        ..IVerge::default()
+    })
+    .await
+}
+
+/// 打开目录，不存在时先创建
+pub fn open_dir(dir: PathBuf) -> Result<()> {
+    if !dir.exists() {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create the dir \"{}\"", dir.display()))?;
+    }
+    open::that(&dir).with_context(|| format!("failed to open the dir \"{}\"", dir.display()))
+}
+
+/// 打开配置目录 (hotkey)
+pub fn open_config_dir() {
+    log_err!(dirs::app_home_dir().and_then(open_dir));
+}
+
+/// 打开日志目录 (hotkey)
+pub fn open_logs_dir() {
+    log_err!(dirs::app_logs_dir().and_then(open_dir));
+}
+
 // 重启clash
 pub fn restart_clash_core() {
     tauri::async_runtime::spawn(async {
@@ -45,25 +123,117 @@ pub fn restart_clash_core() {
 
 // 切换模式 rule/global/direct/script mode
 pub fn change_clash_mode(mode: String) {
-    let mut mapping = Mapping::new();
-    mapping.insert(Value::from("mode"), mode.clone().into());
-
     tauri::async_runtime::spawn(async move {
-        log::debug!(target: "app", "change clash mode to {mode}");
+        log_err!(set_clash_mode(mode).await);
+    });
+}
 
-        match clash_api::patch_configs(&mapping).await {
-            Ok(_) => {
-                // 更新订阅
-                Config::clash().data().patch_config(mapping);
+/// the mode in the clash config, default is `rule`
+pub fn get_clash_mode() -> String {
+    let clash = Config::clash();
+    let clash = clash.data();
+    let mode = clash.0.get("mode").and_then(|v| v.as_str());
+    mode.unwrap_or("rule").to_lowercase()
+}
 
-                if Config::clash().data().save_config().is_ok() {
-                    handle::Handle::refresh_clash();
-                    log_err!(handle::Handle::update_systray_part());
-                }
+/// 修改内核的模式并保存，返回之前的模式
+/// 模式相同时不做任何事
+pub async fn set_clash_mode(mode: String) -> Result<String> {
+    let mode = mode.trim().to_lowercase();
+    match mode.as_str() {
+        "rule" | "global" | "direct" => {}
+        // 只有 premium 内核支持 script 模式
+        "script" => {
+            let version = CoreManager::global().get_version().await?;
+            if !version.premium {
+                bail!("the script mode is not supported by the core");
             }
-            Err(err) => log::error!(target: "app", "{err}"),
         }
-    });
+        _ => bail!("invalid clash mode \"{mode}\""),
+    }
+
+    let previous = get_clash_mode();
+    if previous == mode {
+        return Ok(previous);
+    }
+    log::debug!(target: "app", "change clash mode from {previous} to {mode}");
+
+    let mut mapping = Mapping::new();
+    mapping.insert(Value::from("mode"), mode.into());
+    clash_api::ClashApi::from_config()?
+        .patch_configs(&mapping)
+        .await?;
+
+    // 更新订阅
+    Config::clash().data().patch_config(mapping);
+    Config::clash().data().save_config()?;
+    handle::Handle::refresh_clash();
+    log_err!(handle::Handle::update_systray_part());
+    Ok(previous)
+}
+
+/// 同步通过外部控制修改的模式，返回是否有变化
+pub async fn sync_clash_mode() -> Result<bool> {
+    let configs = clash_api::ClashApi::from_config()?.get_configs().await?;
+    let mode = match configs.get("mode").and_then(|v| v.as_str()) {
+        Some(mode) => mode.to_lowercase(),
+        None => return Ok(false),
+    };
+    let current = { Config::clash().data().0.get("mode").cloned() };
+    if current.as_ref().and_then(|v| v.as_str()) == Some(mode.as_str()) {
+        return Ok(false);
+    }
+    log::debug!(target: "app", "the clash mode is changed to {mode} by the core");
+
+    let mut mapping = Mapping::new();
+    mapping.insert(Value::from("mode"), mode.into());
+    Config::clash().data().patch_config(mapping);
+    Config::clash().data().save_config()?;
+    handle::Handle::refresh_clash();
+    log_err!(handle::Handle::update_systray_part());
+    Ok(true)
+}
+
+/// log levels accepted by the core
+pub const CORE_LOG_LEVELS: [&str; 5] = ["silent", "error", "warning", "info", "debug"];
+
+/// 获取内核的日志等级
+pub fn get_core_log_level() -> String {
+    Config::clash()
+        .latest()
+        .0
+        .get("log-level")
+        .and_then(|value| value.as_str())
+        .unwrap_or("info")
+        .to_owned()
+}
+
+/// 修改内核的日志等级
+/// 内核支持热更新，无需重启
+pub async fn set_core_log_level(level: String) -> Result<()> {
+    let level = level.trim().to_lowercase();
+    if !CORE_LOG_LEVELS.contains(&level.as_str()) {
+        bail!("invalid core log level \"{level}\"");
+    }
+
+    let api = clash_api::ClashApi::from_config()?;
+    let mut mapping = Mapping::new();
+    mapping.insert("log-level".into(), level.into());
+    Config::clash().draft().patch_config(mapping.clone());
+
+    match api.patch_configs(&mapping).await {
+        Ok(_) => {
+            Config::clash().apply();
+            Config::clash().data().save_config()?;
+            Config::runtime().latest().patch_config(mapping);
+            handle::Handle::refresh_clash();
+            Ok(())
+        }
+        Err(err) => {
+            Config::clash().discard();
+            Err(err)
+        }
+    }
 }
 
 // 切换系统代理
@@ -72,63 +242,167 @@ pub fn toggle_system_proxy() {
     let enable = enable.unwrap_or(false);
 
     tauri::async_runtime::spawn(async move {
-        match patch_verge(IVerge {
-            enable_system_proxy: Some(!enable),
-            ..IVerge::default()
-        })
-        .await
-        {
-            Ok(_) => handle::Handle::refresh_verge(),
-            Err(err) => log::error!(target: "app", "{err}"),
-        }
+        log_err!(set_system_proxy(!enable).await);
     });
 }
 
+/// 只开关系统代理，内核保持运行
+pub async fn set_system_proxy(enable: bool) -> Result<()> {
+    patch_verge(IVerge {
+        enable_system_proxy: Some(enable),
+        ..IVerge::default()
+    })
+    .await?;
+    handle::Handle::refresh_verge();
+    Ok(())
+}
+
+/// 开关开机启动，立即写入系统的启动项
+pub async fn set_auto_launch(enable: bool) -> Result<bool> {
+    patch_verge(IVerge {
+        enable_auto_launch: Some(enable),
+        ..IVerge::default()
+    })
+    .await?;
+    handle::Handle::refresh_verge();
+    sysopt::Sysopt::global().is_autostart_enabled()
+}
+
 // 切换tun模式
 pub fn toggle_tun_mode() {
     let enable = Config::verge().data().enable_tun_mode;
     let enable = enable.unwrap_or(false);
 
     tauri::async_runtime::spawn(async move {
-        match patch_verge(IVerge {
-            enable_tun_mode: Some(!enable),
-            ..IVerge::default()
-        })
-        .await
-        {
-            Ok(_) => handle::Handle::refresh_verge(),
-            Err(err) => log::error!(target: "app", "{err}"),
+        if let Err(err) = set_tun_mode(!enable).await {
+            handle::Handle::notice_message("set_config::error", format!("{err}"));
+            log::error!(target: "app", "{err}");
         }
     });
 }
 
+/// 开关tun模式
+/// 开启前检查驱动和权限，关闭后恢复系统代理
+pub async fn set_tun_mode(enable: bool) -> Result<()> {
+    if enable {
+        let capability = manager::check_tun_capability().await;
+        if !capability.available {
+            bail!(
+                "tun mode is unavailable, missing: {}",
+                capability.missing.join(", ")
+            );
+        }
+    }
+
+    patch_verge(IVerge {
+        enable_tun_mode: Some(enable),
+        ..IVerge::default()
+    })
+    .await?;
+
+    let system_proxy = { Config::verge().latest().enable_system_proxy };
+    if !enable && system_proxy.unwrap_or(false) {
+        sysopt::Sysopt::global().update_sysproxy()?;
+        sysopt::Sysopt::global().guard_proxy();
+    }
+
+    handle::Handle::refresh_verge();
+    Ok(())
+}
+
+/// 单独修改tun的某个字段，写入clash配置后重新加载
+async fn patch_tun(key: &str, value: Value) -> Result<()> {
+    let mut tun = {
+        let clash = Config::clash();
+        let clash = clash.latest();
+        let tun = clash.0.get("tun").and_then(|v| v.as_mapping());
+        tun.cloned().unwrap_or_default()
+    };
+    tun.insert(key.into(), value);
+
+    let auto_route = tun.get("auto-route").and_then(|v| v.as_bool());
+    let strict_route = tun.get("strict-route").and_then(|v| v.as_bool());
+    if strict_route.unwrap_or(false) && !auto_route.unwrap_or(true) {
+        let msg = "the strict-route takes no effect without the auto-route";
+        log::warn!(target: "app", "{msg}");
+        handle::Handle::notice_message("set_config::warn", msg);
+    }
+
+    let mut patch = Mapping::new();
+    patch.insert("tun".into(), tun.into());
+    patch_clash(patch).await?;
+    update_core_config().await
+}
+
+pub async fn set_tun_auto_route(enable: bool) -> Result<()> {
+    patch_tun("auto-route", enable.into()).await
+}
+
+pub async fn set_tun_strict_route(enable: bool) -> Result<()> {
+    patch_tun("strict-route", enable.into()).await
+}
+
+pub async fn set_tun_dns_hijack(list: Vec<String>) -> Result<()> {
+    let list = IClashTUN::check_dns_hijack(&list)?;
+    patch_tun("dns-hijack", list.into()).await
+}
+
+pub async fn set_tun_stack(stack: String) -> Result<()> {
+    let stack = IClashTUN::check_stack(&stack)?;
+    patch_tun("stack", stack.into()).await
+}
+
 /// 修改clash的订阅
 pub async fn patch_clash(patch: Mapping) -> Result<()> {
     Config::clash().draft().patch_config(patch.clone());
 
-    match {
+    // 所有的错误都要走到下面丢弃草稿
+    match async {
         let redir_port = patch.get("redir-port");
         let tproxy_port = patch.get("tproxy-port");
         let mixed_port = patch.get("mixed-port");
         let socks_port = patch.get("socks-port");
         let port = patch.get("port");
         let enable_random_port = Config::verge().latest().enable_random_port.unwrap_or(false);
-        if mixed_port.is_some() && !enable_random_port {
-            let changed = mixed_port.unwrap()
-                != Config::verge()
-                    .latest()
-                    .verge_mixed_port
-                    .unwrap_or(Config::clash().data().get_mixed_port());
-            // 检查端口占用
-            if changed {
-                if let Some(port) = mixed_port.unwrap().as_u64() {
-                    if !port_scanner::local_port_available(port as u16) {
-                        Config::clash().discard();
-                        bail!("port already in use");
-                    }
-                }
+        let current = {
+            let mut ports = resolve::listen_ports(&Config::clash().data().0);
+            if let Some(port) = { Config::verge().latest().verge_mixed_port } {
+                ports[0].1 = port;
             }
+            ports
         };
+        let changed = [
+            ("mixed", mixed_port),
+            ("socks", socks_port),
+            ("http", port),
+            ("redir", redir_port),
+            ("tproxy", tproxy_port),
+        ];
+        // 检查端口占用
+        for (name, value) in changed {
+            let Some(value) = value.and_then(|v| v.as_u64()) else {
+                continue;
+            };
+            if name == "mixed" && enable_random_port {
+                continue;
+            }
+            let value = value as u16;
+            if !current.contains(&(name, value)) && !resolve::is_port_available(value) {
+                bail!("the {name} port {value} already in use");
+            }
+        }
+
+        // 检查端口冲突
+        if changed.iter().any(|(_, value)| value.is_some())
+            || patch.get("external-controller").is_some()
+        {
+            let disabled = resolve::disabled_ports();
+            let ports = resolve::listen_ports(&Config::clash().draft().0)
+                .into_iter()
+                .filter(|(name, _)| !disabled.contains(name))
+                .collect::<Vec<_>>();
+            resolve::check_port_conflicts(&ports)?;
+        }
 
         // 激活订阅
         if redir_port.is_some()
@@ -144,6 +418,21 @@ pub async fn patch_clash(patch: Mapping) -> Result<()> {
             handle::Handle::refresh_clash();
         }
 
+        // 局域网认证只在 allow-lan 时生效
+        // 指定了ip版本时 bind-address 也跟着 allow-lan 变
+        if let Some(allow_lan) = patch.get("allow-lan").and_then(|v| v.as_bool()) {
+            let no_auth = get_lan_auth_users().is_empty();
+            let ip_version = { Config::verge().latest().ip_version.clone() };
+            if !no_auth || ip_version.is_some() {
+                update_core_config().await?;
+            }
+            if no_auth && allow_lan {
+                let msg = "the lan is allowed without any authentication";
+                log::warn!(target: "app", "{msg}");
+                handle::Handle::notice_message("set_config::warn", msg);
+            }
+        }
+
         // 更新系统代理
         if mixed_port.is_some() {
             log_err!(sysopt::Sysopt::global().init_sysproxy());
@@ -156,7 +445,9 @@ pub async fn patch_clash(patch: Mapping) -> Result<()> {
         Config::runtime().latest().patch_config(patch);
 
         <Result<()>>::Ok(())
-    } {
+    }
+    .await
+    {
         Ok(()) => {
             Config::clash().apply();
             Config::clash().data().save_config()?;
@@ -169,9 +460,648 @@ pub async fn patch_clash(patch: Mapping) -> Result<()> {
     }
 }
 
+/// 设置单独的 socks / http 端口，并重启内核
+pub async fn set_proxy_ports(socks_port: Option<u16>, port: Option<u16>) -> Result<()> {
+    let mut patch = Mapping::new();
+    if let Some(socks_port) = socks_port {
+        patch.insert("socks-port".into(), socks_port.into());
+    }
+    if let Some(port) = port {
+        patch.insert("port".into(), port.into());
+    }
+    if patch.is_empty() {
+        return Ok(());
+    }
+    patch_clash(patch).await?;
+
+    Config::verge().draft().patch_config(IVerge {
+        verge_socks_port: socks_port,
+        verge_port: port,
+        ..IVerge::default()
+    });
+    Config::verge().apply();
+    Config::verge().data().save_file()?;
+    handle::Handle::refresh_verge();
+    Ok(())
+}
+
+/// 设置或轮换外部控制的secret，None时随机生成
+/// 重启内核后生效，ClashApi 每次都从配置读取secret
+pub async fn set_controller_secret(secret: Option<String>) -> Result<String> {
+    let secret = match secret.map(|s| s.trim().to_string()) {
+        Some(secret) if secret.is_empty() => bail!("the secret should not be empty"),
+        Some(secret) => secret,
+        None => help::gen_secret(),
+    };
+
+    let mut patch = Mapping::new();
+    patch.insert("secret".into(), secret.clone().into());
+    patch_clash(patch).await?;
+    Ok(secret)
+}
+
+/// the rule provider of the profile, or disabled by the user
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleProviderState {
+    pub name: String,
+    /// `domain` | `ipcidr` | `classical`, none if disabled
+    pub behavior: Option<String>,
+    pub enabled: bool,
+}
+
+/// the rule providers of the runtime config and the disabled ones
+pub fn list_rule_providers() -> Vec<RuleProviderState> {
+    let disabled = { Config::verge().latest().disabled_rule_providers.clone() };
+    let runtime = Config::runtime();
+    let runtime = runtime.latest();
+
+    let mut list = vec![];
+    let providers = runtime
+        .config
+        .as_ref()
+        .and_then(|config| config.get("rule-providers"))
+        .and_then(|v| v.as_mapping());
+    for (name, value) in providers.into_iter().flatten() {
+        if let Some(name) = name.as_str() {
+            let behavior = value.get("behavior").and_then(|v| v.as_str());
+            list.push(RuleProviderState {
+                name: name.to_string(),
+                behavior: behavior.map(String::from),
+                enabled: true,
+            });
+        }
+    }
+
+    for name in disabled.unwrap_or_default() {
+        list.push(RuleProviderState {
+            name,
+            behavior: None,
+            enabled: false,
+        });
+    }
+    list
+}
+
+/// the temporary relay groups of the chain test are `{prefix}{i}`
+const CHAIN_TEST_PREFIX: &str = "__chain_test_";
+
+/// 同时只有一个测试替换内核的配置
+static CHAIN_TEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// 逐段测试代理链，第 i 跳测试前 i 跳组成的临时 relay 组
+/// 内核没有测试临时代理链的接口，临时组加到运行的配置里让内核重新加载，
+/// 测完再加载回运行的配置，期间的连接会断开
+pub async fn test_proxy_chain(
+    names: Vec<String>,
+    url: Option<String>,
+    timeout: i32,
+) -> Result<clash_api::ChainResult> {
+    let mut relays = names.iter().take(1).cloned().collect::<Vec<String>>();
+    let mut groups = vec![];
+    for i in 1..names.len() {
+        let name = format!("{CHAIN_TEST_PREFIX}{i}");
+        let mut group = Mapping::new();
+        group.insert("name".into(), name.clone().into());
+        group.insert("type".into(), "relay".into());
+        group.insert("proxies".into(), names[..=i].to_vec().into());
+        groups.push(Value::from(group));
+        relays.push(name);
+    }
+
+    let api = clash_api::ClashApi::from_config()?;
+    if groups.is_empty() {
+        return api.test_chain(&names, &relays, url, timeout).await;
+    }
+
+    let _guard = CHAIN_TEST_LOCK.lock().await;
+    let mut config = { Config::runtime().latest().config.clone() }
+        .ok_or(anyhow::anyhow!("failed to get runtime config"))?;
+    let mut proxy_groups = config
+        .get("proxy-groups")
+        .and_then(|v| v.as_sequence())
+        .cloned()
+        .unwrap_or_default();
+    proxy_groups.extend(groups);
+    config.insert("proxy-groups".into(), proxy_groups.into());
+
+    let test_path = dirs::app_home_dir()?.join(CHAIN_TEST_CONFIG);
+    let run_path = dirs::app_home_dir()?.join(RUNTIME_CONFIG);
+    let test_str = dirs::path_to_str(&test_path)?;
+    let run_str = dirs::path_to_str(&run_path)?;
+    help::save_private_yaml(&test_path, &config, "# Generated by Clash Verge")?;
+
+    let result = match api.put_configs(test_str).await {
+        Ok(_) => api.test_chain(&names, &relays, url, timeout).await,
+        Err(err) => Err(err),
+    };
+
+    // 测试失败也要加载回运行的配置
+    let restored = api.put_configs(run_str).await;
+    let _ = std::fs::remove_file(&test_path);
+    restored.context("failed to restore the runtime config after the chain test")?;
+    result
+}
+
+/// 连续多少次检查都不可用才切换到备用订阅
+const HEALTH_CHECK_FAIL_TIMES: usize = 3;
+
+/// 健康检查用较短的超时，不影响正常使用
+const HEALTH_CHECK_TIMEOUT: i32 = 3000;
+
+/// 每轮最多测试的节点数和并发数
+const HEALTH_CHECK_MAX_NODES: usize = 10;
+const HEALTH_CHECK_CONCURRENCY: usize = 5;
+
+/// the profile being checked and its consecutive failures
+static HEALTH_FAILURES: parking_lot::Mutex<Option<(String, usize)>> =
+    parking_lot::const_mutex(None);
+
+/// 每轮换一批节点测试
+static HEALTH_CHECK_ROUND: AtomicUsize = AtomicUsize::new(0);
+
+/// the result of a round of the health check
+#[derive(Debug, PartialEq, Eq)]
+enum HealthVerdict {
+    /// one of the nodes works
+    Healthy,
+    /// the direct connection fails too, not the fault of the profile
+    Offline,
+    /// all the nodes fail for the times in a row
+    Failing(usize),
+    /// failed `HEALTH_CHECK_FAIL_TIMES` times in a row, switch to the fallback
+    Fallback,
+}
+
+fn health_verdict(
+    node_ok: bool,
+    direct_ok: bool,
+    last: Option<&(String, usize)>,
+    current: &str,
+) -> HealthVerdict {
+    if node_ok {
+        return HealthVerdict::Healthy;
+    }
+    if !direct_ok {
+        return HealthVerdict::Offline;
+    }
+    let failures = match last {
+        Some((uid, failures)) if uid == current => failures + 1,
+        _ => 1,
+    };
+    match failures >= HEALTH_CHECK_FAIL_TIMES {
+        true => HealthVerdict::Fallback,
+        false => HealthVerdict::Failing(failures),
+    }
+}
+
+/// at most `max` nodes spread over the list
+/// rotated by `round`, so all the nodes are tested in turn
+fn sample_nodes(nodes: &[String], max: usize, round: usize) -> Vec<String> {
+    let len = nodes.len();
+    if len <= max {
+        return nodes.to_vec();
+    }
+    let offset = round % len;
+    (0..max)
+        .map(|i| nodes[(offset + i * len / max) % len].clone())
+        .collect()
+}
+
+/// 设置备用订阅，None 时关闭健康检查
+pub async fn set_fallback_profile(uid: Option<String>) -> Result<()> {
+    let uid = uid.filter(|uid| !uid.is_empty());
+    if let Some(uid) = uid.as_ref() {
+        let profiles = Config::profiles();
+        let profiles = profiles.latest();
+        let item = profiles.get_item(uid)?;
+        if !matches!(item.itype.as_deref(), Some("remote" | "local")) {
+            bail!("the fallback profile should be a remote or local profile");
+        }
+    }
+
+    patch_verge(IVerge {
+        fallback_profile_uid: Some(uid.unwrap_or_default()),
+        ..IVerge::default()
+    })
+    .await
+}
+
+/// 测试当前订阅第一个代理组里的节点 (最多 `HEALTH_CHECK_MAX_NODES` 个)，有一个可用就算正常
+/// 连续 `HEALTH_CHECK_FAIL_TIMES` 次都不可用时切换到备用订阅
+pub async fn check_profile_health() -> Result<()> {
+    let fallback = { Config::verge().latest().fallback_profile_uid.clone() };
+    let Some(fallback) = fallback.filter(|uid| !uid.is_empty()) else {
+        return Ok(());
+    };
+    let Some(current) = Config::profiles().latest().get_current() else {
+        return Ok(());
+    };
+    if current == fallback || !monitor::Monitor::global().core_running() {
+        *HEALTH_FAILURES.lock() = None;
+        return Ok(());
+    }
+
+    let proxies = {
+        let runtime = Config::runtime();
+        let runtime = runtime.latest();
+        let group = runtime
+            .config
+            .as_ref()
+            .and_then(|config| config.get("proxy-groups"))
+            .and_then(|v| v.as_sequence())
+            .and_then(|groups| groups.first());
+        let proxies = group
+            .and_then(|g| g.get("proxies"))
+            .and_then(|v| v.as_sequence());
+        proxies
+            .into_iter()
+            .flatten()
+            .filter_map(|v| v.as_str())
+            .filter(|name| !matches!(*name, "DIRECT" | "REJECT" | "REJECT-DROP" | "PASS"))
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    if proxies.is_empty() {
+        return Ok(());
+    }
+
+    let round = HEALTH_CHECK_ROUND.fetch_add(1, Ordering::SeqCst);
+    let proxies = sample_nodes(&proxies, HEALTH_CHECK_MAX_NODES, round);
+
+    // 并发测试，有一个可用就停止
+    let test_url = { Config::verge().latest().default_latency_test.clone() };
+    let api = Arc::new(clash_api::ClashApi::from_config()?);
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(HEALTH_CHECK_CONCURRENCY));
+    let mut tasks = tokio::task::JoinSet::new();
+    for name in proxies {
+        let (api, semaphore, test_url) = (api.clone(), semaphore.clone(), test_url.clone());
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            api.delay_with_timeout(&name, &test_url, HEALTH_CHECK_TIMEOUT)
+                .await
+                .is_ok()
+        });
+    }
+    let mut node_ok = false;
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok(true) = joined {
+            node_ok = true;
+            tasks.abort_all();
+            break;
+        }
+    }
+
+    // 直连也不通时多半是断网了，不算订阅的问题
+    let direct_ok = node_ok
+        || api
+            .delay_with_timeout("DIRECT", &test_url, HEALTH_CHECK_TIMEOUT)
+            .await
+            .is_ok();
+
+    let verdict = {
+        let mut state = HEALTH_FAILURES.lock();
+        let verdict = health_verdict(node_ok, direct_ok, state.as_ref(), &current);
+        match verdict {
+            HealthVerdict::Healthy | HealthVerdict::Fallback => *state = None,
+            HealthVerdict::Failing(failures) => *state = Some((current.clone(), failures)),
+            HealthVerdict::Offline => {}
+        }
+        verdict
+    };
+    match verdict {
+        HealthVerdict::Healthy => return Ok(()),
+        HealthVerdict::Offline => {
+            log::info!(target: "app", "skip the profile health check, the network is unreachable");
+            return Ok(());
+        }
+        HealthVerdict::Failing(failures) => {
+            log::warn!(target: "app", "the nodes of the profile `{current}` all fail ({failures})");
+            return Ok(());
+        }
+        HealthVerdict::Fallback => {}
+    }
+
+    let name = {
+        let profiles = Config::profiles();
+        let profiles = profiles.latest();
+        let item = profiles.get_item(&fallback)?;
+        item.name.clone().unwrap_or(fallback.clone())
+    };
+    patch_profiles_config(IProfiles {
+        current: Some(fallback),
+        ..IProfiles::default()
+    })
+    .await?;
+
+    let msg = format!("the nodes of the current profile all fail, switched to \"{name}\"");
+    log::warn!(target: "app", "{msg}");
+    handle::Handle::notice_message("set_config::warn", &msg);
+    handle::Handle::refresh_profiles();
+    log_err!(tauri::api::notification::Notification::new(dirs::APP_ID)
+        .title("Clash Verge")
+        .body(msg)
+        .show());
+    Ok(())
+}
+
+/// whether the user has been asked for the current captive portal
+static PORTAL_ASKED: AtomicBool = AtomicBool::new(false);
+
+/// 检测到认证页面时询问是否暂停系统代理，网络恢复后重新设置
+pub async fn check_captive_portal() -> Result<()> {
+    let (enable, system_proxy, tun_mode, zh) = {
+        let verge = Config::verge();
+        let verge = verge.latest();
+        (
+            verge.enable_captive_portal_detect.unwrap_or(false),
+            verge.enable_system_proxy.unwrap_or(false),
+            verge.enable_tun_mode.unwrap_or(false),
+            verge.language == Some("zh".into()),
+        )
+    };
+    if !enable {
+        return Ok(());
+    }
+
+    macro_rules! t {
+        ($en: expr, $zh: expr) => {
+            if zh {
+                $zh
+            } else {
+                $en
+            }
+        };
+    }
+    let notify = |body: &str| {
+        log_err!(tauri::api::notification::Notification::new(dirs::APP_ID)
+            .title("Clash Verge")
+            .body(body)
+            .show());
+    };
+
+    let sysopt = sysopt::Sysopt::global();
+    match captive_portal::detect().await {
+        Connectivity::Online => {
+            PORTAL_ASKED.store(false, Ordering::SeqCst);
+            if sysopt.is_portal_paused() {
+                sysopt.resume_after_portal()?;
+                notify(t!(
+                    "The network is connected, the system proxy is restored",
+                    "网络已连接，系统代理已恢复"
+                ));
+            }
+        }
+        // TUN 模式下关掉系统代理也没用，只能提示用户
+        Connectivity::CaptivePortal if tun_mode => {
+            if PORTAL_ASKED.swap(true, Ordering::SeqCst) {
+                return Ok(());
+            }
+            log::warn!(target: "app", "a captive portal is detected in the tun mode");
+            notify(t!(
+                "The network requires signing in, turn off the TUN mode to open the login page",
+                "当前网络需要认证，请关闭 TUN 模式后打开认证页面"
+            ));
+        }
+        Connectivity::CaptivePortal if system_proxy && !sysopt.is_portal_paused() => {
+            if PORTAL_ASKED.swap(true, Ordering::SeqCst) {
+                return Ok(());
+            }
+            log::warn!(target: "app", "a captive portal is detected");
+
+            let window = handle::Handle::global().get_window();
+            tauri::api::dialog::ask(
+                window.as_ref(),
+                "Clash Verge",
+                t!(
+                    "The network requires signing in, pause the system proxy until it is connected?",
+                    "当前网络需要认证，是否在连接成功前暂停系统代理？"
+                ),
+                |yes| {
+                    if yes {
+                        log_err!(sysopt::Sysopt::global().pause_for_portal());
+                        log_err!(open::that(captive_portal::CAPTIVE_PORTAL_URL));
+                    }
+                },
+            );
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// 启用或禁用 rule provider，对所有订阅生效
+/// 当前订阅里没有也可以先禁用
+pub async fn set_rule_provider_enabled(name: String, enabled: bool) -> Result<()> {
+    let mut disabled = {
+        let verge = Config::verge();
+        let verge = verge.latest();
+        verge.disabled_rule_providers.clone().unwrap_or_default()
+    };
+    let exists = disabled.contains(&name);
+    match enabled {
+        true if exists => disabled.retain(|n| n != &name),
+        false if !exists => disabled.push(name),
+        _ => return Ok(()),
+    }
+
+    patch_verge(IVerge {
+        disabled_rule_providers: Some(disabled),
+        ..IVerge::default()
+    })
+    .await
+}
+
+/// the users of the lan authentication
+pub fn get_lan_auth_users() -> Vec<String> {
+    let verge = Config::verge();
+    let verge = verge.latest();
+    lan_auth::users(verge.lan_authentication.as_deref().unwrap_or_default())
+}
+
+/// 添加局域网认证的用户，同名用户替换密码
+/// `verge.yaml` 只保存哈希，给内核的明文单独保存
+/// 哈希保存成功后才写明文，再更新一次内核的认证
+pub async fn add_lan_auth_user(user: String, pass: String) -> Result<()> {
+    let user = user.trim();
+    let entries = { Config::verge().latest().lan_authentication.clone() };
+    let entries = lan_auth::upsert(&entries.unwrap_or_default(), user, &pass)?;
+    patch_verge(IVerge {
+        lan_authentication: Some(entries),
+        ..IVerge::default()
+    })
+    .await?;
+    lan_auth::save_secret(user, Some(&pass))?;
+    update_core_config().await
+}
+
+/// 没有哈希的明文不会给内核，删除失败也不影响认证
+pub async fn remove_lan_auth_user(user: String) -> Result<()> {
+    let user = user.trim();
+    let entries = { Config::verge().latest().lan_authentication.clone() };
+    let entries = lan_auth::remove(&entries.unwrap_or_default(), user);
+    patch_verge(IVerge {
+        lan_authentication: Some(entries),
+        ..IVerge::default()
+    })
+    .await?;
+    lan_auth::save_secret(user, None)
+}
+
+/// 暂停或恢复订阅的自动更新，重启后保持
+pub async fn set_timer_paused(paused: bool) -> Result<()> {
+    patch_verge(IVerge {
+        timer_paused: Some(paused),
+        ..IVerge::default()
+    })
+    .await
+}
+
+/// the dns config in use
+/// `config.yaml` first, then the runtime config, then the default one
+pub fn get_dns() -> IClashDNS {
+    if let Some(dns) = Config::clash().latest().get_dns() {
+        return dns;
+    }
+
+    let runtime = Config::runtime();
+    let runtime = runtime.latest();
+    runtime
+        .config
+        .as_ref()
+        .and_then(|config| config.get("dns"))
+        .and_then(|value| serde_yaml::from_value(value.clone()).ok())
+        .unwrap_or(IClashDNS::template())
+}
+
+/// replay the rules of the runtime config against the host (or ip)
+/// the core has no api for rule matching, so the rules which need the core are reported as skipped
+pub async fn match_rule(target: String) -> Result<RuleMatch> {
+    let rules = {
+        let runtime = Config::runtime();
+        let runtime = runtime.latest();
+        runtime
+            .config
+            .as_ref()
+            .and_then(|config| config.get("rules"))
+            .and_then(|rules| rules.as_sequence())
+            .map(|rules| {
+                rules
+                    .iter()
+                    .filter_map(|rule| rule.as_str().map(String::from))
+                    .collect::<Vec<String>>()
+            })
+            .unwrap_or_default()
+    };
+
+    let (host, _) = rule_match::parse_target(&target);
+    if host.is_empty() {
+        bail!("the target is empty");
+    }
+
+    // resolve the domain by the system resolver for the ip rules
+    let mut resolved = vec![];
+    if host.parse::<std::net::IpAddr>().is_err() && rule_match::needs_resolve(&rules) {
+        match tokio::net::lookup_host((host.as_str(), 0)).await {
+            Ok(addrs) => resolved = addrs.map(|addr| addr.ip()).collect(),
+            Err(err) => log::warn!(target: "app", "failed to resolve \"{host}\": {err}"),
+        }
+    }
+
+    Ok(rule_match::match_rules(&rules, &target, &resolved))
+}
+
+/// 修改dns配置
+/// 写入 `config.yaml`，覆盖订阅中的dns，更新订阅后依然生效
+pub async fn patch_dns(patch: IClashDNS) -> Result<()> {
+    let mut dns = get_dns();
+    dns.patch_config(patch);
+    dns.validate()?;
+
+    let mut mapping = Mapping::new();
+    mapping.insert("dns".into(), serde_yaml::to_value(&dns)?);
+    Config::clash().draft().patch_config(mapping);
+
+    match CoreManager::global().update_config().await {
+        Ok(_) => {
+            handle::Handle::refresh_clash();
+            Config::clash().apply();
+            Config::clash().data().save_config()?;
+            Ok(())
+        }
+        Err(err) => {
+            Config::clash().discard();
+            Err(err)
+        }
+    }
+}
+
+/// 导出热键配置为json
+pub fn export_hotkeys() -> Result<String> {
+    let hotkeys = { Config::verge().latest().hotkeys.clone() };
+    let bindings = hotkey::Hotkey::to_bindings(&hotkeys.unwrap_or_default());
+    Ok(serde_json::to_string_pretty(&bindings)?)
+}
+
+/// 导入热键配置，校验通过后替换现有的热键并重新注册
+pub async fn import_hotkeys(json: String) -> Result<()> {
+    let bindings: Vec<hotkey::HotkeyBinding> =
+        serde_json::from_str(&json).context("invalid hotkey config")?;
+    let hotkeys = hotkey::Hotkey::parse_bindings(&bindings)?;
+
+    patch_verge(IVerge {
+        hotkeys: Some(hotkeys),
+        ..IVerge::default()
+    })
+    .await
+}
+
+/// the mixed port actually in use
+/// the random port has been resolved into `verge_mixed_port` on setup
+pub fn get_running_port() -> u16 {
+    Config::verge()
+        .latest()
+        .verge_mixed_port
+        .unwrap_or(Config::clash().data().get_mixed_port())
+}
+
+/// 检查patch中的取值，要在产生任何副作用之前
+fn check_verge_patch(patch: &IVerge) -> Result<()> {
+    if let Some(args) = patch.webview_extra_args.as_ref() {
+        resolve::check_webview_args(args)?;
+    }
+    if let Some(ip_version) = patch.ip_version.as_deref() {
+        resolve::listen_config(ip_version, false)?;
+    }
+    if let Some(policy) = patch.startup_profile_policy.as_deref() {
+        if !matches!(policy, "last-selected" | "specific-uid" | "none") {
+            bail!("invalid startup profile policy \"{policy}\"");
+        }
+    }
+    if let Some(mode) = patch.system_proxy_mode.as_deref() {
+        if !matches!(mode, "fixed" | "pac") {
+            bail!("invalid system proxy mode \"{mode}\"");
+        }
+    }
+    if let Some(action) = patch.core_crash_action.as_deref() {
+        if !matches!(action, "restart" | "notify") {
+            bail!("invalid core crash action \"{action}\"");
+        }
+    }
+    if let Some(dns) = patch.dns_override.as_ref() {
+        // override 合并到当前的dns上再检查
+        let dns = serde_yaml::from_value::<IClashDNS>(Value::Mapping(dns.clone()))
+            .context("invalid dns override")?;
+        let mut merged = get_dns();
+        merged.patch_config(dns);
+        merged.validate().context("invalid dns override")?;
+    }
+    Ok(())
+}
+
 /// 修改verge的订阅
 /// 一般都是一个个的修改
 pub async fn patch_verge(patch: IVerge) -> Result<()> {
+    check_verge_patch(&patch)?;
     Config::verge().draft().patch_config(patch.clone());
 
     let tun_mode = patch.enable_tun_mode;
@@ -212,14 +1142,59 @@ pub async fn patch_verge(patch: IVerge) -> Result<()> {
             Config::generate()?;
             CoreManager::global().run_core().await?;
         }
+        if patch.core_binary_path.is_some() || patch.core_extra_args.is_some() {
+            CoreManager::core_path()?;
+            CoreManager::global().run_core().await?;
+        }
         if socks_enabled.is_some() || http_enabled.is_some() {
             Config::generate()?;
             CoreManager::global().run_core().await?;
         }
-        if auto_launch.is_some() {
+        if patch.enable_dns_override.is_some()
+            || patch.dns_override.is_some()
+            || patch.disabled_rule_providers.is_some()
+            || patch.lan_authentication.is_some()
+        {
+            update_core_config().await?;
+        }
+        // 生成配置时写入 ipv6 和 bind-address
+        if patch.ip_version.is_some() {
+            Config::generate()?;
+            CoreManager::global().run_core().await?;
+        }
+        if let Some(on_top) = patch.window_always_on_top {
+            // 窗口隐藏在托盘或还没创建时，下次创建窗口再生效
+            if let Some(window) = handle::Handle::global().get_window() {
+                window.set_always_on_top(on_top)?;
+            }
+        }
+        if patch.fallback_profile_uid.is_some() {
+            *HEALTH_FAILURES.lock() = None;
+            timer::Timer::global().refresh_health_check()?;
+        }
+        if let Some(detect) = patch.enable_captive_portal_detect {
+            PORTAL_ASKED.store(false, Ordering::SeqCst);
+            if !detect {
+                sysopt::Sysopt::global().resume_after_portal()?;
+            }
+            timer::Timer::global().refresh_captive_portal()?;
+        }
+        match patch.timer_paused {
+            Some(true) => timer::Timer::global().pause(),
+            Some(false) => timer::Timer::global().resume()?,
+            None => {}
+        }
+        if patch.auto_launch_args.is_some() {
+            sysopt::Sysopt::global().reload_launch()?;
+        } else if auto_launch.is_some() {
             sysopt::Sysopt::global().update_launch()?;
         }
-        if system_proxy.is_some() || proxy_bypass.is_some() || port.is_some() {
+        if system_proxy.is_some()
+            || proxy_bypass.is_some()
+            || port.is_some()
+            || patch.system_proxy_mode.is_some()
+            || patch.ip_version.is_some()
+        {
             sysopt::Sysopt::global().update_sysproxy()?;
             sysopt::Sysopt::global().guard_proxy();
         }
@@ -227,18 +1202,25 @@ pub async fn patch_verge(patch: IVerge) -> Result<()> {
         if let Some(true) = patch.enable_proxy_guard {
             sysopt::Sysopt::global().guard_proxy();
         }
+        if let Some(false) = patch.kill_switch {
+            sysopt::Sysopt::global().release_kill_switch()?;
+        }
 
         if let Some(hotkeys) = patch.hotkeys {
             hotkey::Hotkey::global().update(hotkeys)?;
         }
 
-        if language.is_some() {
+        if language.is_some()
+            || patch.tray_profiles_limit.is_some()
+            || patch.tray_quick_actions.is_some()
+        {
             handle::Handle::update_systray()?;
         } else if system_proxy.is_some()
             || tun_mode.is_some()
             || common_tray_icon.is_some()
             || sysproxy_tray_icon.is_some()
             || tun_tray_icon.is_some()
+            || patch.tray_mode_icon.is_some()
         {
             handle::Handle::update_systray_part()?;
         }
@@ -252,6 +1234,9 @@ pub async fn patch_verge(patch: IVerge) -> Result<()> {
         Ok(()) => {
             Config::verge().apply();
             Config::verge().data().save_file()?;
+            if port.is_some() {
+                handle::Handle::notice_port_changed(get_running_port());
+            }
             Ok(())
         }
         Err(err) => {
@@ -261,6 +1246,239 @@ pub async fn patch_verge(patch: IVerge) -> Result<()> {
     }
 }
 
+/// 重置单个verge设置为默认值
+/// 再走一遍 `patch_verge` 使其生效，默认值为空的用空值清除
+pub async fn reset_verge_key(key: String) -> Result<serde_json::Value> {
+    let reset = |key: &str| -> Result<serde_json::Value> {
+        let verge = Config::verge();
+        let mut verge = verge.data();
+        let value = verge.reset_key(key)?;
+        verge.save_file()?;
+        Ok(value)
+    };
+    let value = reset(&key)?;
+
+    let patch_value = match value.is_null() {
+        true => empty_verge_value(&key),
+        false => Some(value.clone()),
+    };
+    if let Some(patch_value) = patch_value {
+        let patch = serde_json::json!({ key.as_str(): patch_value });
+        patch_verge(serde_json::from_value::<IVerge>(patch)?).await?;
+    }
+    // `patch_verge` 保存的是空值，改回默认的空
+    if value.is_null() {
+        reset(&key)?;
+    }
+
+    handle::Handle::refresh_verge();
+    Ok(value)
+}
+
+/// the empty value which clears the setting whose default is none
+/// so that `patch_verge` applies it, e.g. unregisters the hotkeys
+fn empty_verge_value(key: &str) -> Option<serde_json::Value> {
+    match key {
+        "system_proxy_bypass" => Some(serde_json::json!("")),
+        "hotkeys" => Some(serde_json::json!([])),
+        "dns_override" => Some(serde_json::json!({})),
+        "core_binary_path" => Some(serde_json::json!("")),
+        "core_extra_args" => Some(serde_json::json!([])),
+        "auto_launch_args" => Some(serde_json::json!([])),
+        "disabled_rule_providers" => Some(serde_json::json!([])),
+        "lan_authentication" => Some(serde_json::json!([])),
+        "fallback_profile_uid" => Some(serde_json::json!("")),
+        "timer_paused" => Some(serde_json::json!(false)),
+        "window_always_on_top" => Some(serde_json::json!(false)),
+        "enable_captive_portal_detect" => Some(serde_json::json!(false)),
+        _ => None,
+    }
+}
+
+/// 重置全部verge设置，不影响订阅和clash配置
+/// 原来的 `verge.yaml` 先备份，返回备份文件的路径，文件不存在时没有备份
+pub async fn reset_verge_settings() -> Result<Option<PathBuf>> {
+    let path = dirs::verge_path()?;
+    let backup = match path.exists() {
+        true => {
+            let backup = path.with_file_name(format!(
+                "verge.{}.yaml.bak",
+                chrono::Local::now().format("%Y%m%d%H%M%S")
+            ));
+            std::fs::copy(&path, &backup)
+                .with_context(|| format!("failed to backup \"{}\"", path.display()))?;
+            Some(backup)
+        }
+        false => None,
+    };
+
+    {
+        let verge = Config::verge();
+        let mut verge = verge.data();
+        // 保持当前的端口，免得和运行中的内核对不上
+        let port = verge.verge_mixed_port;
+        *verge = IVerge::template();
+        verge.verge_mixed_port = port.or(verge.verge_mixed_port);
+        verge.save_file()?;
+    }
+    match backup.as_ref() {
+        Some(backup) => {
+            log::info!(target: "app", "verge settings reset, backup at \"{}\"", backup.display())
+        }
+        None => log::info!(target: "app", "verge settings reset, no file to backup"),
+    }
+
+    // 没有经过 `patch_verge`，模板里为空的设置在这里生效
+    log_err!(timer::Timer::global().resume());
+    *HEALTH_FAILURES.lock() = None;
+    log_err!(timer::Timer::global().refresh_health_check());
+    PORTAL_ASKED.store(false, Ordering::SeqCst);
+    log_err!(sysopt::Sysopt::global().resume_after_portal());
+    log_err!(timer::Timer::global().refresh_captive_portal());
+    if let Some(window) = handle::Handle::global().get_window() {
+        log_err!(window.set_always_on_top(false));
+    }
+
+    log_err!(sysopt::Sysopt::global().reload_launch());
+    log_err!(sysopt::Sysopt::global().update_sysproxy());
+    log_err!(hotkey::Hotkey::global().update(vec![]));
+    log_err!(handle::Handle::update_systray());
+
+    Config::generate()?;
+    CoreManager::global().run_core().await?;
+    handle::Handle::refresh_clash();
+    handle::Handle::refresh_verge();
+    Ok(backup)
+}
+
+/// 修改profiles的配置，如切换当前订阅
+pub async fn patch_profiles_config(profiles: IProfiles) -> Result<()> {
+    Config::profiles().draft().patch_config(profiles)?;
+
+    match CoreManager::global().update_config().await {
+        Ok(_) => {
+            handle::Handle::refresh_clash();
+            Config::profiles().apply();
+            Config::profiles().data().save_file()?;
+            log_err!(handle::Handle::update_systray());
+            Ok(())
+        }
+        Err(err) => {
+            Config::profiles().discard();
+            log::error!(target: "app", "{err}");
+            Err(err)
+        }
+    }
+}
+
+/// 重命名订阅，uid不变，链和选择都不受影响
+pub fn rename_profile(uid: String, name: String) -> Result<()> {
+    Config::profiles().data().rename_item(&uid, &name)?;
+    log_err!(handle::Handle::update_systray());
+    Ok(())
+}
+
+/// 设置订阅的自动更新间隔，返回换算后的分钟数
+pub fn set_update_interval(uid: String, value: u64, unit: String) -> Result<u64> {
+    let minutes = PrfOption::interval_minutes(value, &unit)?;
+    Config::profiles()
+        .data()
+        .set_update_interval(&uid, minutes)?;
+    timer::Timer::global().refresh()?;
+    Ok(minutes)
+}
+
+/// 调整merge/script的应用顺序，在当前chain里时重新生成配置
+pub async fn bump_merge_order(uid: String, step: i32) -> Result<i32> {
+    let (order, in_chain) = {
+        let profiles = Config::profiles();
+        let mut profiles = profiles.data();
+        let order = profiles.bump_merge_order(&uid, step)?;
+        let in_chain = profiles
+            .current_chain()
+            .iter()
+            .any(|item| item.uid.as_ref() == Some(&uid));
+        (order, in_chain)
+    };
+    if in_chain {
+        update_core_config().await?;
+    }
+    Ok(order)
+}
+
+/// 切换当前订阅 (tray)
+pub fn switch_profile(uid: String) {
+    tauri::async_runtime::spawn(async move {
+        let profiles = IProfiles {
+            current: Some(uid),
+            ..IProfiles::default()
+        };
+        match patch_profiles_config(profiles).await {
+            Ok(_) => handle::Handle::refresh_profiles(),
+            Err(err) => handle::Handle::notice_message("set_config::error", format!("{err}")),
+        }
+    });
+}
+
+/// 修改订阅自己的chain，修改的是当前订阅时重新生成
+pub async fn patch_profile_chain<F>(base: String, patch: F) -> Result<()>
+where
+    F: FnOnce(&mut IProfiles) -> Result<()>,
+{
+    let is_current = {
+        let profiles = Config::profiles();
+        let mut profiles = profiles.data();
+        patch(&mut *profiles)?;
+        profiles.get_current() == Some(base)
+    };
+    if is_current {
+        update_core_config().await?;
+    }
+    Ok(())
+}
+
+/// 新建一个挂载到订阅上的merge，并启用它
+pub async fn create_merge_profile(base: String, name: Option<String>) -> Result<String> {
+    let base_name = {
+        let profiles = Config::profiles();
+        let profiles = profiles.latest();
+        let item = profiles.get_item(&base)?;
+        if !item.is_base() {
+            bail!("the profile \"uid:{base}\" can not be the base");
+        }
+        item.name.clone().unwrap_or_default()
+    };
+
+    let name = name.unwrap_or(format!("{base_name} Merge"));
+    let mut item = PrfItem::from_merge(name, "".into())?;
+    item.base = Some(base.clone());
+    let uid = item.uid.clone().unwrap_or_default();
+
+    let (own_chain, chain) = {
+        let profiles = Config::profiles();
+        let mut profiles = profiles.data();
+        profiles.append_item(item)?;
+        let mut chain = profiles.chain.clone().unwrap_or_default();
+        chain.push(uid.clone());
+        (profiles.has_own_chain(&base), chain)
+    };
+
+    // 订阅有自己的chain时全局chain对它不生效，加到订阅的chain里
+    if own_chain {
+        let new_uid = uid.clone();
+        let patch = |profiles: &mut IProfiles| profiles.chain_add(&base, new_uid, None);
+        patch_profile_chain(base.clone(), patch).await?;
+        return Ok(uid);
+    }
+
+    patch_profiles_config(IProfiles {
+        chain: Some(chain),
+        ..IProfiles::default()
+    })
+    .await?;
+    Ok(uid)
+}
+
 /// 更新某个profile
 /// 如果更新当前订阅就激活订阅
 pub async fn update_profile(uid: String, option: Option<PrfOption>) -> Result<()> {
@@ -275,20 +1493,50 @@ pub async fn update_profile(uid: String, option: Option<PrfOption>) -> Result<()
         } else if item.url.is_none() {
             bail!("failed to get the profile item url");
         } else {
-            Some((item.url.clone().unwrap(), item.option.clone()))
+            let cache = PrfCache::from_item(item);
+            Some((item.url.clone().unwrap(), item.option.clone(), cache))
         }
     };
 
     let should_update = match url_opt {
-        Some((url, opt)) => {
+        Some((url, opt, cache)) => {
             let merged_opt = PrfOption::merge(opt, option);
-            let item = PrfItem::from_url(&url, None, None, merged_opt).await?;
+            let result = download_profile(uid.clone(), &url, merged_opt, Some(cache)).await;
+            // 记录更新的结果，失败时前端显示标记
+            {
+                let error = result.as_ref().err().map(|err| format!("{err}"));
+                let profiles = Config::profiles();
+                let mut profiles = profiles.latest();
+                log_err!(profiles.set_update_result(&uid, error));
+            }
+            handle::Handle::refresh_profiles();
+
+            let item = match result {
+                // 内容没有变化，不用重载内核
+                Ok(None) => {
+                    let profiles = Config::profiles();
+                    let mut profiles = profiles.latest();
+                    return profiles.set_checked(&uid);
+                }
+                Ok(Some(item)) => item,
+                Err(err) => {
+                    // 记录校验失败的原因，保留原来的订阅内容
+                    if let Some(validation) = err.downcast_ref::<PrfValidation>() {
+                        let profiles = Config::profiles();
+                        let mut profiles = profiles.latest();
+                        log_err!(profiles.set_validation(&uid, validation.clone()));
+                        handle::Handle::refresh_profiles();
+                    }
+                    return Err(err);
+                }
+            };
 
+            let changed = item.file_data.is_some();
             let profiles = Config::profiles();
             let mut profiles = profiles.latest();
             profiles.update_item(uid.clone(), item)?;
 
-            Some(uid) == profiles.get_current()
+            changed && Some(uid) == profiles.get_current()
         }
         None => true,
     };
@@ -300,6 +1548,281 @@ pub async fn update_profile(uid: String, option: Option<PrfOption>) -> Result<()
     Ok(())
 }
 
+/// the result of `update_profile_now`
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateResult {
+    pub success: bool,
+    pub error: Option<String>,
+    /// the subscription-userinfo after updating
+    pub extra: Option<PrfExtra>,
+}
+
+/// 立即更新某个订阅，和定时器走同一个更新流程
+/// 成功后重新计时，避免刚更新完又被定时器更新
+pub async fn update_profile_now(uid: String) -> Result<UpdateResult> {
+    let result = update_profile(uid.clone(), None).await;
+    if result.is_ok() {
+        log_err!(timer::Timer::global().reset_task(&uid));
+    }
+
+    let extra = {
+        let profiles = Config::profiles();
+        let profiles = profiles.latest();
+        profiles.get_item(&uid)?.extra
+    };
+    Ok(UpdateResult {
+        success: result.is_ok(),
+        error: result.err().map(|err| format!("{err}")),
+        extra,
+    })
+}
+
+/// the payload of `verge://profile-download`
+/// `total` is none if the server gives no content length
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    /// the uid of the profile, or the url when importing
+    pub key: String,
+    /// `start` / `progress` / `done` / `error`
+    pub stage: &'static str,
+    pub received: u64,
+    pub total: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// 下载远程订阅，并通知前端下载进度
+/// 带上`cache`时，内容没变化返回none
+pub async fn download_profile(
+    key: String,
+    url: &str,
+    option: Option<PrfOption>,
+    cache: Option<PrfCache>,
+) -> Result<Option<PrfItem>> {
+    use tokio::time::{Duration, Instant};
+
+    let emit = |stage, received, total, error| {
+        let progress = DownloadProgress {
+            key: key.clone(),
+            stage,
+            received,
+            total,
+            error,
+        };
+        handle::Handle::emit("verge://profile-download", progress);
+    };
+
+    emit("start", 0, None, None);
+    let mut last = Instant::now();
+    let (mut received, mut total) = (0, None);
+    let result = PrfItem::from_url_cached(url, None, None, option, cache.as_ref(), |r, t| {
+        (received, total) = (r, t);
+        // 限制事件的频率
+        if last.elapsed() >= Duration::from_millis(100) {
+            last = Instant::now();
+            emit("progress", r, t, None);
+        }
+    })
+    .await;
+
+    match &result {
+        Ok(_) => emit("done", received, total, None),
+        Err(err) => emit("error", received, total, Some(err.to_string())),
+    }
+    result
+}
+
+/// 同时更新的订阅数
+const UPDATE_ALL_CONCURRENCY: usize = 3;
+
+static UPDATE_ALL_RUNNING: AtomicBool = AtomicBool::new(false);
+static UPDATE_ALL_CANCELED: AtomicBool = AtomicBool::new(false);
+
+/// the payload of `verge://update-all-progress`
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateProgress {
+    pub uid: String,
+    pub name: Option<String>,
+    /// `ok` / `failed` / `canceled`
+    pub status: &'static str,
+    pub error: Option<String>,
+    pub finished: usize,
+    pub total: usize,
+}
+
+/// the payload of `verge://update-all-finished`
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct UpdateSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub canceled: usize,
+    pub total: usize,
+}
+
+/// 更新全部的远程订阅
+/// 每个订阅的结果通过事件通知前端，失败的订阅保留原来的内容
+pub async fn update_all_profiles() -> Result<UpdateSummary> {
+    if UPDATE_ALL_RUNNING.swap(true, Ordering::SeqCst) {
+        bail!("the profiles are being updated");
+    }
+    UPDATE_ALL_CANCELED.store(false, Ordering::SeqCst);
+
+    let summary = update_all_profiles_inner().await;
+    UPDATE_ALL_RUNNING.store(false, Ordering::SeqCst);
+
+    handle::Handle::refresh_profiles();
+    handle::Handle::emit("verge://update-all-finished", summary.clone());
+    Ok(summary)
+}
+
+/// cancel the profiles not started yet, return false if nothing is updating
+pub fn cancel_update_all() -> bool {
+    let running = UPDATE_ALL_RUNNING.load(Ordering::SeqCst);
+    if running {
+        UPDATE_ALL_CANCELED.store(true, Ordering::SeqCst);
+    }
+    running
+}
+
+async fn update_all_profiles_inner() -> UpdateSummary {
+    let items = {
+        let profiles = Config::profiles();
+        let profiles = profiles.latest();
+        profiles
+            .get_items()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter(|item| item.itype.as_deref() == Some("remote") && item.url.is_some())
+                    .filter_map(|item| Some((item.uid.clone()?, item.name.clone())))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+
+    let total = items.len();
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(UPDATE_ALL_CONCURRENCY));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (uid, name) in items {
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            if UPDATE_ALL_CANCELED.load(Ordering::SeqCst) {
+                return (uid, name, None);
+            }
+            let result = update_profile(uid.clone(), None).await;
+            (uid, name, Some(result))
+        });
+    }
+
+    let mut summary = UpdateSummary {
+        total,
+        ..UpdateSummary::default()
+    };
+    let mut finished = 0;
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((uid, name, result)) = joined else {
+            continue;
+        };
+        finished += 1;
+
+        let (status, error) = match result {
+            Some(Ok(())) => {
+                summary.succeeded += 1;
+                ("ok", None)
+            }
+            Some(Err(err)) => {
+                log::error!(target: "app", "failed to update the profile {uid}: {err}");
+                summary.failed += 1;
+                ("failed", Some(err.to_string()))
+            }
+            None => {
+                summary.canceled += 1;
+                ("canceled", None)
+            }
+        };
+
+        let progress = UpdateProgress {
+            uid,
+            name,
+            status,
+            error,
+            finished,
+            total,
+        };
+        handle::Handle::emit("verge://update-all-progress", progress);
+    }
+    summary
+}
+
+/// 同时导入的订阅数
+const IMPORT_BATCH_CONCURRENCY: usize = 3;
+
+/// the result of each url of `import_profiles_batch`
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportResult {
+    pub url: String,
+    /// the uid of the new profile
+    pub uid: Option<String>,
+    pub error: Option<String>,
+}
+
+/// 并发下载多个订阅，一个失败不影响其他的
+/// 按输入的顺序添加，结果也按输入的顺序返回
+pub async fn import_profiles_batch(urls: Vec<String>) -> Vec<ImportResult> {
+    let mut urls = urls
+        .iter()
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+        .collect::<Vec<String>>();
+    // 重复的只导入一次
+    let mut seen = std::collections::HashSet::new();
+    urls.retain(|url| seen.insert(url.clone()));
+
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(IMPORT_BATCH_CONCURRENCY));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (index, url) in urls.iter().enumerate() {
+        let semaphore = semaphore.clone();
+        let url = url.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            (index, PrfItem::from_url(&url, None, None, None).await)
+        });
+    }
+
+    let mut items = urls.iter().map(|_| None).collect::<Vec<_>>();
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, result)) = joined {
+            items[index] = Some(result);
+        }
+    }
+
+    let mut results = vec![];
+    for (url, item) in urls.into_iter().zip(items) {
+        let result = match item {
+            Some(Ok(item)) => {
+                let uid = item.uid.clone();
+                let profiles = Config::profiles();
+                let mut profiles = profiles.data();
+                profiles.append_item(item).map(|_| uid)
+            }
+            Some(Err(err)) => Err(err),
+            None => Err(anyhow::anyhow!("the import task is aborted")),
+        };
+        let (uid, error) = match result {
+            Ok(uid) => (uid, None),
+            Err(err) => {
+                log::error!(target: "app", "failed to import {url}: {err}");
+                (None, Some(format!("{err}")))
+            }
+        };
+        results.push(ImportResult { url, uid, error });
+    }
+
+    handle::Handle::refresh_profiles();
+    log_err!(handle::Handle::update_systray());
+    results
+}
+
 /// 更新订阅
 async fn update_core_config() -> Result<()> {
     match CoreManager::global().update_config().await {
@@ -315,41 +1838,84 @@ async fn update_core_config() -> Result<()> {
     }
 }
 
-/// copy env variable
-pub fn copy_clash_env(app_handle: &AppHandle) {
-    let port = { Config::verge().latest().verge_mixed_port.unwrap_or(7897) };
-    let http_proxy = format!("http://127.0.0.1:{}", port);
-    let socks5_proxy = format!("socks5://127.0.0.1:{}", port);
+/// 从备份中恢复
+/// 重新加载配置后重启内核、系统代理等，无需手动重启应用
+pub async fn restore_backup(path: PathBuf, force: bool) -> Result<()> {
+    backup::restore_backup(&path, force)?;
+    Config::reload()?;
 
-    let sh =
-        format!("export https_proxy={http_proxy} http_proxy={http_proxy} all_proxy={socks5_proxy}");
-    let cmd: String = format!("set http_proxy={http_proxy}\r\nset https_proxy={http_proxy}");
-    let ps: String = format!("$env:HTTP_PROXY=\"{http_proxy}\"; $env:HTTPS_PROXY=\"{http_proxy}\"");
+    CoreManager::global().run_core().await?;
+    sysopt::Sysopt::global().update_sysproxy()?;
+    sysopt::Sysopt::global().guard_proxy();
+    log_err!(sysopt::Sysopt::global().update_launch());
 
-    let mut cliboard = app_handle.clipboard_manager();
+    let hotkeys = { Config::verge().latest().hotkeys.clone() };
+    log_err!(hotkey::Hotkey::global().update(hotkeys.unwrap_or_default()));
+    log_err!(timer::Timer::global().refresh());
+
+    handle::Handle::refresh_clash();
+    handle::Handle::refresh_verge();
+    handle::Handle::refresh_profiles();
+    log_err!(handle::Handle::update_systray());
+    Ok(())
+}
 
+/// copy env variable
+pub fn copy_clash_env(app_handle: &AppHandle) -> Result<()> {
     let env_type = { Config::verge().latest().env_type.clone() };
-    let env_type = match env_type {
-        Some(env_type) => env_type,
-        None => {
-            #[cfg(not(target_os = "windows"))]
-            let default = "bash";
-            #[cfg(target_os = "windows")]
-            let default = "powershell";
+    let shell = match env_type {
+        Some(env_type) => env_type.parse::<ShellKind>()?,
+        None => ShellKind::default(),
+    };
+    copy_proxy_env(app_handle, shell)
+}
 
-            default.to_string()
+/// generate the proxy env statements for the shell
+/// use the live mixed port, and the bind address when lan is allowed
+pub fn get_proxy_env(shell: ShellKind) -> String {
+    let port = Config::verge()
+        .latest()
+        .verge_mixed_port
+        .unwrap_or_else(|| Config::clash().data().get_mixed_port());
+
+    // 生成配置时按ip版本写入了 bind-address，从运行的配置里读
+    let bind_address = {
+        let runtime = Config::runtime();
+        let runtime = runtime.latest();
+        let config = runtime.config.as_ref();
+        let allow_lan = config
+            .and_then(|c| c.get("allow-lan"))
+            .and_then(|v| v.as_bool());
+        let bind_address = config
+            .and_then(|c| c.get("bind-address"))
+            .and_then(|v| v.as_str());
+        match (allow_lan, bind_address.map(str::trim)) {
+            (Some(true), Some(addr)) if !matches!(addr, "" | "*" | "0.0.0.0" | "::") => {
+                Some(addr.to_string())
+            }
+            _ => None,
         }
     };
-    match env_type.as_str() {
-        "bash" => cliboard.write_text(sh).unwrap_or_default(),
-        "cmd" => cliboard.write_text(cmd).unwrap_or_default(),
-        "powershell" => cliboard.write_text(ps).unwrap_or_default(),
-        _ => log::error!(target: "app", "copy_clash_env: Invalid env type! {env_type}"),
-    };
+    let host = bind_address.unwrap_or_else(|| resolve::loopback_host().into());
+
+    let bypass = { Config::verge().latest().system_proxy_bypass.clone() };
+    let bypass = bypass
+        .filter(|b| !b.is_empty())
+        .unwrap_or(sysopt::DEFAULT_BYPASS.into());
+
+    let no_proxy = proxy_env::no_proxy_from_bypass(&bypass);
+    proxy_env::render(shell, &host, port, &no_proxy)
 }
 
-pub async fn test_delay(url: String) -> Result<u32> {
-    use tokio::time::{Duration, Instant};
+/// write the proxy env statements to the clipboard
+pub fn copy_proxy_env(app_handle: &AppHandle, shell: ShellKind) -> Result<()> {
+    let mut clipboard = app_handle.clipboard_manager();
+    clipboard.write_text(get_proxy_env(shell))?;
+    Ok(())
+}
+
+/// 走本地代理端口的client，tun模式下直连
+fn self_proxy_client() -> reqwest::ClientBuilder {
     let mut builder = reqwest::ClientBuilder::new().use_rustls_tls().no_proxy();
 
     let port = Config::verge()
@@ -358,7 +1924,7 @@ pub async fn test_delay(url: String) -> Result<u32> {
         .unwrap_or(Config::clash().data().get_mixed_port());
     let tun_mode = Config::verge().latest().enable_tun_mode.unwrap_or(false);
 
-    let proxy_scheme = format!("http://127.0.0.1:{port}");
+    let proxy_scheme = format!("http://{}:{port}", resolve::loopback_url_host());
 
     if !tun_mode {
         if let Ok(proxy) = reqwest::Proxy::http(&proxy_scheme) {
@@ -371,8 +1937,13 @@ pub async fn test_delay(url: String) -> Result<u32> {
             builder = builder.proxy(proxy);
         }
     }
+    builder
+}
 
-    let request = builder
+pub async fn test_delay(url: String) -> Result<u32> {
+    use tokio::time::{Duration, Instant};
+
+    let request = self_proxy_client()
         .timeout(Duration::from_millis(10000))
         .build()?
         .get(url).header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.0.0");
@@ -386,3 +1957,83 @@ pub async fn test_delay(url: String) -> Result<u32> {
         Ok(10000u32)
     }
 }
+
+static GEODATA_UPDATING: AtomicBool = AtomicBool::new(false);
+
+/// 下载最新的geodata，全部校验通过后才替换并重启内核
+/// 任何一个失败都保留原有的文件，返回更新的时间
+pub async fn update_geodata() -> Result<usize> {
+    use tokio::time::Duration;
+
+    if GEODATA_UPDATING.swap(true, Ordering::SeqCst) {
+        bail!("the geodata is updating");
+    }
+
+    let result = async {
+        let urls = { Config::verge().latest().geodata_urls.clone() };
+        let client = self_proxy_client()
+            .timeout(Duration::from_secs(120))
+            .build()?;
+
+        let mut files = vec![];
+        for (name, url) in geodata::geodata_files(urls.as_ref()) {
+            log::info!(target: "app", "downloading \"{name}\" from {url}");
+
+            let resp = client.get(&url).send().await?;
+            let status = resp.status();
+            if !status.is_success() {
+                bail!("failed to download \"{name}\" with status \"{status}\"");
+            }
+            let data = resp.bytes().await?.to_vec();
+            geodata::validate(name, &data)?;
+            files.push((name, data));
+        }
+
+        geodata::replace_files(&dirs::app_home_dir()?, &files)?;
+
+        let now = chrono::Local::now().timestamp() as usize;
+        Config::verge().draft().patch_config(IVerge {
+            geodata_updated: Some(now),
+            ..IVerge::default()
+        });
+        Config::verge().apply();
+        Config::verge().data().save_file()?;
+        handle::Handle::refresh_verge();
+
+        CoreManager::global().run_core().await?;
+        Ok(now)
+    }
+    .await;
+
+    GEODATA_UPDATING.store(false, Ordering::SeqCst);
+    result
+}
+
+#[test]
+fn test_health_verdict() {
+    use HealthVerdict::*;
+
+    let last = ("a".to_string(), 1);
+    let verdict =
+        |node_ok, direct_ok, current| health_verdict(node_ok, direct_ok, Some(&last), current);
+    assert_eq!(verdict(true, false, "a"), Healthy);
+    assert_eq!(verdict(false, false, "a"), Offline);
+    assert_eq!(verdict(false, true, "a"), Failing(2));
+    // another profile is counted from the beginning
+    assert_eq!(verdict(false, true, "b"), Failing(1));
+    assert_eq!(health_verdict(false, true, None, "a"), Failing(1));
+    let last = ("a".to_string(), HEALTH_CHECK_FAIL_TIMES - 1);
+    assert_eq!(health_verdict(false, true, Some(&last), "a"), Fallback);
+
+    let nodes = (0..25).map(|i| i.to_string()).collect::<Vec<_>>();
+    assert_eq!(sample_nodes(&nodes[..3], 10, 7), nodes[..3].to_vec());
+    let sampled = sample_nodes(&nodes, 10, 0);
+    assert_eq!(sampled.len(), 10);
+    assert_eq!(sampled[..3], ["0", "2", "5"]);
+    // the next round tests the others
+    assert_eq!(sample_nodes(&nodes, 10, 1)[..3], ["1", "3", "6"]);
+    let mut unique = sample_nodes(&nodes, 10, 24);
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), 10);
+}
@@ -10,10 +10,13 @@ mod enhance;
 mod feat;
 mod utils;
 
-use crate::utils::{init, resolve, server};
-use tauri::{api, SystemTray};
+use crate::utils::{dirs, init, resolve, server};
+use tauri::SystemTray;
 
 fn main() -> std::io::Result<()> {
+    // 锁文件在 app_home_dir 下，先确定是否便携版
+    let _ = dirs::init_portable_flag();
+
     // 单例检测
     if server::check_singleton().is_err() {
         println!("app exists");
@@ -25,6 +28,12 @@ fn main() -> std::io::Result<()> {
 
     crate::log_err!(init::init_config());
 
+    // 要在创建 webview 之前设置
+    #[cfg(target_os = "linux")]
+    if config::Config::verge().latest().software_rendering == Some(true) {
+        std::env::set_var("WEBKIT_DISABLE_COMPOSITING_MODE", "1");
+    }
+
     #[allow(unused_mut)]
     let mut builder = tauri::Builder::default()
         .system_tray(SystemTray::new())
@@ -36,53 +45,138 @@ fn main() -> std::io::Result<()> {
         .invoke_handler(tauri::generate_handler![
             // common
             cmds::get_sys_proxy,
+            cmds::detect_existing_proxy,
+            cmds::enable_system_proxy,
+            cmds::disable_system_proxy,
+            cmds::get_proxy_env,
+            cmds::copy_proxy_env,
             cmds::open_app_dir,
+            cmds::open_config_dir,
             cmds::open_logs_dir,
+            cmds::read_log_tail,
+            cmds::start_log_stream,
+            cmds::stop_log_stream,
             cmds::open_web_url,
             cmds::open_core_dir,
             cmds::get_portable_flag,
             // cmds::kill_sidecar,
             cmds::restart_sidecar,
+            cmds::get_core_crash_state,
+            cmds::flush_fakeip,
             cmds::grant_permission,
+            cmds::check_tun_capability,
+            cmds::is_elevated,
+            cmds::relaunch_as_admin,
+            cmds::update_geodata,
+            cmds::export_hotkeys,
+            cmds::import_hotkeys,
+            cmds::set_tun_mode,
+            cmds::set_fallback_profile,
+            cmds::set_tun_auto_route,
+            cmds::set_tun_strict_route,
+            cmds::set_tun_dns_hijack,
+            cmds::set_tun_stack,
             // clash
             cmds::get_clash_info,
+            cmds::get_controller_info,
+            cmds::set_controller_secret,
             cmds::get_clash_logs,
+            cmds::get_core_logs,
             cmds::patch_clash_config,
             cmds::change_clash_core,
+            cmds::get_core_version,
             cmds::get_runtime_config,
             cmds::get_runtime_yaml,
+            cmds::dry_run_profile,
             cmds::get_runtime_exists,
             cmds::get_runtime_logs,
+            cmds::match_rule,
+            cmds::get_running_port,
+            cmds::get_startup_script_result,
+            cmds::get_core_memory,
+            cmds::get_connection_stats,
+            cmds::refresh_tray_icon,
+            cmds::get_core_log_level,
+            cmds::set_core_log_level,
+            cmds::get_dns_config,
+            cmds::patch_dns_config,
             cmds::uwp::invoke_uwp_tool,
             // verge
             cmds::get_verge_config,
             cmds::patch_verge_config,
+            cmds::reset_verge_key,
+            cmds::reset_verge_settings,
             cmds::test_delay,
             cmds::get_app_dir,
             cmds::copy_icon_file,
             cmds::download_icon_cache,
             cmds::open_devtools,
             cmds::exit_app,
+            cmds::export_backup,
+            cmds::restore_backup,
             // cmds::update_hotkeys,
             // profile
             cmds::get_profiles,
+            cmds::search_profiles,
             cmds::enhance_profiles,
             cmds::patch_profiles_config,
+            cmds::add_profile_chain,
+            cmds::remove_profile_chain,
+            cmds::reorder_profile_chain,
             cmds::view_profile,
             cmds::patch_profile,
+            cmds::rename_profile,
+            cmds::set_update_interval,
+            cmds::raise_merge_order,
+            cmds::lower_merge_order,
             cmds::create_profile,
+            cmds::create_merge_profile,
             cmds::import_profile,
+            cmds::import_profiles_batch,
+            cmds::probe_subscription,
             cmds::reorder_profile,
             cmds::update_profile,
+            cmds::update_profile_now,
+            cmds::retry_update,
+            cmds::update_all_profiles,
+            cmds::cancel_update_all,
             cmds::delete_profile,
             cmds::read_profile_file,
+            cmds::get_profile_userinfo,
             cmds::save_profile_file,
             // service mode
             cmds::service::check_service,
             cmds::service::install_service,
             cmds::service::uninstall_service,
             // clash api
-            cmds::clash_api_get_proxy_delay
+            cmds::clash_api_get_proxy_delay,
+            cmds::test_proxy_chain,
+            cmds::get_mode,
+            cmds::set_mode,
+            cmds::list_providers,
+            cmds::refresh_provider,
+            cmds::list_rule_providers,
+            cmds::set_rule_provider_enabled,
+            cmds::get_lan_auth_users,
+            cmds::add_lan_auth_user,
+            cmds::remove_lan_auth_user,
+            cmds::get_connections,
+            cmds::close_connection,
+            cmds::get_local_api_token,
+            cmds::reset_local_api_token,
+            cmds::is_autostart_enabled,
+            cmds::set_auto_launch,
+            cmds::set_always_on_top,
+            cmds::set_software_rendering,
+            cmds::pause_timers,
+            cmds::resume_timers,
+            cmds::is_timer_paused,
+            cmds::check_ports,
+            cmds::set_proxy_ports,
+            cmds::is_port_available,
+            cmds::get_startup_timings,
+            cmds::verify_resources,
+            cmds::repair_app_dirs
         ]);
 
     #[cfg(target_os = "macos")]
@@ -113,9 +207,11 @@ fn main() -> std::io::Result<()> {
         tauri::RunEvent::ExitRequested { api, .. } => {
             api.prevent_exit();
         }
+        tauri::RunEvent::Exit => {
+            resolve::on_exit(app_handle);
+        }
         tauri::RunEvent::Updater(tauri::UpdaterEvent::Downloaded) => {
-            resolve::resolve_reset();
-            api::process::kill_children();
+            resolve::on_exit(app_handle);
         }
         tauri::RunEvent::WindowEvent { label, event, .. } => {
             if label == "main" {
@@ -123,8 +219,11 @@ fn main() -> std::io::Result<()> {
                     tauri::WindowEvent::Destroyed => {
                         let _ = resolve::save_window_size_position(app_handle, true);
                     }
-                    tauri::WindowEvent::CloseRequested { .. } => {
+                    tauri::WindowEvent::CloseRequested { api, .. } => {
                         let _ = resolve::save_window_size_position(app_handle, true);
+                        if resolve::handle_close_requested(app_handle) {
+                            api.prevent_close();
+                        }
                     }
                     tauri::WindowEvent::Moved(_) | tauri::WindowEvent::Resized(_) => {
                         let _ = resolve::save_window_size_position(app_handle, false);
@@ -0,0 +1,242 @@
+use crate::utils::{dirs, help, resolve::VERSION};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    fs,
+    io::{self, Seek, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+pub const BACKUP_MANIFEST: &str = "manifest.yaml";
+
+/// ### `manifest.yaml` schema of the backup archive
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct BackupManifest {
+    /// the app version which created this backup
+    pub version: String,
+
+    /// created time
+    pub created: usize,
+
+    /// whether the logs and the icon cache are packed
+    pub include_logs: bool,
+}
+
+/// pack the profiles and the config files into a zip archive
+/// the logs and the icon cache are skipped unless `include_logs` is true
+pub fn create_backup(dest: &Path, include_logs: bool) -> Result<()> {
+    let app_dir = dirs::app_home_dir()?;
+
+    let mut entries = vec![
+        dirs::clash_path()?,
+        dirs::verge_path()?,
+        dirs::lan_auth_path()?,
+        dirs::profiles_path()?,
+        dirs::app_profiles_dir()?,
+        app_dir.join("icons"),
+    ];
+    let mut skip = vec![];
+    if include_logs {
+        entries.push(dirs::app_logs_dir()?);
+    } else {
+        skip.push(app_dir.join("icons").join("cache"));
+    }
+    pack_backup(&app_dir, &entries, &skip, dest, include_logs)
+}
+
+/// pack the entries with the names relative to `app_dir`
+fn pack_backup(
+    app_dir: &Path,
+    entries: &[PathBuf],
+    skip: &[PathBuf],
+    dest: &Path,
+    include_logs: bool,
+) -> Result<()> {
+    let file = fs::File::create(dest)
+        .with_context(|| format!("failed to create the backup file \"{}\"", dest.display()))?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let manifest = BackupManifest {
+        version: VERSION.get().cloned().unwrap_or("unknown".into()),
+        created: chrono::Local::now().timestamp() as usize,
+        include_logs,
+    };
+    zip.start_file(BACKUP_MANIFEST, options)?;
+    zip.write_all(serde_yaml::to_string(&manifest)?.as_bytes())?;
+
+    for entry in entries.iter() {
+        add_path(&mut zip, app_dir, entry, skip, options)?;
+    }
+
+    zip.finish().context("failed to write the backup file")?;
+    log::info!(target: "app", "backup created at \"{}\"", dest.display());
+    Ok(())
+}
+
+/// extract the backup archive into the app dir
+/// refuse the backup created by a newer version unless `force` is true
+pub fn restore_backup(src: &Path, force: bool) -> Result<BackupManifest> {
+    let app_dir = dirs::app_home_dir()?;
+    // 局域网认证的明文只给自己读
+    let private = [dirs::lan_auth_path()?];
+    unpack_backup(src, &app_dir, &private, force)
+}
+
+/// extract the archive into `app_dir`, the `private` files are only readable by the owner
+fn unpack_backup(
+    src: &Path,
+    app_dir: &Path,
+    private: &[PathBuf],
+    force: bool,
+) -> Result<BackupManifest> {
+    let file = fs::File::open(src)
+        .with_context(|| format!("failed to open the backup file \"{}\"", src.display()))?;
+    let mut archive = ZipArchive::new(file).context("the backup file is not a valid zip")?;
+
+    let manifest: BackupManifest = {
+        let entry = archive
+            .by_name(BACKUP_MANIFEST)
+            .context("the backup manifest is not found")?;
+        serde_yaml::from_reader(entry).context("failed to parse the backup manifest")?
+    };
+
+    if let Some(version) = VERSION.get() {
+        if compare_version(&manifest.version, version) == Ordering::Greater && !force {
+            bail!(
+                "the backup is created by a newer version v{} (current v{version})",
+                manifest.version
+            );
+        }
+    }
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        // skip the entry which escapes the app dir
+        let name = match entry.enclosed_name() {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        if name == Path::new(BACKUP_MANIFEST) {
+            continue;
+        }
+
+        let path = app_dir.join(name);
+        if entry.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = match private.contains(&path) {
+            true => help::create_private_file(&path)?,
+            false => fs::File::create(&path)
+                .with_context(|| format!("failed to create the file \"{}\"", path.display()))?,
+        };
+        io::copy(&mut entry, &mut out)?;
+    }
+
+    log::info!(target: "app", "backup restored from \"{}\"", src.display());
+    Ok(manifest)
+}
+
+/// compare the version string like `1.6.2`
+/// the pre-release suffix is ignored
+fn compare_version(a: &str, b: &str) -> Ordering {
+    let parse = |v: &str| {
+        v.trim_start_matches('v')
+            .split('-')
+            .next()
+            .unwrap_or("")
+            .split('.')
+            .map(|s| s.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<u64>>()
+    };
+    parse(a).cmp(&parse(b))
+}
+
+/// add the file or the dir (recursively) to the archive
+/// the entry name is the path relative to `base`
+fn add_path<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    base: &Path,
+    path: &Path,
+    skip: &[PathBuf],
+    options: FileOptions,
+) -> Result<()> {
+    if !path.exists() || skip.iter().any(|s| s == path) {
+        return Ok(());
+    }
+
+    let name = path
+        .strip_prefix(base)?
+        .to_string_lossy()
+        .replace('\\', "/");
+
+    if path.is_dir() {
+        zip.add_directory(name, options)?;
+        for entry in fs::read_dir(path)?.flatten() {
+            add_path(zip, base, &entry.path(), skip, options)?;
+        }
+    } else {
+        let data = fs::read(path)
+            .with_context(|| format!("failed to read the file \"{}\"", path.display()))?;
+        zip.start_file(name, options)?;
+        zip.write_all(&data)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compare_version() {
+    assert_eq!(compare_version("1.6.2", "1.6.2"), Ordering::Equal);
+    assert_eq!(compare_version("v1.6.2", "1.6.2"), Ordering::Equal);
+    assert_eq!(compare_version("1.6.10", "1.6.2"), Ordering::Greater);
+    assert_eq!(compare_version("1.7.0-alpha", "1.6.2"), Ordering::Greater);
+    assert_eq!(compare_version("1.6.2-alpha", "1.6.2"), Ordering::Equal);
+    assert_eq!(compare_version("1.5", "1.6.2"), Ordering::Less);
+    assert_eq!(compare_version("unknown", "1.6.2"), Ordering::Less);
+}
+
+#[test]
+fn test_backup_round_trip() {
+    let root = std::env::temp_dir().join(format!("clash-verge-backup-{}", std::process::id()));
+    let app_dir = root.join("app");
+    let restored = root.join("restored");
+    fs::create_dir_all(app_dir.join("profiles")).unwrap();
+
+    let files: [(&str, &[u8]); 4] = [
+        ("verge.yaml", b"theme_mode: dark\nverge_mixed_port: 7897\n"),
+        ("profiles.yaml", b"current: rAbc\nitems: []\n"),
+        (
+            "profiles/rAbc.yaml",
+            b"proxies: []\r\n# \xe4\xb8\xad\xe6\x96\x87\n",
+        ),
+        (
+            "profiles/sXyz.js",
+            b"function main(params) {\n  return params;\n}\n",
+        ),
+    ];
+    for (name, data) in files {
+        fs::write(app_dir.join(name), data).unwrap();
+    }
+
+    let entries = vec![
+        app_dir.join("verge.yaml"),
+        app_dir.join("profiles.yaml"),
+        app_dir.join("profiles"),
+    ];
+    let dest = root.join("backup.zip");
+    pack_backup(&app_dir, &entries, &[], &dest, false).unwrap();
+
+    let manifest = unpack_backup(&dest, &restored, &[], false).unwrap();
+    assert!(!manifest.include_logs);
+    for (name, data) in files {
+        assert_eq!(fs::read(restored.join(name)).unwrap(), data, "{name}");
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
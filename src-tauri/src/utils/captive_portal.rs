@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+/// the endpoint replies 204 directly, never redirects
+pub const CAPTIVE_PORTAL_URL: &str = "http://cp.cloudflare.com/";
+
+const DETECT_TIMEOUT_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Online,
+    /// the request is redirected or replaced by the login page
+    CaptivePortal,
+    /// 网络不通，也可能是认证页面还没弹出
+    Offline,
+}
+
+/// request the endpoint directly, bypass the system proxy
+pub async fn detect() -> Connectivity {
+    let client = match reqwest::ClientBuilder::new()
+        .no_proxy()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(Duration::from_secs(DETECT_TIMEOUT_SECS))
+        .build()
+    {
+        Ok(client) => client,
+        Err(_) => return Connectivity::Offline,
+    };
+
+    let resp = match client.get(CAPTIVE_PORTAL_URL).send().await {
+        Ok(resp) => resp,
+        Err(err) => {
+            log::debug!(target: "app", "failed to detect the captive portal, {err}");
+            return Connectivity::Offline;
+        }
+    };
+    let status = resp.status().as_u16();
+    let body = resp.bytes().await.unwrap_or_default();
+    classify(status, &body)
+}
+
+/// some networks turn the 204 into an empty 200, it is still online
+pub fn classify(status: u16, body: &[u8]) -> Connectivity {
+    match status {
+        204 => Connectivity::Online,
+        200 if body.iter().all(|b| b.is_ascii_whitespace()) => Connectivity::Online,
+        _ => Connectivity::CaptivePortal,
+    }
+}
+
+#[test]
+fn test_classify_connectivity() {
+    assert_eq!(classify(204, b""), Connectivity::Online);
+    assert_eq!(classify(200, b"\r\n"), Connectivity::Online);
+    assert_eq!(
+        classify(200, b"<html>login</html>"),
+        Connectivity::CaptivePortal
+    );
+    assert_eq!(classify(302, b""), Connectivity::CaptivePortal);
+    assert_eq!(classify(511, b""), Connectivity::CaptivePortal);
+}
@@ -1,7 +1,7 @@
 use crate::core::handle;
 use anyhow::Result;
 use once_cell::sync::OnceCell;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tauri::{
     api::path::{data_dir, resource_dir},
     Env,
@@ -24,9 +24,7 @@ pub fn init_portable_flag() -> Result<()> {
 
     let app_exe = current_exe()?;
     if let Some(dir) = app_exe.parent() {
-        let dir = PathBuf::from(dir).join(".config/PORTABLE");
-
-        if dir.exists() {
+        if is_portable_dir(dir) {
             PORTABLE_FLAG.get_or_init(|| true);
         }
     }
@@ -34,6 +32,16 @@ pub fn init_portable_flag() -> Result<()> {
     Ok(())
 }
 
+/// 程序目录下有 `.config/PORTABLE` 就是便携版
+fn is_portable_dir(app_dir: &Path) -> bool {
+    app_dir.join(".config/PORTABLE").exists()
+}
+
+/// the home dir of the portable app, next to the exe
+fn portable_home_dir(app_dir: &Path) -> PathBuf {
+    app_dir.join(".config").join(APP_ID)
+}
+
 /// get the verge app home dir
 pub fn app_home_dir() -> Result<PathBuf> {
     use tauri::utils::platform::current_exe;
@@ -45,7 +53,7 @@ pub fn app_home_dir() -> Result<PathBuf> {
         let app_dir = app_exe
             .parent()
             .ok_or(anyhow::anyhow!("failed to get the portable app dir"))?;
-        return Ok(PathBuf::from(app_dir).join(".config").join(APP_ID));
+        return Ok(portable_home_dir(app_dir));
     }
 
     Ok(data_dir()
@@ -92,6 +100,27 @@ pub fn clash_pid_path() -> Result<PathBuf> {
     Ok(app_home_dir()?.join("clash.pid"))
 }
 
+/// record the system proxy set by the app
+pub fn sysproxy_marker_path() -> Result<PathBuf> {
+    Ok(app_home_dir()?.join("sysproxy-marker.yaml"))
+}
+
+/// the pid of the primary instance, created exclusively on startup
+/// then the port which its embed server is listening on
+pub fn singleton_lock_path() -> Result<PathBuf> {
+    Ok(app_home_dir()?.join("singleton.lock"))
+}
+
+/// the token of the local api
+pub fn local_api_token_path() -> Result<PathBuf> {
+    Ok(app_home_dir()?.join("local-api.token"))
+}
+
+/// the passwords of the lan authentication, `verge.yaml` only keeps the hashes
+pub fn lan_auth_path() -> Result<PathBuf> {
+    Ok(app_home_dir()?.join("lan-auth.yaml"))
+}
+
 #[cfg(not(target_os = "windows"))]
 pub fn service_path() -> Result<PathBuf> {
     Ok(app_resources_dir()?.join("clash-verge-service"))
@@ -123,3 +152,24 @@ pub fn path_to_str(path: &PathBuf) -> Result<&str> {
         .ok_or(anyhow::anyhow!("failed to get path from {:?}", path))?;
     Ok(path_str)
 }
+
+#[test]
+fn test_app_dirs() {
+    // 测试里没有初始化便携标记，用的是系统的数据目录
+    let home = app_home_dir().unwrap();
+    assert_eq!(home, data_dir().unwrap().join(APP_ID));
+    assert_eq!(app_logs_dir().unwrap(), home.join("logs"));
+
+    let app_dir = std::env::temp_dir().join(format!("verge-dirs-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&app_dir);
+    std::fs::create_dir_all(app_dir.join(".config")).unwrap();
+    assert!(!is_portable_dir(&app_dir));
+
+    std::fs::write(app_dir.join(".config/PORTABLE"), "").unwrap();
+    assert!(is_portable_dir(&app_dir));
+    assert_eq!(
+        portable_home_dir(&app_dir),
+        app_dir.join(".config").join(APP_ID)
+    );
+    let _ = std::fs::remove_dir_all(&app_dir);
+}
@@ -0,0 +1,187 @@
+use crate::config::IVergeGeoData;
+use anyhow::{bail, Result};
+use std::{fs, path::Path};
+
+pub const DEFAULT_GEOIP_URL: &str =
+    "https://github.com/MetaCubeX/meta-rules-dat/releases/download/latest/geoip.dat";
+pub const DEFAULT_GEOSITE_URL: &str =
+    "https://github.com/MetaCubeX/meta-rules-dat/releases/download/latest/geosite.dat";
+pub const DEFAULT_MMDB_URL: &str =
+    "https://github.com/MetaCubeX/meta-rules-dat/releases/download/latest/country.mmdb";
+
+/// mmdb 的元数据以这个标记开头，位于文件最后的128KB内
+const MMDB_METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+const MMDB_METADATA_MAX_SIZE: usize = 128 * 1024;
+
+/// the geodata files in the app dir and their download urls
+pub fn geodata_files(urls: Option<&IVergeGeoData>) -> Vec<(&'static str, String)> {
+    let pick = |url: Option<&String>, default: &str| match url.map(|u| u.trim()) {
+        Some(url) if !url.is_empty() => url.to_string(),
+        _ => default.to_string(),
+    };
+
+    vec![
+        (
+            "geoip.dat",
+            pick(urls.and_then(|u| u.geoip.as_ref()), DEFAULT_GEOIP_URL),
+        ),
+        (
+            "geosite.dat",
+            pick(urls.and_then(|u| u.geosite.as_ref()), DEFAULT_GEOSITE_URL),
+        ),
+        (
+            "Country.mmdb",
+            pick(urls.and_then(|u| u.mmdb.as_ref()), DEFAULT_MMDB_URL),
+        ),
+    ]
+}
+
+/// check the downloaded data roughly, avoid replacing the files with an error page
+pub fn validate(name: &str, data: &[u8]) -> Result<()> {
+    if data.is_empty() {
+        bail!("the file \"{name}\" is empty");
+    }
+
+    if name.ends_with(".mmdb") {
+        let start = data.len().saturating_sub(MMDB_METADATA_MAX_SIZE);
+        let found = data[start..]
+            .windows(MMDB_METADATA_MARKER.len())
+            .any(|w| w == MMDB_METADATA_MARKER);
+        if !found {
+            bail!("the file \"{name}\" is not a valid mmdb");
+        }
+    } else if name.ends_with(".dat") {
+        // GeoIPList/GeoSiteList 的第一个字段是 repeated message，tag 为 0x0a
+        if data[0] != 0x0a {
+            bail!("the file \"{name}\" is not a valid geodata");
+        }
+    }
+    Ok(())
+}
+
+/// write all the files to `.tmp` first, then replace them one by one
+/// the originals are moved to `.bak` and restored if any of the steps fails
+pub fn replace_files(dir: &Path, files: &[(&str, Vec<u8>)]) -> Result<()> {
+    let tmp_path = |name: &str| dir.join(format!("{name}.tmp"));
+    let bak_path = |name: &str| dir.join(format!("{name}.bak"));
+
+    let cleanup = || {
+        for (name, _) in files {
+            let _ = fs::remove_file(tmp_path(name));
+        }
+    };
+
+    for (name, data) in files {
+        if let Err(err) = fs::write(tmp_path(name), data) {
+            cleanup();
+            bail!("failed to write \"{name}\", {err}");
+        }
+    }
+
+    // the replaced files and whether they had the originals
+    let mut replaced = vec![];
+    let rollback = |replaced: &[(&str, bool)]| {
+        for (name, has_bak) in replaced.iter().rev() {
+            let path = dir.join(name);
+            let _ = match has_bak {
+                true => fs::rename(bak_path(name), &path),
+                false => fs::remove_file(&path),
+            };
+        }
+    };
+
+    for (name, _) in files {
+        let path = dir.join(name);
+        let has_bak = path.exists();
+        let result = (|| {
+            if has_bak {
+                fs::rename(&path, bak_path(name))?;
+            }
+            if let Err(err) = fs::rename(tmp_path(name), &path) {
+                if has_bak {
+                    let _ = fs::rename(bak_path(name), &path);
+                }
+                return Err(err);
+            }
+            Ok(())
+        })();
+
+        if let Err(err) = result {
+            rollback(&replaced);
+            cleanup();
+            bail!("failed to replace \"{name}\", {err}");
+        }
+        replaced.push((*name, has_bak));
+    }
+
+    for (name, has_bak) in replaced {
+        if has_bak {
+            let _ = fs::remove_file(bak_path(name));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_validate_geodata() {
+    assert!(validate("geoip.dat", &[]).is_err());
+    assert!(validate("geoip.dat", b"<html></html>").is_err());
+    assert!(validate("geosite.dat", &[0x0a, 0x10, 0x0a]).is_ok());
+
+    let mut mmdb = vec![0u8; 64];
+    assert!(validate("Country.mmdb", &mmdb).is_err());
+    mmdb.extend_from_slice(MMDB_METADATA_MARKER);
+    mmdb.extend_from_slice(&[0u8; 16]);
+    assert!(validate("Country.mmdb", &mmdb).is_ok());
+
+    let files = geodata_files(Some(&IVergeGeoData {
+        geoip: Some(" https://example.com/geoip.dat ".into()),
+        geosite: Some("".into()),
+        mmdb: None,
+    }));
+    assert_eq!(files[0].1, "https://example.com/geoip.dat");
+    assert_eq!(files[1].1, DEFAULT_GEOSITE_URL);
+    assert_eq!(files[2].1, DEFAULT_MMDB_URL);
+}
+
+#[test]
+fn test_replace_files() {
+    let dir = std::env::temp_dir().join(format!("verge-geodata-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+
+    fs::write(dir.join("a.dat"), "old a").unwrap();
+    fs::write(dir.join("b.dat"), "old b").unwrap();
+    let files = [
+        ("a.dat", b"new a".to_vec()),
+        ("b.dat", b"new b".to_vec()),
+        ("c.dat", b"new c".to_vec()),
+    ];
+    replace_files(&dir, &files).unwrap();
+    assert_eq!(
+        (read("a.dat"), read("b.dat"), read("c.dat")),
+        ("new a".into(), "new b".into(), "new c".into())
+    );
+    assert!(!dir.join("a.dat.bak").exists());
+    assert!(!dir.join("a.dat.tmp").exists());
+
+    // b can not be moved to the backup, a and c are rolled back
+    fs::remove_file(dir.join("c.dat")).unwrap();
+    fs::create_dir_all(dir.join("b.dat.bak").join("busy")).unwrap();
+    let files = [
+        ("a.dat", b"newer a".to_vec()),
+        ("c.dat", b"newer c".to_vec()),
+        ("b.dat", b"newer b".to_vec()),
+    ];
+    assert!(replace_files(&dir, &files).is_err());
+    assert_eq!(
+        (read("a.dat"), read("b.dat")),
+        ("new a".into(), "new b".into())
+    );
+    assert!(!dir.join("c.dat").exists());
+    assert!(!dir.join("a.dat.bak").exists());
+    assert!(!dir.join("b.dat.tmp").exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
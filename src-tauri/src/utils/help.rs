@@ -2,7 +2,12 @@ use anyhow::{anyhow, bail, Context, Result};
 use nanoid::nanoid;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_yaml::{Mapping, Value};
-use std::{fs, path::PathBuf, str::FromStr};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use tauri::{
     api::shell::{open, Program},
     Manager,
@@ -55,6 +60,35 @@ pub fn save_yaml<T: Serialize>(path: &PathBuf, data: &T, prefix: Option<&str>) -
         .with_context(|| format!("failed to save file \"{path_str}\""))
 }
 
+/// create or truncate the file which is only readable by the owner
+/// the file created by the old version is restricted before writing
+pub fn create_private_file(path: &Path) -> Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options
+        .open(path)
+        .with_context(|| format!("failed to create the file \"{}\"", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(file)
+}
+
+/// save the data which contains the secrets, see `create_private_file`
+pub fn save_private_yaml<T: Serialize>(path: &Path, data: &T, prefix: &str) -> Result<()> {
+    let data_str = serde_yaml::to_string(data)?;
+    let mut file = create_private_file(path)?;
+    file.write_all(format!("{prefix}\n\n{data_str}").as_bytes())
+        .with_context(|| format!("failed to save file \"{}\"", path.display()))
+}
+
 const ALPHABET: [char; 62] = [
     '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i',
     'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'A', 'B',
@@ -68,6 +102,12 @@ pub fn get_uid(prefix: &str) -> String {
     format!("{prefix}{id}")
 }
 
+/// generate the controller secret
+/// nanoid 用系统熵播种的 CSPRNG，可以直接当密钥
+pub fn gen_secret() -> String {
+    nanoid!(32, &ALPHABET)
+}
+
 /// parse the string
 /// xxx=123123; => 123123
 pub fn parse_str<T: FromStr>(target: &str, key: &str) -> Option<T> {
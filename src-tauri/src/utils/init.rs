@@ -1,5 +1,5 @@
 use crate::config::*;
-use crate::utils::{dirs, help};
+use crate::utils::{dirs, help, log_tail};
 use anyhow::Result;
 use chrono::{Local, TimeZone};
 use log::LevelFilter;
@@ -7,10 +7,15 @@ use log4rs::append::console::ConsoleAppender;
 use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Logger, Root};
 use log4rs::encode::pattern::PatternEncoder;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde::Serialize;
 use std::fs::{self, DirEntry};
 use std::path::PathBuf;
 use std::str::FromStr;
-use tauri::api::process::Command;
+use std::time::Duration;
+use tauri::api::notification::Notification;
+use tauri::api::process::{Command, CommandEvent};
 
 /// initialize this instance's log file
 fn init_log() -> Result<()> {
@@ -36,7 +41,10 @@ fn init_log() -> Result<()> {
     let encode = Box::new(PatternEncoder::new(log_pattern));
 
     let stdout = ConsoleAppender::builder().encoder(encode.clone()).build();
-    let tofile = FileAppender::builder().encoder(encode).build(log_file)?;
+    let tofile = FileAppender::builder()
+        .encoder(encode)
+        .build(log_file.clone())?;
+    log_tail::set_log_file(log_file);
 
     let mut logger_builder = Logger::builder();
     let mut root_builder = Root::builder();
@@ -151,17 +159,7 @@ pub fn init_config() -> Result<()> {
     let _ = init_log();
     let _ = delete_log();
 
-    crate::log_err!(dirs::app_home_dir().map(|app_dir| {
-        if !app_dir.exists() {
-            let _ = fs::create_dir_all(&app_dir);
-        }
-    }));
-
-    crate::log_err!(dirs::app_profiles_dir().map(|profiles_dir| {
-        if !profiles_dir.exists() {
-            let _ = fs::create_dir_all(&profiles_dir);
-        }
-    }));
+    crate::log_err!(repair_app_dirs());
 
     crate::log_err!(dirs::clash_path().map(|path| {
         if !path.exists() {
@@ -187,6 +185,73 @@ pub fn init_config() -> Result<()> {
     Ok(())
 }
 
+/// the result of `repair_app_dirs`
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RepairReport {
+    /// the missing dirs which are created
+    pub created: Vec<String>,
+    /// the dirs which can not be fixed, e.g. taken by a file
+    pub failed: Vec<String>,
+}
+
+/// the dirs which the app needs
+fn required_dirs() -> Result<Vec<PathBuf>> {
+    let app_dir = dirs::app_home_dir()?;
+    Ok(vec![
+        app_dir.clone(),
+        dirs::app_profiles_dir()?,
+        dirs::app_logs_dir()?,
+        app_dir.join("icons"),
+    ])
+}
+
+/// 检查app的目录，重建缺失的目录
+pub fn repair_app_dirs() -> Result<RepairReport> {
+    let mut report = RepairReport::default();
+    for dir in required_dirs()? {
+        if dir.is_dir() {
+            continue;
+        }
+
+        let path = dir.to_string_lossy().to_string();
+        if dir.exists() {
+            log::error!(target: "app", "\"{path}\" is not a directory");
+            report.failed.push(format!("{path}: not a directory"));
+            continue;
+        }
+        match fs::create_dir_all(&dir) {
+            Ok(_) => {
+                log::info!(target: "app", "create the missing dir \"{path}\"");
+                report.created.push(path);
+            }
+            Err(err) => {
+                log::error!(target: "app", "failed to create \"{path}\": {err}");
+                report.failed.push(format!("{path}: {err}"));
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// the resources copied from the bundle to the app dir
+const RESOURCE_FILES: [&str; 3] = ["Country.mmdb", "geoip.dat", "geosite.dat"];
+
+/// the check result of a resource file in the app dir
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceState {
+    pub name: String,
+    /// `ok` / `restored` / `missing` / `empty`
+    pub status: &'static str,
+    pub size: u64,
+    pub error: Option<String>,
+}
+
+impl ResourceState {
+    pub fn is_ok(&self) -> bool {
+        matches!(self.status, "ok" | "restored")
+    }
+}
+
 /// initialize app resources
 /// after tauri setup
 pub fn init_resources() -> Result<()> {
@@ -200,14 +265,9 @@ pub fn init_resources() -> Result<()> {
         let _ = fs::create_dir_all(&res_dir);
     }
 
-    #[cfg(target_os = "windows")]
-    let file_list = ["Country.mmdb", "geoip.dat", "geosite.dat"];
-    #[cfg(not(target_os = "windows"))]
-    let file_list = ["Country.mmdb", "geoip.dat", "geosite.dat"];
-
     // copy the resource file
     // if the source file is newer than the destination file, copy it over
-    for file in file_list.iter() {
+    for file in RESOURCE_FILES.iter() {
         let src_path = res_dir.join(file);
         let dest_path = app_dir.join(file);
 
@@ -246,6 +306,54 @@ pub fn init_resources() -> Result<()> {
     Ok(())
 }
 
+/// 检查资源文件存在且不为空，有问题的从安装目录重新复制
+/// 复制的部分可能被中断，留下空文件
+pub fn verify_resources() -> Result<Vec<ResourceState>> {
+    let app_dir = dirs::app_home_dir()?;
+    let res_dir = dirs::app_resources_dir()?;
+    let size = |path: &PathBuf| fs::metadata(path).map(|m| m.len()).ok();
+
+    let mut list = vec![];
+    for file in RESOURCE_FILES {
+        let dest_path = app_dir.join(file);
+        let mut state = ResourceState {
+            name: file.into(),
+            status: "ok",
+            size: 0,
+            error: None,
+        };
+        match size(&dest_path) {
+            Some(len) if len > 0 => {
+                state.size = len;
+                list.push(state);
+                continue;
+            }
+            Some(_) => state.status = "empty",
+            None => state.status = "missing",
+        }
+        log::warn!(target: "app", "the resource '{file}' is {}, copy it again", state.status);
+
+        let src_path = res_dir.join(file);
+        let result = match size(&src_path) {
+            Some(len) if len > 0 => fs::copy(&src_path, &dest_path).map_err(|err| err.to_string()),
+            _ => Err("the bundled file is missing or empty".into()),
+        };
+        match result {
+            Ok(len) if len > 0 => {
+                state.status = "restored";
+                state.size = len;
+            }
+            Ok(_) => state.error = Some("the copied file is empty".into()),
+            Err(err) => {
+                log::error!(target: "app", "failed to restore the resource '{file}', {err}");
+                state.error = Some(err);
+            }
+        }
+        list.push(state);
+    }
+    Ok(list)
+}
+
 /// initialize url scheme
 #[cfg(target_os = "windows")]
 pub fn init_scheme() -> Result<()> {
@@ -288,6 +396,38 @@ pub fn init_scheme() -> Result<()> {
     Ok(())
 }
 
+/// 启动脚本的超时时间
+const STARTUP_SCRIPT_TIMEOUT: Duration = Duration::from_secs(30);
+/// 保留的输出长度
+const STARTUP_SCRIPT_OUTPUT_LIMIT: usize = 16 * 1024;
+
+/// the result of the last startup script
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct StartupScriptResult {
+    pub path: String,
+    /// none if the script is killed or timed out
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub timed_out: bool,
+    /// the start time
+    pub started: i64,
+}
+
+impl StartupScriptResult {
+    pub fn global() -> &'static Mutex<Option<StartupScriptResult>> {
+        static RESULT: OnceCell<Mutex<Option<StartupScriptResult>>> = OnceCell::new();
+        RESULT.get_or_init(|| Mutex::new(None))
+    }
+
+    fn push_output(output: &mut String, line: String) {
+        if output.len() < STARTUP_SCRIPT_OUTPUT_LIMIT {
+            output.push_str(&line);
+            output.push('\n');
+        }
+    }
+}
+
 pub fn startup_script() -> Result<()> {
     let path = {
         let verge = Config::verge();
@@ -313,18 +453,66 @@ pub fn startup_script() -> Result<()> {
         if !current_dir.exists() {
             return Err(anyhow::anyhow!("script not found: {path}"));
         }
-        let current_dir = current_dir.parent();
-        match current_dir {
-            Some(dir) => {
-                let _ = Command::new(shell)
-                    .current_dir(dir.to_path_buf())
-                    .args(&[path])
-                    .output()?;
-            }
-            None => {
-                let _ = Command::new(shell).args(&[path]).output()?;
-            }
+        let mut command = Command::new(shell).args(&[path.clone()]);
+        if let Some(dir) = current_dir.parent() {
+            command = command.current_dir(dir.to_path_buf());
+        }
+
+        let result = run_startup_script(command, path)?;
+        *StartupScriptResult::global().lock() = Some(result.clone());
+
+        if result.timed_out || result.code != Some(0) {
+            let body = match result.code {
+                _ if result.timed_out => format!("Startup script timed out: {}", result.path),
+                Some(code) => format!("Startup script exited with code {code}: {}", result.path),
+                None => format!("Startup script was terminated: {}", result.path),
+            };
+            log::error!(target: "app", "{body}\n{}", result.stderr);
+            let _ = Notification::new(dirs::APP_ID)
+                .title("Clash Verge")
+                .body(body)
+                .show();
         }
     }
     Ok(())
 }
+
+/// 执行脚本并收集输出，超时后结束脚本
+fn run_startup_script(command: Command, path: String) -> Result<StartupScriptResult> {
+    let mut result = StartupScriptResult {
+        path,
+        started: Local::now().timestamp(),
+        ..StartupScriptResult::default()
+    };
+    let (mut rx, child) = command.spawn()?;
+
+    let finished = tauri::async_runtime::block_on(async {
+        tokio::time::timeout(STARTUP_SCRIPT_TIMEOUT, async {
+            while let Some(event) = rx.recv().await {
+                match event {
+                    CommandEvent::Stdout(line) => {
+                        StartupScriptResult::push_output(&mut result.stdout, line)
+                    }
+                    CommandEvent::Stderr(line) => {
+                        StartupScriptResult::push_output(&mut result.stderr, line)
+                    }
+                    CommandEvent::Error(err) => {
+                        StartupScriptResult::push_output(&mut result.stderr, err)
+                    }
+                    CommandEvent::Terminated(payload) => {
+                        result.code = payload.code;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await
+    });
+
+    if finished.is_err() {
+        result.timed_out = true;
+        let _ = child.kill();
+    }
+    Ok(result)
+}
@@ -0,0 +1,143 @@
+use crate::utils::{dirs, help};
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+
+/// check the user and the password of the `authentication`
+pub fn check_user(user: &str, pass: &str) -> Result<()> {
+    if user.is_empty() || pass.is_empty() {
+        bail!("the user and the password should not be empty");
+    }
+    if user.contains(':') {
+        bail!("the user should not contain \":\"");
+    }
+    let invalid = |c: char| c.is_whitespace() || c.is_control();
+    if user.chars().chain(pass.chars()).any(invalid) {
+        bail!("the user and the password should not contain spaces");
+    }
+    Ok(())
+}
+
+fn digest(salt: &str, pass: &str) -> String {
+    Sha256::digest(format!("{salt}:{pass}"))
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// `user:salt:sha256` saved in `verge.yaml`
+pub fn hash(user: &str, pass: &str) -> String {
+    let salt = nanoid::nanoid!(16);
+    format!("{user}:{salt}:{}", digest(&salt, pass))
+}
+
+pub fn verify(entry: &str, user: &str, pass: &str) -> bool {
+    let mut parts = entry.trim().splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(u), Some(salt), Some(hash)) => u == user && digest(salt, pass) == hash,
+        _ => false,
+    }
+}
+
+fn entry_user(entry: &str) -> Option<&str> {
+    entry.trim().split_once(':').map(|(user, _)| user)
+}
+
+/// the users without the passwords
+pub fn users(entries: &[String]) -> Vec<String> {
+    entries
+        .iter()
+        .filter_map(|entry| entry_user(entry))
+        .map(String::from)
+        .collect()
+}
+
+/// add the user, the password of the same user is replaced
+pub fn upsert(entries: &[String], user: &str, pass: &str) -> Result<Vec<String>> {
+    check_user(user, pass)?;
+    let mut entries = remove(entries, user);
+    entries.push(hash(user, pass));
+    Ok(entries)
+}
+
+pub fn remove(entries: &[String], user: &str) -> Vec<String> {
+    entries
+        .iter()
+        .filter(|entry| entry_user(entry) != Some(user))
+        .cloned()
+        .collect()
+}
+
+/// 内核只认明文的 `user:pass`，明文单独保存在 `lan-auth.yaml`
+/// 每个哈希都要有对得上的明文，缺了就报错，不能让局域网不认证
+pub fn core_auth(entries: &[String], secrets: &[String]) -> Result<Vec<String>> {
+    entries
+        .iter()
+        .map(|entry| {
+            let secret = secrets.iter().find(|secret| match secret.split_once(':') {
+                Some((user, pass)) => verify(entry, user, pass),
+                None => false,
+            });
+            match secret {
+                Some(secret) => Ok(secret.clone()),
+                None => bail!(
+                    "the password of the lan user \"{}\" is not found",
+                    entry_user(entry).unwrap_or_default()
+                ),
+            }
+        })
+        .collect()
+}
+
+/// the plain `user:pass` list for the core
+pub fn read_secrets() -> Result<Vec<String>> {
+    let path = dirs::lan_auth_path()?;
+    match path.exists() {
+        true => help::read_yaml::<Vec<String>>(&path),
+        false => Ok(vec![]),
+    }
+}
+
+/// replace or remove the password of the user, only readable by the owner
+pub fn save_secret(user: &str, pass: Option<&str>) -> Result<()> {
+    let mut secrets = read_secrets()?;
+    secrets.retain(|secret| secret.split_once(':').map(|(u, _)| u) != Some(user));
+    if let Some(pass) = pass {
+        secrets.push(format!("{user}:{pass}"));
+    }
+
+    let path = dirs::lan_auth_path()?;
+    help::save_private_yaml(&path, &secrets, "# Clash Verge LAN Authentication")
+}
+
+#[test]
+fn test_lan_auth() {
+    let entries = upsert(&[], "alice", "p@ss:word").unwrap();
+    assert!(!entries[0].contains("p@ss:word"));
+    assert!(verify(&entries[0], "alice", "p@ss:word"));
+    assert!(!verify(&entries[0], "alice", "p@ss"));
+    assert!(!verify(&entries[0], "bob", "p@ss:word"));
+    // 同样的密码每次的盐不同
+    assert_ne!(hash("alice", "pass"), hash("alice", "pass"));
+
+    let entries = upsert(&entries, "bob", "123").unwrap();
+    let entries = upsert(&entries, "alice", "new").unwrap();
+    assert_eq!(users(&entries), vec!["bob", "alice"]);
+    assert!(verify(&entries[1], "alice", "new"));
+
+    assert_eq!(users(&remove(&entries, "bob")), vec!["alice"]);
+
+    let secrets: Vec<String> = vec!["bob:123".into(), "alice:new".into(), "carol:x".into()];
+    assert_eq!(
+        core_auth(&entries, &secrets).unwrap(),
+        vec!["bob:123", "alice:new"]
+    );
+    assert!(core_auth(&[], &secrets).unwrap().is_empty());
+    // 哈希没有对得上的明文时不能少写认证
+    let secrets: Vec<String> = vec!["alice:new".into(), "bob:old".into()];
+    assert!(core_auth(&entries, &secrets).is_err());
+    assert!(core_auth(&entries, &[]).is_err());
+
+    assert!(upsert(&[], "", "pass").is_err());
+    assert!(upsert(&[], "a:b", "pass").is_err());
+    assert!(upsert(&[], "alice", "pa ss").is_err());
+}
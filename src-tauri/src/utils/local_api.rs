@@ -0,0 +1,220 @@
+use super::{dirs, help, resolve::VERSION};
+use crate::{
+    config::{Config, IProfiles, IVerge},
+    core::handle,
+    feat,
+};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fs, io::Write};
+use warp::{
+    filters::BoxedFilter,
+    http::StatusCode,
+    reply::{self, Response},
+    Filter, Rejection, Reply,
+};
+
+/// the error which is replied as json
+#[derive(Debug)]
+struct ApiError(StatusCode, String);
+
+impl warp::reject::Reject for ApiError {}
+
+fn reject(status: StatusCode, msg: impl Into<String>) -> Rejection {
+    warp::reject::custom(ApiError(status, msg.into()))
+}
+
+#[derive(Debug, Serialize)]
+struct ApiStatus {
+    version: Option<String>,
+    mode: Option<String>,
+    current_profile: Option<String>,
+    system_proxy: bool,
+    tun_mode: bool,
+    mixed_port: u16,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileBody {
+    uid: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModeBody {
+    mode: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SysproxyBody {
+    enable: bool,
+}
+
+/// read the token, generate one if not exists
+pub fn read_token() -> Result<String> {
+    let path = dirs::local_api_token_path()?;
+    match fs::read_to_string(&path) {
+        Ok(token) if !token.trim().is_empty() => Ok(token.trim().to_string()),
+        _ => reset_token(),
+    }
+}
+
+/// generate a new token and save it, only readable by the owner
+pub fn reset_token() -> Result<String> {
+    let path = dirs::local_api_token_path()?;
+    let token = nanoid::nanoid!(32);
+    help::create_private_file(&path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+/// compare the token in constant time, not to leak it by the timing
+fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// the local api mounted on the embed server
+/// - GET  /api/status
+/// - PUT  /api/profile   `{"uid": "xxx"}`
+/// - PUT  /api/mode      `{"mode": "rule"}`
+/// - PUT  /api/sysproxy  `{"enable": true}`
+pub fn routes() -> BoxedFilter<(Response,)> {
+    let get_status = warp::path!("status")
+        .and(warp::get())
+        .and_then(|| async { Ok::<_, Rejection>(reply::json(&status()).into_response()) });
+
+    let profile = warp::path!("profile")
+        .and(warp::put())
+        .and(json_body::<ProfileBody>())
+        .and_then(put_profile);
+
+    let mode = warp::path!("mode")
+        .and(warp::put())
+        .and(json_body::<ModeBody>())
+        .and_then(put_mode);
+
+    let sysproxy = warp::path!("sysproxy")
+        .and(warp::put())
+        .and(json_body::<SysproxyBody>())
+        .and_then(put_sysproxy);
+
+    warp::path("api")
+        .and(authorize())
+        .and(
+            get_status
+                .or(profile)
+                .unify()
+                .or(mode)
+                .unify()
+                .or(sysproxy)
+                .unify(),
+        )
+        .recover(handle_rejection)
+        .unify()
+        .boxed()
+}
+
+/// reject the request unless the local api is enabled and the token matches
+fn authorize() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(|auth: Option<String>| async move {
+            if !Config::verge().latest().enable_local_api.unwrap_or(false) {
+                return Err(warp::reject::not_found());
+            }
+
+            let token = read_token()
+                .map_err(|err| reject(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+            match auth.as_deref().and_then(|s| s.strip_prefix("Bearer ")) {
+                Some(auth) if token_eq(auth.trim(), &token) => Ok(()),
+                _ => Err(reject(StatusCode::UNAUTHORIZED, "invalid token")),
+            }
+        })
+        .untuple_one()
+}
+
+fn json_body<T>() -> impl Filter<Extract = (T,), Error = Rejection> + Clone
+where
+    T: DeserializeOwned + Send,
+{
+    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+}
+
+fn status() -> ApiStatus {
+    let verge = Config::verge().latest().clone();
+    let clash = Config::clash().latest().clone();
+
+    ApiStatus {
+        version: VERSION.get().cloned(),
+        mode: clash
+            .0
+            .get("mode")
+            .and_then(|v| v.as_str())
+            .map(String::from),
+        current_profile: Config::profiles().latest().get_current(),
+        system_proxy: verge.enable_system_proxy.unwrap_or(false),
+        tun_mode: verge.enable_tun_mode.unwrap_or(false),
+        mixed_port: verge.verge_mixed_port.unwrap_or(clash.get_mixed_port()),
+    }
+}
+
+async fn put_profile(body: ProfileBody) -> Result<Response, Rejection> {
+    if Config::profiles().latest().get_item(&body.uid).is_err() {
+        return Err(reject(
+            StatusCode::NOT_FOUND,
+            format!("the profile \"{}\" is not found", body.uid),
+        ));
+    }
+
+    let profiles = IProfiles {
+        current: Some(body.uid),
+        ..IProfiles::default()
+    };
+    feat::patch_profiles_config(profiles)
+        .await
+        .map_err(|err| reject(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    handle::Handle::refresh_profiles();
+    Ok(reply::json(&status()).into_response())
+}
+
+async fn put_mode(body: ModeBody) -> Result<Response, Rejection> {
+    feat::set_clash_mode(body.mode)
+        .await
+        .map_err(|err| reject(StatusCode::BAD_REQUEST, err.to_string()))?;
+    Ok(reply::json(&status()).into_response())
+}
+
+async fn put_sysproxy(body: SysproxyBody) -> Result<Response, Rejection> {
+    let patch = IVerge {
+        enable_system_proxy: Some(body.enable),
+        ..IVerge::default()
+    };
+    feat::patch_verge(patch)
+        .await
+        .map_err(|err| reject(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    handle::Handle::refresh_verge();
+    Ok(reply::json(&status()).into_response())
+}
+
+/// reply the api error as json, leave the others to warp
+async fn handle_rejection(err: Rejection) -> Result<Response, Rejection> {
+    let (status, msg) = match err.find::<ApiError>() {
+        Some(ApiError(status, msg)) => (*status, msg.clone()),
+        None => match err.find::<warp::body::BodyDeserializeError>() {
+            Some(err) => (StatusCode::BAD_REQUEST, err.to_string()),
+            None => return Err(err),
+        },
+    };
+
+    let body = reply::json(&serde_json::json!({ "error": msg }));
+    Ok(reply::with_status(body, status).into_response())
+}
+
+#[test]
+fn test_token_eq() {
+    assert!(token_eq("abc", "abc"));
+    assert!(!token_eq("abc", "abd"));
+    assert!(!token_eq("abc", "ab"));
+    assert!(!token_eq("", "abc"));
+}
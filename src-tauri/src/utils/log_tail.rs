@@ -0,0 +1,191 @@
+use crate::core::handle;
+use anyhow::{bail, Result};
+use once_cell::sync::OnceCell;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// the max lines returned once
+pub const MAX_TAIL_LINES: usize = 5000;
+
+/// 从文件尾部向前每次读取的大小
+const TAIL_CHUNK_SIZE: u64 = 8 * 1024;
+
+/// 推送时每次最多读取的大小，避免日志暴涨时一次读太多
+const STREAM_MAX_BYTES: u64 = 256 * 1024;
+
+const STREAM_INTERVAL: Duration = Duration::from_millis(500);
+
+/// the event of the new log lines
+pub const LOG_LINES_EVENT: &str = "verge://log-lines";
+
+static LOG_FILE: OnceCell<PathBuf> = OnceCell::new();
+
+/// 每次开始推送都会+1，旧的推送发现不一致后退出
+static STREAM_ID: AtomicU64 = AtomicU64::new(0);
+
+/// record this instance's log file, called by `init_log`
+pub fn set_log_file(path: PathBuf) {
+    let _ = LOG_FILE.set(path);
+}
+
+/// this instance's log file in the logs dir
+pub fn current_log_file() -> Result<PathBuf> {
+    match LOG_FILE.get() {
+        Some(path) => Ok(path.clone()),
+        None => bail!("the app log is disabled"),
+    }
+}
+
+/// read the last `lines` complete lines by seeking from the end
+/// return the lines and the offset after them, for `read_from` later
+pub fn read_tail(path: &Path, lines: usize) -> Result<(Vec<String>, u64)> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    // 末尾没写完的行留给 `read_from`
+    let mut end = None;
+    let mut buf: Vec<u8> = vec![];
+    let mut pos = len;
+    let mut newlines = 0;
+
+    // 多读一个换行，保证最前面的一行是完整的
+    while pos > 0 && newlines <= lines {
+        let size = TAIL_CHUNK_SIZE.min(pos);
+        pos -= size;
+
+        let mut chunk = vec![0; size as usize];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut chunk)?;
+
+        if end.is_none() {
+            end = chunk
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map(|i| pos + i as u64 + 1);
+        }
+        newlines += chunk.iter().filter(|&&b| b == b'\n').count();
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
+    }
+    let end = end.unwrap_or(pos);
+
+    let buf = &buf[..(end - pos) as usize];
+    let text = String::from_utf8_lossy(buf);
+    let mut all = text.lines().collect::<Vec<_>>();
+    if pos > 0 && !all.is_empty() {
+        all.remove(0);
+    }
+
+    let start = all.len().saturating_sub(lines);
+    let result = all[start..].iter().map(|l| l.to_string()).collect();
+    Ok((result, end))
+}
+
+/// read the complete lines after the offset
+/// start from the beginning if the file is truncated
+pub fn read_from(path: &Path, offset: u64) -> Result<(Vec<String>, u64)> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let offset = if len < offset { 0 } else { offset };
+
+    let size = (len - offset).min(STREAM_MAX_BYTES);
+    if size == 0 {
+        return Ok((vec![], offset));
+    }
+
+    let mut buf = vec![0; size as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+
+    let end = match buf.iter().rposition(|&b| b == b'\n') {
+        Some(i) => i + 1,
+        // 超长的一行不能一直卡住
+        None if size == STREAM_MAX_BYTES => buf.len(),
+        None => 0,
+    };
+
+    let text = String::from_utf8_lossy(&buf[..end]);
+    let lines = text.lines().map(|l| l.to_string()).collect();
+    Ok((lines, offset + end as u64))
+}
+
+/// emit the new lines of the log file with `LOG_LINES_EVENT` until stopped
+/// return the last `lines` lines as the initial content
+pub fn start_stream(lines: usize) -> Result<Vec<String>> {
+    let path = current_log_file()?;
+    let (init, mut offset) = read_tail(&path, lines.min(MAX_TAIL_LINES))?;
+    let id = STREAM_ID.fetch_add(1, Ordering::SeqCst) + 1;
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(STREAM_INTERVAL).await;
+
+            // 窗口关闭后不再推送
+            if STREAM_ID.load(Ordering::SeqCst) != id
+                || handle::Handle::global().get_window().is_none()
+            {
+                break;
+            }
+
+            match read_from(&path, offset) {
+                Ok((lines, next)) => {
+                    offset = next;
+                    if !lines.is_empty() {
+                        handle::Handle::emit(LOG_LINES_EVENT, lines);
+                    }
+                }
+                Err(err) => {
+                    log::error!(target: "app", "failed to read the log file, {err}");
+                    break;
+                }
+            }
+        }
+    });
+    Ok(init)
+}
+
+pub fn stop_stream() {
+    STREAM_ID.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test]
+fn test_read_log_tail() {
+    use std::io::Write;
+
+    let path = std::env::temp_dir().join(format!("verge-log-tail-{}.log", std::process::id()));
+    let mut file = File::create(&path).unwrap();
+    for i in 0..3000 {
+        writeln!(file, "line {i}").unwrap();
+    }
+    write!(file, "partial").unwrap();
+    file.flush().unwrap();
+
+    let (lines, offset) = read_tail(&path, 3).unwrap();
+    assert_eq!(lines, vec!["line 2997", "line 2998", "line 2999"]);
+
+    let (all, _) = read_tail(&path, 5000).unwrap();
+    assert_eq!(all.len(), 3000);
+    assert_eq!(all[0], "line 0");
+
+    // 没写完的行等写完再读
+    let (lines, next) = read_from(&path, offset).unwrap();
+    assert!(lines.is_empty());
+    assert_eq!(next, offset);
+
+    writeln!(file, " done").unwrap();
+    writeln!(file, "line 3001").unwrap();
+    file.flush().unwrap();
+    let (lines, _) = read_from(&path, offset).unwrap();
+    assert_eq!(lines, vec!["partial done", "line 3001"]);
+
+    // 截断后从头读
+    File::create(&path).unwrap().write_all(b"new\n").unwrap();
+    let (lines, next) = read_from(&path, offset).unwrap();
+    assert_eq!(lines, vec!["new"]);
+    assert_eq!(next, 4);
+
+    let _ = std::fs::remove_file(path);
+}
@@ -1,7 +1,17 @@
+pub mod backup;
+pub mod captive_portal;
 pub mod dirs;
+pub mod geodata;
 pub mod help;
 pub mod init;
+pub mod lan_auth;
+pub mod local_api;
+pub mod log_tail;
+pub mod pac;
+pub mod proxy_env;
+pub mod proxy_uri;
 pub mod resolve;
+pub mod rule_match;
 pub mod server;
 pub mod tmpl;
 pub mod unix_helper;
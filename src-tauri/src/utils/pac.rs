@@ -0,0 +1,92 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// the path of the pac file on the embed server
+pub const PAC_PATH: &str = "commands/pac";
+
+/// generate the pac file
+/// the hosts in the bypass list go DIRECT, others go to the mixed port on `host`
+pub fn generate(host: &str, port: u16, bypass: &str) -> String {
+    let conditions = bypass
+        .split([',', ';'])
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .filter_map(bypass_condition)
+        .collect::<Vec<_>>();
+
+    let mut pac = String::from("function FindProxyForURL(url, host) {\n");
+    if !conditions.is_empty() {
+        pac.push_str(&format!(
+            "  if ({}) {{\n    return \"DIRECT\";\n  }}\n",
+            conditions.join("\n    || ")
+        ));
+    }
+    pac.push_str(&format!(
+        "  return \"PROXY {host}:{port}; SOCKS5 {host}:{port}\";\n}}\n"
+    ));
+    pac
+}
+
+/// the pac url of the embed server
+/// 内容变化时地址也跟着变，避免系统一直用缓存的旧文件
+pub fn pac_url(server_port: u16, content: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    format!(
+        "http://127.0.0.1:{server_port}/{PAC_PATH}?v={:x}",
+        hasher.finish()
+    )
+}
+
+/// translate the item of the system proxy bypass list
+/// - `<local>` the plain host name
+/// - `10.0.0.0/8` the ipv4 cidr
+/// - others, e.g. `127.*` `*.local` `localhost`, by the shell expression
+fn bypass_condition(item: &str) -> Option<String> {
+    if item == "<local>" {
+        return Some("isPlainHostName(host)".into());
+    }
+
+    // 不能带到js字符串里
+    if item.contains(['"', '\\']) {
+        return None;
+    }
+
+    if let Some((net, prefix)) = item.split_once('/') {
+        let net = net.parse::<std::net::Ipv4Addr>().ok()?;
+        let prefix = prefix.parse::<u32>().ok().filter(|p| *p <= 32)?;
+        let mask = match prefix {
+            0 => 0,
+            _ => u32::MAX << (32 - prefix),
+        };
+        let mask = std::net::Ipv4Addr::from(mask);
+        return Some(format!("isInNet(host, \"{net}\", \"{mask}\")"));
+    }
+
+    Some(format!("shExpMatch(host, \"{item}\")"))
+}
+
+#[test]
+fn test_generate_pac() {
+    let pac = generate(
+        "127.0.0.1",
+        7897,
+        "localhost;127.*;<local>, 10.0.0.0/8,172.16.0.0/12,bad\"host,::1",
+    );
+    assert!(pac.contains("shExpMatch(host, \"localhost\")"));
+    assert!(pac.contains("shExpMatch(host, \"127.*\")"));
+    assert!(pac.contains("isPlainHostName(host)"));
+    assert!(pac.contains("isInNet(host, \"10.0.0.0\", \"255.0.0.0\")"));
+    assert!(pac.contains("isInNet(host, \"172.16.0.0\", \"255.240.0.0\")"));
+    assert!(pac.contains("shExpMatch(host, \"::1\")"));
+    assert!(!pac.contains("bad"));
+    assert!(pac.contains("return \"PROXY 127.0.0.1:7897; SOCKS5 127.0.0.1:7897\";"));
+
+    let pac = generate("[::1]", 7890, "");
+    assert!(!pac.contains("DIRECT"));
+    assert!(pac.contains("return \"PROXY [::1]:7890; SOCKS5 [::1]:7890\";"));
+
+    let url = pac_url(33331, &pac);
+    assert!(url.starts_with("http://127.0.0.1:33331/commands/pac?v="));
+    assert_ne!(url, pac_url(33331, &generate("[::1]", 7891, "")));
+}
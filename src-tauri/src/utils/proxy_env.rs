@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// the shell which the proxy env statements are generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+    Cmd,
+}
+
+impl Default for ShellKind {
+    fn default() -> Self {
+        #[cfg(not(target_os = "windows"))]
+        return ShellKind::Bash;
+        #[cfg(target_os = "windows")]
+        return ShellKind::Powershell;
+    }
+}
+
+impl FromStr for ShellKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "bash" | "sh" => Ok(ShellKind::Bash),
+            "zsh" => Ok(ShellKind::Zsh),
+            "fish" => Ok(ShellKind::Fish),
+            "powershell" | "pwsh" => Ok(ShellKind::Powershell),
+            "cmd" => Ok(ShellKind::Cmd),
+            _ => anyhow::bail!("invalid shell kind \"{s}\""),
+        }
+    }
+}
+
+/// transform the system proxy bypass list to `no_proxy` format
+/// the bypass list is separated by `;` on windows and `,` on others
+pub fn no_proxy_from_bypass(bypass: &str) -> String {
+    bypass
+        .split([';', ','])
+        .map(str::trim)
+        .filter(|s| !s.is_empty() && *s != "<local>")
+        .collect::<Vec<&str>>()
+        .join(",")
+}
+
+/// generate the statements which export the proxy env for the shell
+pub fn render(shell: ShellKind, host: &str, port: u16, no_proxy: &str) -> String {
+    let host = match host.contains(':') {
+        true => format!("[{host}]"),
+        false => host.to_string(),
+    };
+    let http_proxy = format!("http://{host}:{port}");
+    let socks5_proxy = format!("socks5://{host}:{port}");
+
+    let mut lines = vec![];
+    match shell {
+        ShellKind::Bash | ShellKind::Zsh => {
+            lines.push(format!(
+                "export https_proxy={http_proxy} http_proxy={http_proxy} all_proxy={socks5_proxy}"
+            ));
+            if !no_proxy.is_empty() {
+                lines.push(format!("export no_proxy=\"{no_proxy}\""));
+            }
+        }
+        ShellKind::Fish => {
+            lines.push(format!("set -gx https_proxy {http_proxy}"));
+            lines.push(format!("set -gx http_proxy {http_proxy}"));
+            lines.push(format!("set -gx all_proxy {socks5_proxy}"));
+            if !no_proxy.is_empty() {
+                lines.push(format!("set -gx no_proxy \"{no_proxy}\""));
+            }
+        }
+        ShellKind::Powershell => {
+            lines.push(format!("$env:HTTP_PROXY=\"{http_proxy}\""));
+            lines.push(format!("$env:HTTPS_PROXY=\"{http_proxy}\""));
+            if !no_proxy.is_empty() {
+                lines.push(format!("$env:NO_PROXY=\"{no_proxy}\""));
+            }
+            return lines.join("; ");
+        }
+        ShellKind::Cmd => {
+            lines.push(format!("set http_proxy={http_proxy}"));
+            lines.push(format!("set https_proxy={http_proxy}"));
+            if !no_proxy.is_empty() {
+                lines.push(format!("set no_proxy={no_proxy}"));
+            }
+            return lines.join("\r\n");
+        }
+    }
+    lines.join("\n")
+}
+
+#[test]
+fn test_render_proxy_env() {
+    let no_proxy = no_proxy_from_bypass("localhost;127.*;10.*;<local>");
+    assert_eq!(no_proxy, "localhost,127.*,10.*");
+    assert_eq!(no_proxy_from_bypass(" localhost, ::1 ,,"), "localhost,::1");
+
+    assert_eq!(
+        render(ShellKind::Bash, "127.0.0.1", 7897, "localhost"),
+        "export https_proxy=http://127.0.0.1:7897 http_proxy=http://127.0.0.1:7897 all_proxy=socks5://127.0.0.1:7897\nexport no_proxy=\"localhost\""
+    );
+    assert_eq!(
+        render(ShellKind::Zsh, "127.0.0.1", 7897, ""),
+        "export https_proxy=http://127.0.0.1:7897 http_proxy=http://127.0.0.1:7897 all_proxy=socks5://127.0.0.1:7897"
+    );
+    assert_eq!(
+        render(ShellKind::Fish, "192.168.1.2", 8888, "localhost,10.*"),
+        "set -gx https_proxy http://192.168.1.2:8888\nset -gx http_proxy http://192.168.1.2:8888\nset -gx all_proxy socks5://192.168.1.2:8888\nset -gx no_proxy \"localhost,10.*\""
+    );
+    assert_eq!(
+        render(ShellKind::Powershell, "127.0.0.1", 7897, "localhost"),
+        "$env:HTTP_PROXY=\"http://127.0.0.1:7897\"; $env:HTTPS_PROXY=\"http://127.0.0.1:7897\"; $env:NO_PROXY=\"localhost\""
+    );
+    assert_eq!(
+        render(ShellKind::Cmd, "127.0.0.1", 7897, "localhost"),
+        "set http_proxy=http://127.0.0.1:7897\r\nset https_proxy=http://127.0.0.1:7897\r\nset no_proxy=localhost"
+    );
+    assert_eq!(
+        render(ShellKind::Cmd, "::1", 7897, ""),
+        "set http_proxy=http://[::1]:7897\r\nset https_proxy=http://[::1]:7897"
+    );
+
+    assert_eq!("pwsh".parse::<ShellKind>().unwrap(), ShellKind::Powershell);
+    assert!("nu".parse::<ShellKind>().is_err());
+}
@@ -0,0 +1,427 @@
+use base64::{engine::general_purpose, Engine};
+use percent_encoding::percent_decode_str;
+use serde_yaml::{Mapping, Value};
+use std::collections::{HashMap, HashSet};
+
+/// the select group which contains all the converted proxies
+const PROXY_GROUP: &str = "PROXY";
+
+/// convert the base64 encoded (or plain) proxy uri list to a clash config
+/// the unrecognized lines are skipped, return none if no proxy is found
+pub fn convert_subscription(data: &str) -> Option<Mapping> {
+    let text = decode_base64(data).unwrap_or(data.to_string());
+
+    let mut names = HashSet::new();
+    let mut proxies = vec![];
+    let mut skipped = 0;
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        match parse_uri(line) {
+            Some(mut proxy) => {
+                let name = proxy
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                proxy.insert("name".into(), unique_name(&mut names, name).into());
+                proxies.push(Value::Mapping(proxy));
+            }
+            None => skipped += 1,
+        }
+    }
+
+    if skipped > 0 {
+        log::warn!(target: "app", "skipped {skipped} unrecognized proxy uri(s) of the subscription");
+    }
+    if proxies.is_empty() {
+        return None;
+    }
+
+    let mut group_proxies = proxies
+        .iter()
+        .filter_map(|p| p.get("name").cloned())
+        .collect::<Vec<Value>>();
+    group_proxies.push("DIRECT".into());
+
+    let mut group = Mapping::new();
+    group.insert("name".into(), PROXY_GROUP.into());
+    group.insert("type".into(), "select".into());
+    group.insert("proxies".into(), group_proxies.into());
+
+    let mut config = Mapping::new();
+    config.insert("proxies".into(), proxies.into());
+    config.insert("proxy-groups".into(), vec![Value::Mapping(group)].into());
+    config.insert("rules".into(), vec![format!("MATCH,{PROXY_GROUP}")].into());
+    Some(config)
+}
+
+/// parse the single proxy uri to the clash proxy
+fn parse_uri(uri: &str) -> Option<Mapping> {
+    let (scheme, rest) = uri.split_once("://")?;
+    match scheme.to_lowercase().as_str() {
+        "ss" => parse_ss(rest),
+        "vmess" => parse_vmess(rest),
+        "vless" => parse_vless(rest),
+        "trojan" => parse_trojan(rest),
+        "hysteria2" | "hy2" => parse_hysteria2(rest),
+        _ => None,
+    }
+}
+
+/// `ss://base64(method:password)@server:port/?plugin=xxx#name` (SIP002)
+/// `ss://base64(method:password@server:port)#name` (legacy)
+fn parse_ss(rest: &str) -> Option<Mapping> {
+    let (rest, name) = split_fragment(rest);
+    let (rest, query) = split_query(rest);
+    let rest = rest.trim_end_matches('/');
+
+    let rest = match rest.contains('@') {
+        true => rest.to_string(),
+        false => decode_base64(rest)?,
+    };
+    let (userinfo, addr) = rest.rsplit_once('@')?;
+    let userinfo = match userinfo.contains(':') {
+        true => decode_percent(userinfo),
+        false => decode_base64(&decode_percent(userinfo))?,
+    };
+    let (cipher, password) = userinfo.split_once(':')?;
+    let (server, port) = split_host_port(addr)?;
+
+    let mut proxy = new_proxy(name, "ss", &server, port);
+    proxy.insert("cipher".into(), cipher.into());
+    proxy.insert("password".into(), password.into());
+    proxy.insert("udp".into(), true.into());
+
+    if let Some(plugin) = query.get("plugin") {
+        let mut parts = plugin.split(';');
+        let plugin_name = parts.next().unwrap_or_default();
+        let opts = parts
+            .map(|s| s.split_once('=').unwrap_or((s, "")))
+            .collect::<HashMap<&str, &str>>();
+
+        let mut plugin_opts = Mapping::new();
+        match plugin_name {
+            "obfs-local" | "simple-obfs" => {
+                proxy.insert("plugin".into(), "obfs".into());
+                insert_some(&mut plugin_opts, "mode", opts.get("obfs"));
+                insert_some(&mut plugin_opts, "host", opts.get("obfs-host"));
+            }
+            "v2ray-plugin" => {
+                proxy.insert("plugin".into(), "v2ray-plugin".into());
+                plugin_opts.insert("mode".into(), "websocket".into());
+                plugin_opts.insert("tls".into(), opts.contains_key("tls").into());
+                insert_some(&mut plugin_opts, "host", opts.get("host"));
+                insert_some(&mut plugin_opts, "path", opts.get("path"));
+            }
+            // the plugin is not supported by clash
+            _ => return None,
+        }
+        proxy.insert("plugin-opts".into(), plugin_opts.into());
+    }
+    Some(proxy)
+}
+
+/// `vmess://base64({"v":"2","ps":"name","add":"server","port":"443","id":"uuid",...})`
+fn parse_vmess(rest: &str) -> Option<Mapping> {
+    let (rest, _) = split_fragment(rest);
+    let json = decode_base64(rest)?;
+    let value = serde_json::from_str::<serde_json::Value>(&json).ok()?;
+    // some fields may be number or string
+    let field = |key: &str| {
+        let field = match value.get(key)? {
+            serde_json::Value::String(s) => s.trim().to_string(),
+            serde_json::Value::Number(n) => n.to_string(),
+            _ => return None,
+        };
+        Some(field).filter(|s| !s.is_empty())
+    };
+
+    let server = field("add")?;
+    let port = field("port")?.parse::<u16>().ok()?;
+    let mut proxy = new_proxy(field("ps"), "vmess", &server, port);
+    proxy.insert("uuid".into(), field("id")?.into());
+    let alter_id = field("aid").and_then(|s| s.parse::<u64>().ok());
+    proxy.insert("alterId".into(), alter_id.unwrap_or(0).into());
+    let cipher = field("scy").unwrap_or("auto".into());
+    proxy.insert("cipher".into(), cipher.into());
+    proxy.insert("udp".into(), true.into());
+
+    if field("tls").as_deref() == Some("tls") {
+        proxy.insert("tls".into(), true.into());
+        insert_some(&mut proxy, "servername", field("sni").or(field("host")));
+        insert_some(&mut proxy, "client-fingerprint", field("fp"));
+    }
+    // the grpc service name is stored in `path`
+    let network = field("net").unwrap_or("tcp".into());
+    let (host, path) = (field("host"), field("path"));
+    apply_transport(&mut proxy, &network, host, path.clone(), path);
+    Some(proxy)
+}
+
+/// `vless://uuid@server:port?security=tls&type=ws&sni=xxx#name`
+fn parse_vless(rest: &str) -> Option<Mapping> {
+    let (rest, name) = split_fragment(rest);
+    let (rest, query) = split_query(rest);
+    let (uuid, addr) = rest.trim_end_matches('/').rsplit_once('@')?;
+    let (server, port) = split_host_port(addr)?;
+
+    let mut proxy = new_proxy(name, "vless", &server, port);
+    proxy.insert("uuid".into(), decode_percent(uuid).into());
+    proxy.insert("udp".into(), true.into());
+    insert_some(&mut proxy, "flow", query.get("flow"));
+
+    let security = query.get("security").map(String::as_str);
+    if matches!(security, Some("tls") | Some("reality")) {
+        proxy.insert("tls".into(), true.into());
+        insert_some(&mut proxy, "servername", query.get("sni"));
+        insert_some(&mut proxy, "client-fingerprint", query.get("fp"));
+        apply_tls_options(&mut proxy, &query);
+    }
+    if security == Some("reality") {
+        let mut reality_opts = Mapping::new();
+        insert_some(&mut reality_opts, "public-key", query.get("pbk"));
+        insert_some(&mut reality_opts, "short-id", query.get("sid"));
+        proxy.insert("reality-opts".into(), reality_opts.into());
+    }
+    apply_query_transport(&mut proxy, &query);
+    Some(proxy)
+}
+
+/// `trojan://password@server:port?sni=xxx&type=ws#name`
+fn parse_trojan(rest: &str) -> Option<Mapping> {
+    let (rest, name) = split_fragment(rest);
+    let (rest, query) = split_query(rest);
+    let (password, addr) = rest.trim_end_matches('/').rsplit_once('@')?;
+    let (server, port) = split_host_port(addr)?;
+
+    let mut proxy = new_proxy(name, "trojan", &server, port);
+    proxy.insert("password".into(), decode_percent(password).into());
+    proxy.insert("udp".into(), true.into());
+    insert_some(&mut proxy, "sni", query.get("sni").or(query.get("peer")));
+    insert_some(&mut proxy, "client-fingerprint", query.get("fp"));
+    apply_tls_options(&mut proxy, &query);
+    apply_query_transport(&mut proxy, &query);
+    Some(proxy)
+}
+
+/// `hysteria2://auth@server:port?sni=xxx&obfs=salamander&obfs-password=xxx#name`
+fn parse_hysteria2(rest: &str) -> Option<Mapping> {
+    let (rest, name) = split_fragment(rest);
+    let (rest, query) = split_query(rest);
+    let (password, addr) = rest.trim_end_matches('/').rsplit_once('@')?;
+    let (server, port) = split_host_port(addr)?;
+
+    let mut proxy = new_proxy(name, "hysteria2", &server, port);
+    proxy.insert("password".into(), decode_percent(password).into());
+    insert_some(&mut proxy, "sni", query.get("sni"));
+    insert_some(&mut proxy, "obfs", query.get("obfs"));
+    insert_some(&mut proxy, "obfs-password", query.get("obfs-password"));
+    apply_tls_options(&mut proxy, &query);
+    Some(proxy)
+}
+
+/// the common fields of the proxy
+fn new_proxy(name: Option<String>, typ: &str, server: &str, port: u16) -> Mapping {
+    let name = name.unwrap_or(format!("{server}:{port}"));
+
+    let mut proxy = Mapping::new();
+    proxy.insert("name".into(), name.into());
+    proxy.insert("type".into(), typ.into());
+    proxy.insert("server".into(), server.into());
+    proxy.insert("port".into(), port.into());
+    proxy
+}
+
+/// `allowInsecure=1` / `insecure=1` and `alpn=h2,http/1.1`
+fn apply_tls_options(proxy: &mut Mapping, query: &HashMap<String, String>) {
+    let insecure = query.get("allowInsecure").or(query.get("insecure"));
+    if matches!(insecure.map(String::as_str), Some("1") | Some("true")) {
+        proxy.insert("skip-cert-verify".into(), true.into());
+    }
+    if let Some(alpn) = query.get("alpn") {
+        let alpn = alpn.split(',').map(str::to_string).collect::<Vec<String>>();
+        proxy.insert("alpn".into(), alpn.into());
+    }
+}
+
+/// the transport options of the uri query
+fn apply_query_transport(proxy: &mut Mapping, query: &HashMap<String, String>) {
+    let network = query.get("type").map(String::as_str).unwrap_or("tcp");
+    apply_transport(
+        proxy,
+        network,
+        query.get("host").cloned(),
+        query.get("path").cloned(),
+        query.get("serviceName").cloned(),
+    );
+}
+
+fn apply_transport(
+    proxy: &mut Mapping,
+    network: &str,
+    host: Option<String>,
+    path: Option<String>,
+    service_name: Option<String>,
+) {
+    let mut opts = Mapping::new();
+    let key = match network {
+        "ws" | "httpupgrade" => {
+            insert_some(&mut opts, "path", path.as_ref());
+            if let Some(host) = host {
+                let mut headers = Mapping::new();
+                headers.insert("Host".into(), host.into());
+                opts.insert("headers".into(), headers.into());
+            }
+            if network == "httpupgrade" {
+                opts.insert("v2ray-http-upgrade".into(), true.into());
+            }
+            "ws-opts"
+        }
+        "grpc" => {
+            insert_some(&mut opts, "grpc-service-name", service_name.as_ref());
+            "grpc-opts"
+        }
+        "h2" => {
+            if let Some(host) = host {
+                opts.insert("host".into(), vec![host].into());
+            }
+            insert_some(&mut opts, "path", path.as_ref());
+            "h2-opts"
+        }
+        _ => return,
+    };
+
+    let network = match network {
+        "httpupgrade" => "ws",
+        network => network,
+    };
+    proxy.insert("network".into(), network.into());
+    proxy.insert(key.into(), opts.into());
+}
+
+fn insert_some<T: AsRef<str>>(map: &mut Mapping, key: &str, value: Option<T>) {
+    if let Some(value) = value {
+        if !value.as_ref().is_empty() {
+            map.insert(key.into(), value.as_ref().into());
+        }
+    }
+}
+
+/// make the proxy name unique by appending the index
+fn unique_name(names: &mut HashSet<String>, name: String) -> String {
+    let mut unique = name.clone();
+    let mut index = 1;
+    while !names.insert(unique.clone()) {
+        index += 1;
+        unique = format!("{name} {index}");
+    }
+    unique
+}
+
+/// split `xxx#name` and decode the name
+fn split_fragment(s: &str) -> (&str, Option<String>) {
+    match s.split_once('#') {
+        Some((rest, name)) => {
+            let name = decode_percent(name);
+            (rest, Some(name).filter(|n| !n.trim().is_empty()))
+        }
+        None => (s, None),
+    }
+}
+
+/// split `xxx?a=1&b=2` and decode the query
+fn split_query(s: &str) -> (&str, HashMap<String, String>) {
+    match s.split_once('?') {
+        Some((rest, query)) => {
+            let query = query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(k, v)| (k.to_string(), decode_percent(v)))
+                .collect();
+            (rest, query)
+        }
+        None => (s, HashMap::new()),
+    }
+}
+
+/// split `server:port` or `[ipv6]:port`
+fn split_host_port(addr: &str) -> Option<(String, u16)> {
+    let (host, port) = addr.rsplit_once(':')?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+fn decode_percent(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().to_string()
+}
+
+/// decode the standard or url safe base64 with or without padding
+fn decode_base64(s: &str) -> Option<String> {
+    let s = s.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    let s = s.trim_end_matches('=');
+    let bytes = general_purpose::STANDARD_NO_PAD
+        .decode(s)
+        .or_else(|_| general_purpose::URL_SAFE_NO_PAD.decode(s))
+        .ok()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[test]
+fn test_convert_subscription() {
+    let list = [
+        "ss://YWVzLTI1Ni1nY206cGFzc3dvcmQ@1.2.3.4:8388#ss%20node",
+        "ss://YWVzLTI1Ni1nY206cGFzc3dvcmRAMS4yLjMuNDo4Mzg4#legacy",
+        "vless://uuid@example.com:443?security=reality&sni=a.com&pbk=key&sid=01&type=grpc&serviceName=svc#vless",
+        "trojan://pass@[::1]:443?sni=b.com&type=ws&path=%2Fws&host=c.com&allowInsecure=1#trojan",
+        "hy2://auth@example.com:8443?sni=d.com&obfs=salamander&obfs-password=x",
+        "trojan://pass@example.com:443#trojan",
+        "unknown://foo",
+        "not a uri",
+    ];
+    let data = general_purpose::STANDARD.encode(list.join("\n"));
+    let config = convert_subscription(&data).unwrap();
+
+    let proxies = config.get("proxies").unwrap().as_sequence().unwrap();
+    assert_eq!(proxies.len(), 6);
+    let get = |i: usize, key: &str| proxies[i].get(key).cloned().unwrap_or(Value::Null);
+
+    assert_eq!(get(0, "name"), "ss node");
+    assert_eq!(get(0, "cipher"), "aes-256-gcm");
+    assert_eq!(get(0, "password"), "password");
+    assert_eq!(get(1, "server"), "1.2.3.4");
+    assert_eq!(get(1, "port"), 8388);
+
+    assert_eq!(get(2, "type"), "vless");
+    assert_eq!(get(2, "servername"), "a.com");
+    assert_eq!(get(2, "network"), "grpc");
+    assert_eq!(
+        get(2, "reality-opts").get("public-key").cloned(),
+        Some("key".into())
+    );
+
+    assert_eq!(get(3, "server"), "::1");
+    assert_eq!(get(3, "skip-cert-verify"), true);
+    assert_eq!(get(3, "ws-opts").get("path").cloned(), Some("/ws".into()));
+
+    assert_eq!(get(4, "type"), "hysteria2");
+    assert_eq!(get(4, "name"), "example.com:8443");
+    assert_eq!(get(5, "name"), "trojan 2");
+
+    // plain uri list and no valid uri
+    assert!(convert_subscription(list[0]).is_some());
+    assert!(convert_subscription("<html></html>").is_none());
+}
+
+#[test]
+fn test_parse_vmess() {
+    let json = r#"{"v":"2","ps":"vmess","add":"1.2.3.4","port":443,"id":"uuid","aid":"0","net":"ws","host":"a.com","path":"/p","tls":"tls"}"#;
+    let uri = format!("vmess://{}", general_purpose::STANDARD.encode(json));
+    let proxy = parse_uri(&uri).unwrap();
+
+    assert_eq!(proxy.get("port").cloned(), Some(443.into()));
+    assert_eq!(proxy.get("cipher").cloned(), Some("auto".into()));
+    assert_eq!(proxy.get("servername").cloned(), Some("a.com".into()));
+    assert_eq!(proxy.get("network").cloned(), Some("ws".into()));
+}
@@ -1,36 +1,314 @@
-use crate::config::{IVerge, PrfOption};
+use crate::config::{IClashTemp, IVerge, PrfOption, DEFAULT_WINDOW_MONITOR};
 use crate::{
-    config::{Config, PrfItem},
+    config::{Config, PrfItem, PrfTagError},
     core::*,
     utils::init,
     utils::server,
 };
 use crate::{log_err, trace_err};
-use anyhow::Result;
+use anyhow::{bail, Result};
 use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde::Serialize;
 use serde_yaml::Mapping;
-use std::net::TcpListener;
-use tauri::api::notification;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tauri::api::{self, notification};
 use tauri::{App, AppHandle, Manager};
 use window_shadows::set_shadow;
 
 pub static VERSION: OnceCell<String> = OnceCell::new();
 
+/// the launch arg to start without showing the window
+pub const SILENT_ARG: &str = "--silent";
+
+/// the launch arg of the elevated relaunch, the new instance waits for the old one to exit
+pub const RELAUNCH_ARG: &str = "--relaunch";
+
+/// the loopback addresses which the port should be available on by `ip_version`
+pub fn loopback_addrs(ip_version: Option<&str>) -> Vec<IpAddr> {
+    let v4 = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+    match ip_version {
+        Some("v6") => vec![v6],
+        Some("dual") => vec![v4, v6],
+        _ => vec![v4],
+    }
+}
+
+/// the loopback host which the app dials the proxy port on
+/// the core only listens on `::1` in the `v6` mode without lan
+pub fn loopback_host() -> &'static str {
+    let ip_version = { Config::verge().latest().ip_version.clone() };
+    match ip_version.as_deref() {
+        Some("v6") => "::1",
+        _ => "127.0.0.1",
+    }
+}
+
+/// `loopback_host` in the url, the ipv6 address is bracketed
+pub fn loopback_url_host() -> &'static str {
+    match loopback_host() {
+        "::1" => "[::1]",
+        host => host,
+    }
+}
+
+/// the `ipv6` and `bind-address` of the clash config by `ip_version`
+/// every mode writes both, so the values of the former mode are reset
+/// the core binds `bind-address` only if `allow-lan` is on, otherwise `127.0.0.1`,
+/// so `v6` without lan turns `allow-lan` on and binds `::1`, which is still loopback only
+/// `dual` without lan listens on `127.0.0.1`, `::` would expose the port to the lan
+pub fn listen_config(ip_version: &str, allow_lan: bool) -> Result<Mapping> {
+    let (ipv6, bind_address) = match (ip_version, allow_lan) {
+        ("v4", true) => (false, "0.0.0.0"),
+        ("v4", false) => (false, "127.0.0.1"),
+        ("v6", true) => (true, "::"),
+        ("v6", false) => (true, "::1"),
+        ("dual", true) => (true, "*"),
+        ("dual", false) => (true, "127.0.0.1"),
+        _ => bail!("invalid ip version \"{ip_version}\""),
+    };
+
+    let mut mapping = Mapping::new();
+    mapping.insert("ipv6".into(), ipv6.into());
+    mapping.insert("bind-address".into(), bind_address.into());
+    if ip_version == "v6" && !allow_lan {
+        mapping.insert("allow-lan".into(), true.into());
+    }
+    Ok(mapping)
+}
+
+/// bind the port on all the addresses, port 0 means a random one
+fn bind_port(addrs: &[IpAddr], port: u16) -> Option<u16> {
+    let listener = TcpListener::bind((*addrs.first()?, port)).ok()?;
+    let port = listener.local_addr().ok()?.port();
+    // the same port should be available on the other addresses
+    let others = addrs[1..]
+        .iter()
+        .map(|addr| TcpListener::bind((*addr, port)))
+        .collect::<std::io::Result<Vec<TcpListener>>>();
+    others.ok().map(|_| port)
+}
+
 pub fn find_unused_port() -> Result<u16> {
-    match TcpListener::bind("127.0.0.1:0") {
-        Ok(listener) => {
-            let port = listener.local_addr()?.port();
-            Ok(port)
+    let ip_version = Config::verge().latest().ip_version.clone();
+    let addrs = loopback_addrs(ip_version.as_deref());
+
+    // the random port of v4 may be in use on v6, retry a few times
+    if let Some(port) = (0..5).find_map(|_| bind_port(&addrs, 0)) {
+        return Ok(port);
+    }
+
+    let port = Config::verge()
+        .latest()
+        .verge_mixed_port
+        .unwrap_or(Config::clash().data().get_mixed_port());
+    if bind_port(&addrs, port).is_none() {
+        log::warn!(target: "app", "the default port {port} is not available on {addrs:?}");
+    }
+    log::warn!(target: "app", "use default port: {}", port);
+    Ok(port)
+}
+
+/// whether the port can be bound on the loopback addresses
+pub fn is_port_available(port: u16) -> bool {
+    let ip_version = Config::verge().latest().ip_version.clone();
+    let addrs = loopback_addrs(ip_version.as_deref());
+    port != 0 && bind_port(&addrs, port).is_some()
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct PortStatus {
+    /// `mixed` | `socks` | `http` | `redir` | `tproxy` | `controller`
+    pub name: String,
+    pub port: u16,
+    pub available: bool,
+    /// the port is taken by the running core with the same usage, not a conflict
+    pub used_by_core: bool,
+}
+
+/// the listening ports of the clash config
+pub fn listen_ports(config: &Mapping) -> Vec<(&'static str, u16)> {
+    let mut ports = vec![
+        ("mixed", IClashTemp::guard_mixed_port(config)),
+        ("socks", IClashTemp::guard_socks_port(config)),
+        ("http", IClashTemp::guard_port(config)),
+    ];
+    #[cfg(not(target_os = "windows"))]
+    ports.push(("redir", IClashTemp::guard_redir_port(config)));
+    #[cfg(target_os = "linux")]
+    ports.push(("tproxy", IClashTemp::guard_tproxy_port(config)));
+
+    let ctrl = IClashTemp::guard_client_ctrl(config);
+    if let Ok(addr) = ctrl.parse::<SocketAddr>() {
+        ports.push(("controller", addr.port()));
+    }
+    ports
+}
+
+/// the ports turned off in the verge settings
+pub fn disabled_ports() -> Vec<&'static str> {
+    let verge = Config::verge();
+    let verge = verge.latest();
+    let mut disabled = vec![];
+    if !verge.verge_socks_enabled.unwrap_or(true) {
+        disabled.push("socks");
+    }
+    if !verge.verge_http_enabled.unwrap_or(true) {
+        disabled.push("http");
+    }
+    #[cfg(not(target_os = "windows"))]
+    if !verge.verge_redir_enabled.unwrap_or(true) {
+        disabled.push("redir");
+    }
+    #[cfg(target_os = "linux")]
+    if !verge.verge_tproxy_enabled.unwrap_or(true) {
+        disabled.push("tproxy");
+    }
+    disabled
+}
+
+/// 同一个端口不能给两个用途
+pub fn check_port_conflicts(ports: &[(&str, u16)]) -> Result<()> {
+    for (i, (name, port)) in ports.iter().enumerate() {
+        if let Some((other, _)) = ports[..i].iter().find(|(_, p)| p == port) {
+            bail!("the {name} port {port} conflicts with the {other} port");
         }
-        Err(_) => {
-            let port = Config::verge()
-                .latest()
-                .verge_mixed_port
-                .unwrap_or(Config::clash().data().get_mixed_port());
-            log::warn!(target: "app", "use default port: {}", port);
-            Ok(port)
+    }
+    Ok(())
+}
+
+/// 检查配置中的端口是否被其他程序占用
+pub fn check_ports() -> Vec<PortStatus> {
+    let disabled = disabled_ports();
+    let mut ports = { listen_ports(&Config::clash().latest().0) };
+    // the random port is resolved into `verge_mixed_port`
+    if let Some(port) = { Config::verge().latest().verge_mixed_port } {
+        ports[0].1 = port;
+    }
+    let running = match monitor::Monitor::global().core_running() {
+        true => Config::runtime().latest().config.as_ref().map(listen_ports),
+        false => None,
+    };
+    let running = running.unwrap_or_default();
+
+    ports
+        .into_iter()
+        .filter(|(name, _)| !disabled.contains(name))
+        .map(|(name, port)| {
+            let available = is_port_available(port);
+            PortStatus {
+                name: name.into(),
+                port,
+                available,
+                used_by_core: !available && running.contains(&(name, port)),
+            }
+        })
+        .collect()
+}
+
+/// a phase slower than this is logged as a warning
+const STARTUP_WARN_MS: u64 = 2000;
+/// give up waiting for the core after this
+const CORE_READY_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupPhase {
+    pub name: String,
+    pub ms: u64,
+}
+
+/// the time spent by each phase of `resolve_setup`
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct StartupTimings {
+    /// the unix time when the setup starts
+    pub started: i64,
+    pub phases: Vec<StartupPhase>,
+    /// the whole `resolve_setup`
+    pub total_ms: u64,
+    /// from the setup start to the core answering the controller
+    /// none if the core is not ready yet or failed to start
+    pub core_ready_ms: Option<u64>,
+    /// the core is not ready if any resource is broken
+    pub missing_resources: Vec<String>,
+}
+
+impl StartupTimings {
+    pub fn global() -> &'static Mutex<StartupTimings> {
+        static TIMINGS: OnceCell<Mutex<StartupTimings>> = OnceCell::new();
+        TIMINGS.get_or_init(|| Mutex::new(StartupTimings::default()))
+    }
+
+    /// `setup 850ms: resources 20ms, init_config 120ms, ...`
+    fn summary(&self) -> String {
+        let phases = self
+            .phases
+            .iter()
+            .map(|p| format!("{} {}ms", p.name, p.ms))
+            .collect::<Vec<String>>();
+        format!("setup {}ms: {}", self.total_ms, phases.join(", "))
+    }
+
+    /// record the phase since `since` and reset it to now
+    fn record(name: &str, since: &mut Instant) {
+        let ms = since.elapsed().as_millis() as u64;
+        *since = Instant::now();
+        if ms > STARTUP_WARN_MS {
+            log::warn!(target: "app", "the startup phase \"{name}\" takes {ms}ms");
+        }
+        Self::global().lock().phases.push(StartupPhase {
+            name: name.into(),
+            ms,
+        });
+    }
+}
+
+/// 等待内核的外部控制可用，记录启动耗时
+fn wait_core_ready(start: Instant) {
+    tauri::async_runtime::spawn(async move {
+        while start.elapsed() < CORE_READY_TIMEOUT {
+            if CoreManager::global().get_version().await.is_ok() {
+                let missing = { StartupTimings::global().lock().missing_resources.clone() };
+                if !missing.is_empty() {
+                    log::warn!(target: "app", "the core is not ready, missing {missing:?}");
+                    return;
+                }
+                let ms = start.elapsed().as_millis() as u64;
+                log::info!(target: "app", "the core is ready in {ms}ms");
+                StartupTimings::global().lock().core_ready_ms = Some(ms);
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
         }
+        log::warn!(target: "app", "the core is not ready after {CORE_READY_TIMEOUT:?}");
+    });
+}
+
+/// 检查资源文件，坏掉的内核可能启动不了
+/// `notify` the user by the system notification if some are still broken
+pub fn check_resources(notify: bool) -> Result<Vec<init::ResourceState>> {
+    let list = init::verify_resources()?;
+    let broken = list
+        .iter()
+        .filter(|state| !state.is_ok())
+        .map(|state| state.name.clone())
+        .collect::<Vec<String>>();
+
+    if notify && !broken.is_empty() {
+        let body = format!(
+            "The resources are broken: {}, try reinstalling the app",
+            broken.join(", ")
+        );
+        log::error!(target: "app", "{body}");
+        log_err!(notification::Notification::new(crate::utils::dirs::APP_ID)
+            .title("Clash Verge")
+            .body(body)
+            .show());
     }
+    StartupTimings::global().lock().missing_resources = broken;
+    Ok(list)
 }
 
 /// handle something when start app
@@ -41,9 +319,16 @@ pub fn resolve_setup(app: &mut App) {
     handle::Handle::global().init(app.app_handle());
     VERSION.get_or_init(|| version.clone());
 
+    let start = Instant::now();
+    let mut since = start;
+    StartupTimings::global().lock().started = chrono::Local::now().timestamp();
+
     log_err!(init::init_resources());
+    log_err!(check_resources(true));
     log_err!(init::init_scheme());
     log_err!(init::startup_script());
+    StartupTimings::record("resources", &mut since);
+
     // 处理随机端口
     let enable_random_port = Config::verge().latest().enable_random_port.unwrap_or(false);
 
@@ -61,48 +346,105 @@ pub fn resolve_setup(app: &mut App) {
         );
     }
 
+    // 单独的 socks / http 端口，同样处理占用和冲突
+    let (socks_port, http_port) = {
+        let verge = Config::verge();
+        let verge = verge.latest();
+        (verge.verge_socks_port, verge.verge_port)
+    };
+    let mut taken = vec![port];
+    let mut resolve_port = |name: &str, value: Option<u16>| {
+        let mut value = value?;
+        if taken.contains(&value) || !is_port_available(value) {
+            log::warn!(target: "app", "the {name} port {value} is not available");
+            if enable_random_port {
+                value = find_unused_port().ok().filter(|p| !taken.contains(p))?;
+            }
+        }
+        taken.push(value);
+        Some(value)
+    };
+    let socks_port = resolve_port("socks", socks_port);
+    let http_port = resolve_port("http", http_port);
+
     Config::verge().data().patch_config(IVerge {
         verge_mixed_port: Some(port),
+        verge_socks_port: socks_port,
+        verge_port: http_port,
         ..IVerge::default()
     });
     let _ = Config::verge().data().save_file();
     let mut mapping = Mapping::new();
     mapping.insert("mixed-port".into(), port.into());
+    if let Some(port) = socks_port {
+        mapping.insert("socks-port".into(), port.into());
+    }
+    if let Some(port) = http_port {
+        mapping.insert("port".into(), port.into());
+    }
     Config::clash().data().patch_config(mapping);
     let _ = Config::clash().data().save_config();
 
+    // 上次异常退出时遗留的系统代理
+    log_err!(sysopt::Sysopt::global().recover_sysproxy());
+
     // 启动核心
     log::trace!("init config");
     log_err!(Config::init_config());
+    StartupTimings::record("init_config", &mut since);
 
     log::trace!("launch core");
     log_err!(CoreManager::global().init());
+    monitor::Monitor::global().init();
+    conn_stats::ConnStats::global().init();
+    wait_core_ready(start);
+    StartupTimings::record("launch_core", &mut since);
 
     // setup a simple http server for singleton
     log::trace!("launch embed server");
     server::embed_server(app.app_handle());
+    handle_exit_signal(app.app_handle());
 
     log::trace!("init system tray");
     log_err!(tray::Tray::update_systray(&app.app_handle()));
+    StartupTimings::record("tray", &mut since);
+
+    let argvs: Vec<String> = std::env::args().collect();
 
-    let silent_start = { Config::verge().data().enable_silent_start };
-    if !silent_start.unwrap_or(false) {
+    let (silent_start, start_minimized) = {
+        let verge = Config::verge();
+        let verge = verge.data();
+        (verge.enable_silent_start, verge.start_minimized)
+    };
+    // 开机启动项里带上 --silent 时同样不显示窗口
+    let silent_start = silent_start.unwrap_or(false) || argvs.iter().any(|a| a == SILENT_ARG);
+    if start_minimized.unwrap_or(false) {
+        build_window(&app.app_handle(), true);
+    } else if !silent_start {
         create_window(&app.app_handle());
     }
+    StartupTimings::record("window", &mut since);
 
     log_err!(sysopt::Sysopt::global().init_launch());
+    log_err!(sysopt::Sysopt::global().reconcile_launch());
     log_err!(sysopt::Sysopt::global().init_sysproxy());
+    StartupTimings::record("sysproxy", &mut since);
 
     log_err!(handle::Handle::update_systray_part());
     log_err!(hotkey::Hotkey::global().init(app.app_handle()));
     log_err!(timer::Timer::global().init());
+    StartupTimings::record("hotkey_timer", &mut since);
 
-    let argvs: Vec<String> = std::env::args().collect();
-    if argvs.len() > 1 {
+    let deep_link = argvs.iter().skip(1).find(|a| a.starts_with("clash://"));
+    if let Some(param) = deep_link {
         tauri::async_runtime::block_on(async {
-            resolve_scheme(argvs[1].to_owned()).await;
+            resolve_scheme(param.to_owned()).await;
         });
     }
+
+    let mut timings = StartupTimings::global().lock();
+    timings.total_ms = start.elapsed().as_millis() as u64;
+    log::info!(target: "app", "{}", timings.summary());
 }
 
 /// reset system proxy
@@ -111,6 +453,95 @@ pub fn resolve_reset() {
     log_err!(CoreManager::global().stop_core());
 }
 
+/// relaunch the app as administrator with the current args, windows only
+/// the system proxy is reset and the core is stopped first, restored if it fails
+/// the app running as root would leave the config files owned by root,
+/// grant the permission to the core or use the service mode instead on macos/linux
+#[cfg(not(target_os = "windows"))]
+pub async fn relaunch_as_admin(_: &AppHandle) -> Result<()> {
+    bail!("relaunching the app as root is not supported, grant the permission to the core or use the service mode instead");
+}
+
+/// relaunch the app as administrator with the current args, windows only
+/// the system proxy is reset and the core is stopped first, restored if it fails
+#[cfg(target_os = "windows")]
+pub async fn relaunch_as_admin(app_handle: &AppHandle) -> Result<()> {
+    if manager::is_elevated() {
+        bail!("the app is running elevated already");
+    }
+
+    let mut args = std::env::args()
+        .skip(1)
+        .filter(|arg| arg != RELAUNCH_ARG)
+        .collect::<Vec<_>>();
+    args.push(RELAUNCH_ARG.into());
+
+    save_window_size_position(app_handle, true)?;
+    resolve_reset();
+
+    let spawned =
+        tauri::async_runtime::spawn_blocking(move || manager::spawn_elevated(&args)).await?;
+    if let Err(err) = spawned {
+        log_err!(CoreManager::global().run_core().await);
+        log_err!(sysopt::Sysopt::global().update_sysproxy());
+        return Err(err);
+    }
+
+    on_exit(app_handle);
+    app_handle.exit(0);
+    std::process::exit(0);
+}
+
+/// the cleanup before the app exits
+/// only runs once even if there are multiple exit signals
+pub fn on_exit(app_handle: &AppHandle) {
+    static EXITED: AtomicBool = AtomicBool::new(false);
+    if EXITED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    log::info!(target: "app", "clean up before exit");
+    log_err!(save_window_size_position(app_handle, true));
+    resolve_reset();
+    server::release_instance_lock();
+    api::process::kill_children();
+    log::logger().flush();
+}
+
+/// 收到退出信号时 (kill、注销等) 同样恢复系统代理并保存窗口
+fn handle_exit_signal(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let (Ok(mut term), Ok(mut hup)) = (
+                signal(SignalKind::terminate()),
+                signal(SignalKind::hangup()),
+            ) else {
+                return;
+            };
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
+                _ = hup.recv() => {}
+            }
+        }
+        #[cfg(not(unix))]
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+
+        log::info!(target: "app", "receive the exit signal");
+        // on_exit blocks on the runtime, keep it out of the async context
+        let _ = tauri::async_runtime::spawn_blocking(move || {
+            on_exit(&app_handle);
+            app_handle.exit(0);
+            std::process::exit(0);
+        })
+        .await;
+    });
+}
+
 /// create main window
 pub fn create_window(app_handle: &AppHandle) {
     if let Some(window) = app_handle.get_window("main") {
@@ -120,6 +551,16 @@ pub fn create_window(app_handle: &AppHandle) {
         return;
     }
 
+    build_window(app_handle, false);
+}
+
+/// build the main window
+/// the window keeps hidden in the tray if `minimized` is true
+fn build_window(app_handle: &AppHandle, minimized: bool) {
+    let always_on_top = Config::verge()
+        .latest()
+        .window_always_on_top
+        .unwrap_or(false);
     let mut builder = tauri::window::WindowBuilder::new(
         app_handle,
         "main".to_string(),
@@ -128,9 +569,28 @@ pub fn create_window(app_handle: &AppHandle) {
     .title("Clash Verge")
     .visible(false)
     .fullscreen(false)
+    .always_on_top(always_on_top)
     .min_inner_size(600.0, 520.0);
 
-    match Config::verge().latest().window_size_position.clone() {
+    // 前端加载完成后会自行显示窗口，最小化启动时告知前端不要显示
+    if minimized {
+        builder = builder.initialization_script("window.__VERGE_START_MINIMIZED__ = true;");
+    }
+
+    // 用上次所在显示器的大小位置，不在屏幕内时下面会居中
+    let layout = {
+        let verge = Config::verge();
+        let verge = verge.latest();
+        let monitor = verge
+            .window_monitor
+            .as_deref()
+            .unwrap_or(DEFAULT_WINDOW_MONITOR);
+        verge
+            .window_layouts
+            .as_ref()
+            .and_then(|layouts| layouts.get(monitor).cloned())
+    };
+    match layout {
         Some(size_pos) if size_pos.len() == 4 => {
             let size = (size_pos[0], size_pos[1]);
             let pos = (size_pos[2], size_pos[3]);
@@ -156,9 +616,21 @@ pub fn create_window(app_handle: &AppHandle) {
         }
     };
     #[cfg(target_os = "windows")]
+    let (webview_extra_args, software_rendering) = {
+        let verge = Config::verge();
+        let verge = verge.latest();
+        (
+            verge.webview_extra_args.clone(),
+            verge.software_rendering.unwrap_or(false),
+        )
+    };
+    #[cfg(target_os = "windows")]
     let window = builder
         .decorations(false)
-        .additional_browser_args("--enable-features=msWebView2EnableDraggableRegions --disable-features=OverscrollHistoryNavigation,msExperimentalScrolling")
+        .additional_browser_args(&webview_args(
+            webview_extra_args.as_deref(),
+            software_rendering,
+        ))
         .transparent(true)
         .visible(false)
         .build();
@@ -178,20 +650,18 @@ pub fn create_window(app_handle: &AppHandle) {
                 .window_is_maximized
                 .unwrap_or(false);
             log::trace!("try to calculate the monitor size");
+            // 保存的位置可能在其他显示器上，检查所有显示器
             let center = (|| -> Result<bool> {
-                let mut center = false;
-                let monitor = win.current_monitor()?.ok_or(anyhow::anyhow!(""))?;
-                let size = monitor.size();
                 let pos = win.outer_position()?;
-
-                if pos.x < -400
-                    || pos.x > (size.width - 200) as i32
-                    || pos.y < -200
-                    || pos.y > (size.height - 200) as i32
-                {
-                    center = true;
-                }
-                Ok(center)
+                let monitors = win
+                    .available_monitors()?
+                    .iter()
+                    .map(|m| {
+                        let (pos, size) = (m.position(), m.size());
+                        (pos.x, pos.y, size.width as i32, size.height as i32)
+                    })
+                    .collect::<Vec<_>>();
+                Ok(!is_on_monitors((pos.x, pos.y), &monitors))
             })();
             if center.unwrap_or(true) {
                 trace_err!(win.center(), "set win center");
@@ -202,6 +672,9 @@ pub fn create_window(app_handle: &AppHandle) {
             if is_maximized {
                 trace_err!(win.maximize(), "set win maximize");
             }
+            if minimized {
+                trace_err!(win.hide(), "set win hidden");
+            }
         }
         Err(_) => {
             log::error!("failed to create window");
@@ -210,45 +683,223 @@ pub fn create_window(app_handle: &AppHandle) {
     }
 }
 
+/// the args of WebView2 which the window depends on
+const WEBVIEW_ENABLE_FEATURES: [&str; 1] = ["msWebView2EnableDraggableRegions"];
+const WEBVIEW_DISABLE_FEATURES: [&str; 2] =
+    ["OverscrollHistoryNavigation", "msExperimentalScrolling"];
+
+/// 参数直接传给浏览器进程，不允许引号和shell的特殊字符
+pub fn check_webview_args(args: &str) -> Result<()> {
+    const FORBIDDEN: &[char] = &['"', '\'', '`', '$', '&', '|', ';', '<', '>', '\\'];
+    let invalid = |c: &char| FORBIDDEN.contains(c) || c.is_control();
+    if let Some(c) = args.chars().find(invalid) {
+        bail!("the webview args should not contain {c:?}");
+    }
+    if let Some(arg) = args.split_whitespace().find(|arg| !arg.starts_with("--")) {
+        bail!("the webview arg \"{arg}\" should start with \"--\"");
+    }
+    Ok(())
+}
+
+/// append the extra args to the defaults
+/// the features are merged, the later `--enable-features` overrides the former in chromium
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn webview_args(extra: Option<&str>, disable_gpu: bool) -> String {
+    let mut enable = WEBVIEW_ENABLE_FEATURES.map(String::from).to_vec();
+    let mut disable = WEBVIEW_DISABLE_FEATURES.map(String::from).to_vec();
+    let mut others = vec![];
+
+    let extra = match extra.map(|args| (args, check_webview_args(args))) {
+        Some((args, Ok(_))) => args,
+        Some((_, Err(err))) => {
+            log::warn!(target: "app", "ignore the webview args, {err}");
+            ""
+        }
+        None => "",
+    };
+    for arg in extra.split_whitespace() {
+        let features = |value: &str| {
+            value
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .map(String::from)
+                .collect::<Vec<String>>()
+        };
+        if let Some(value) = arg.strip_prefix("--enable-features=") {
+            enable.extend(features(value));
+        } else if let Some(value) = arg.strip_prefix("--disable-features=") {
+            disable.extend(features(value));
+        } else {
+            others.push(arg);
+        }
+    }
+
+    let mut args = vec![
+        format!("--enable-features={}", enable.join(",")),
+        format!("--disable-features={}", disable.join(",")),
+    ];
+    args.extend(others.into_iter().map(String::from));
+    if disable_gpu && !args.iter().any(|arg| arg == "--disable-gpu") {
+        args.push("--disable-gpu".into());
+    }
+    args.join(" ")
+}
+
+/// whether the window at `pos` (top left) can be seen on one of the monitors `(x, y, w, h)`
+/// allow a little part out of the screen like before
+fn is_on_monitors(pos: (i32, i32), monitors: &[(i32, i32, i32, i32)]) -> bool {
+    monitors.iter().any(|&(x, y, w, h)| {
+        pos.0 >= x - 400 && pos.0 <= x + w - 200 && pos.1 >= y - 200 && pos.1 <= y + h - 200
+    })
+}
+
+/// handle the close button of the main window by `close_to_tray`
+/// return true if the window should not be closed
+pub fn handle_close_requested(app_handle: &AppHandle) -> bool {
+    let close_to_tray = Config::verge().latest().close_to_tray;
+    match close_to_tray {
+        Some(true) => {
+            if let Some(window) = app_handle.get_window("main") {
+                trace_err!(window.hide(), "set win hidden");
+            }
+            log_err!(notice_close_to_tray());
+            true
+        }
+        Some(false) => {
+            on_exit(app_handle);
+            app_handle.exit(0);
+            std::process::exit(0);
+        }
+        None => false,
+    }
+}
+
+/// tell the user the app is still running in the tray, only for the first time
+fn notice_close_to_tray() -> Result<()> {
+    let verge = Config::verge();
+    let mut verge = verge.data();
+    if verge.close_to_tray_noticed.unwrap_or(false) {
+        return Ok(());
+    }
+
+    notification::Notification::new(crate::utils::dirs::APP_ID)
+        .title("Clash Verge")
+        .body("Clash Verge is still running in the tray, quit it from the tray menu")
+        .show()?;
+
+    verge.close_to_tray_noticed = Some(true);
+    verge.save_file()
+}
+
 /// save window size and position
+/// the window may be closed already, then only the last recorded one is saved
 pub fn save_window_size_position(app_handle: &AppHandle, save_to_file: bool) -> Result<()> {
     let verge = Config::verge();
     let mut verge = verge.latest();
 
+    if let Some(win) = app_handle.get_window("main") {
+        let scale = win.scale_factor()?;
+        let size = win.inner_size()?;
+        let size = size.to_logical::<f64>(scale);
+        let pos = win.outer_position()?;
+        let pos = pos.to_logical::<f64>(scale);
+        let is_maximized = win.is_maximized()?;
+        verge.window_is_maximized = Some(is_maximized);
+        if !is_maximized && size.width >= 600.0 && size.height >= 520.0 {
+            let monitor = win
+                .current_monitor()?
+                .and_then(|m| m.name().cloned())
+                .unwrap_or(DEFAULT_WINDOW_MONITOR.into());
+            verge
+                .window_layouts
+                .get_or_insert_with(Default::default)
+                .insert(monitor.clone(), vec![size.width, size.height, pos.x, pos.y]);
+            verge.window_monitor = Some(monitor);
+        }
+    }
+
     if save_to_file {
         verge.save_file()?;
     }
+    Ok(())
+}
 
-    let win = app_handle
-        .get_window("main")
-        .ok_or(anyhow::anyhow!("failed to get window"))?;
+/// the profile to import by the deep link
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SchemeImport {
+    pub url: String,
+    pub name: Option<String>,
+    pub icon: Option<String>,
+}
+
+/// `clash://install-config?url=<url>&name=<name>&icon=<icon>`
+/// the url may be not encoded and has its own query, so only `name` and `icon` are split out
+pub fn parse_scheme(param: &str) -> Option<SchemeImport> {
+    let query = param
+        .trim_start_matches("clash://install-config/?")
+        .trim_start_matches("clash://install-config?");
+    let decode = |s: &str| {
+        let s = percent_encoding::percent_decode_str(s).decode_utf8_lossy();
+        Some(s.trim().to_string()).filter(|s| !s.is_empty())
+    };
 
-    let scale = win.scale_factor()?;
-    let size = win.inner_size()?;
-    let size = size.to_logical::<f64>(scale);
-    let pos = win.outer_position()?;
-    let pos = pos.to_logical::<f64>(scale);
-    let is_maximized = win.is_maximized()?;
-    verge.window_is_maximized = Some(is_maximized);
-    if !is_maximized && size.width >= 600.0 && size.height >= 520.0 {
-        verge.window_size_position = Some(vec![size.width, size.height, pos.x, pos.y]);
+    let mut import = SchemeImport::default();
+    let mut in_url = false;
+    for part in query.split('&') {
+        if let Some(name) = part.strip_prefix("name=") {
+            import.name = decode(&name.replace('+', " "));
+            in_url = false;
+        } else if let Some(icon) = part.strip_prefix("icon=") {
+            import.icon = decode(icon);
+            in_url = false;
+        } else if let Some(url) = part.strip_prefix("url=") {
+            import.url = url.to_string();
+            in_url = true;
+        } else if in_url {
+            import.url.push('&');
+            import.url.push_str(part);
+        }
+    }
+
+    // 编码过的url
+    if !import.url.contains("://") {
+        import.url = decode(&import.url).unwrap_or_default();
+    }
+    match import.url.is_empty() {
+        true => None,
+        false => Some(import),
     }
-    Ok(())
 }
 
 pub async fn resolve_scheme(param: String) {
-    let url = param
-        .trim_start_matches("clash://install-config/?url=")
-        .trim_start_matches("clash://install-config?url=");
+    let Some(import) = parse_scheme(&param) else {
+        log::error!("failed to parse the deep link: {param}");
+        return;
+    };
+    let url = import.url.as_str();
     let option = PrfOption {
         user_agent: None,
         with_proxy: Some(true),
         self_proxy: None,
         danger_accept_invalid_certs: None,
         update_interval: None,
+        download_timeout_secs: None,
+        proxy_url: None,
+        doh_url: None,
+        http_method: None,
+        http_headers: None,
+        http_body: None,
     };
-    if let Ok(item) = PrfItem::from_url(url, None, None, Some(option)).await {
+    if let Ok(mut item) = PrfItem::from_url(url, import.name, None, Some(option)).await {
+        // 图标是可选的，无效时丢掉图标，不影响导入
+        item.icon = import.icon;
+        if let Err(PrfTagError::InvalidIcon(icon)) = item.check_tags() {
+            log::warn!(target: "app", "drop the invalid icon \"{icon}\" of the imported profile");
+            item.icon = None;
+        }
         if Config::profiles().data().append_item(item).is_ok() {
+            log_err!(handle::Handle::update_systray());
+            handle::Handle::refresh_profiles();
             notification::Notification::new(crate::utils::dirs::APP_ID)
                 .title("Clash Verge")
                 .body("Import profile success")
@@ -264,3 +915,103 @@ pub async fn resolve_scheme(param: String) {
         log::error!("failed to parse url: {}", url);
     }
 }
+
+#[test]
+fn test_parse_scheme() {
+    let parse = |s: &str| parse_scheme(s).unwrap();
+
+    let import = parse("clash://install-config?url=https://a.com/sub?token=1&flag=meta");
+    assert_eq!(import.url, "https://a.com/sub?token=1&flag=meta");
+    assert_eq!(import.name, None);
+
+    let import = parse(
+        "clash://install-config/?url=https%3A%2F%2Fa.com%2Fsub%3Ftoken%3D1&name=My+Sub&icon=%F0%9F%9A%80",
+    );
+    assert_eq!(import.url, "https://a.com/sub?token=1");
+    assert_eq!(import.name.as_deref(), Some("My Sub"));
+    assert_eq!(import.icon.as_deref(), Some("🚀"));
+
+    let import = parse("clash://install-config?name=A&url=https://a.com/sub?x=1&y=2&icon=");
+    assert_eq!(import.url, "https://a.com/sub?x=1&y=2");
+    assert_eq!(import.name.as_deref(), Some("A"));
+    assert_eq!(import.icon, None);
+
+    assert!(parse_scheme("clash://install-config?name=A").is_none());
+}
+
+#[test]
+fn test_check_port_conflicts() {
+    let ports = [("mixed", 7897), ("socks", 7898), ("http", 7899)];
+    assert!(check_port_conflicts(&ports).is_ok());
+
+    let ports = [("mixed", 7897), ("socks", 7898), ("controller", 7898)];
+    let err = check_port_conflicts(&ports).unwrap_err().to_string();
+    assert!(err.contains("controller port 7898"));
+}
+
+#[test]
+fn test_is_on_monitors() {
+    let primary = (0, 0, 1920, 1080);
+    let secondary = (1920, -200, 2560, 1440);
+    assert!(is_on_monitors((100, 100), &[primary]));
+    assert!(!is_on_monitors((2400, 300), &[primary]));
+    assert!(is_on_monitors((2400, 300), &[primary, secondary]));
+    assert!(is_on_monitors((-300, 0), &[primary]));
+    assert!(!is_on_monitors((100, 1000), &[primary, secondary]));
+    assert!(!is_on_monitors((100, 100), &[]));
+}
+
+#[test]
+fn test_webview_args() {
+    let default = "--enable-features=msWebView2EnableDraggableRegions \
+        --disable-features=OverscrollHistoryNavigation,msExperimentalScrolling";
+    assert_eq!(webview_args(None, false), default);
+    assert_eq!(webview_args(Some("  "), false), default);
+    assert_eq!(webview_args(None, true), format!("{default} --disable-gpu"));
+
+    let args = webview_args(Some("--disable-gpu --disable-features=Foo,Bar"), true);
+    assert!(args.ends_with(",msExperimentalScrolling,Foo,Bar --disable-gpu"));
+
+    assert!(check_webview_args("--proxy-server=\"a\"").is_err());
+    assert!(check_webview_args("--a && calc").is_err());
+    assert!(check_webview_args("disable-gpu").is_err());
+    // the invalid args are ignored
+    assert_eq!(webview_args(Some("--a; calc"), false), default);
+}
+
+#[test]
+fn test_listen_config() {
+    let listen = |ip_version: &str, allow_lan: bool| {
+        let mapping = listen_config(ip_version, allow_lan).unwrap();
+        (
+            mapping.get("ipv6").and_then(|v| v.as_bool()),
+            mapping
+                .get("bind-address")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            mapping.get("allow-lan").and_then(|v| v.as_bool()),
+        )
+    };
+
+    // v4 resets the values of v6
+    assert_eq!(
+        listen("v4", true),
+        (Some(false), Some("0.0.0.0".into()), None)
+    );
+    assert_eq!(
+        listen("v4", false),
+        (Some(false), Some("127.0.0.1".into()), None)
+    );
+    assert_eq!(listen("v6", true), (Some(true), Some("::".into()), None));
+    assert_eq!(
+        listen("v6", false),
+        (Some(true), Some("::1".into()), Some(true))
+    );
+    assert_eq!(listen("dual", true), (Some(true), Some("*".into()), None));
+    assert_eq!(
+        listen("dual", false),
+        (Some(true), Some("127.0.0.1".into()), None)
+    );
+    assert!(listen_config("v5", false).is_err());
+    assert!(listen_config("", true).is_err());
+}
@@ -0,0 +1,255 @@
+use serde::Serialize;
+use std::net::IpAddr;
+
+/// the result of replaying the rules against the target
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RuleMatch {
+    /// the host (or ip) of the target
+    pub target: String,
+    /// the index of the matched rule
+    pub index: Option<usize>,
+    /// the matched rule, e.g. `DOMAIN-SUFFIX,google.com,PROXY`
+    pub rule: Option<String>,
+    /// the proxy or the group of the matched rule
+    pub proxy: Option<String>,
+    /// the addresses which the ip rules are matched against
+    /// resolved by the system resolver, the core may resolve differently (e.g. fake-ip)
+    pub resolved: Vec<String>,
+    /// the rules before the matched one which could not be evaluated locally
+    /// one of them may match in the core actually
+    pub skipped: Vec<SkippedRule>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedRule {
+    pub index: usize,
+    pub rule: String,
+    pub reason: String,
+}
+
+enum Matched {
+    Yes,
+    No,
+    Unknown(&'static str),
+}
+
+/// whether the rules need the ip of the domain
+pub fn needs_resolve(rules: &[String]) -> bool {
+    rules.iter().any(|rule| {
+        let parts = split_rule(rule);
+        matches!(parts[0].as_str(), "IP-CIDR" | "IP-CIDR6")
+            && !parts.iter().any(|p| p == "no-resolve")
+    })
+}
+
+/// split `host:port` / `[ipv6]:port`, the port is optional
+pub fn parse_target(target: &str) -> (String, Option<u16>) {
+    let target = target.trim();
+    let target = target
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(target);
+    let target = target.split('/').next().unwrap_or_default();
+
+    if target.parse::<IpAddr>().is_ok() {
+        return (target.to_string(), None);
+    }
+    if let Some((host, port)) = target.rsplit_once(':') {
+        if let Ok(port) = port.parse::<u16>() {
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            return (host.to_lowercase(), Some(port));
+        }
+    }
+    (target.trim_matches(['[', ']']).to_lowercase(), None)
+}
+
+/// replay the rules in order against the target
+/// `resolved` is the ip of the domain target, used by the ip rules without `no-resolve`
+pub fn match_rules(rules: &[String], target: &str, resolved: &[IpAddr]) -> RuleMatch {
+    let (host, port) = parse_target(target);
+    let host_ip = host.parse::<IpAddr>().ok();
+
+    let mut result = RuleMatch {
+        target: host.clone(),
+        ..RuleMatch::default()
+    };
+
+    for (index, rule) in rules.iter().enumerate() {
+        let parts = split_rule(rule);
+        let rule_type = parts[0].as_str();
+        let payload = parts.get(1).map(String::as_str).unwrap_or_default();
+        let no_resolve = parts.iter().any(|p| p == "no-resolve");
+
+        // MATCH,proxy
+        if rule_type == "MATCH" || rule_type == "FINAL" {
+            result.index = Some(index);
+            result.rule = Some(rule.clone());
+            result.proxy = parts.get(1).cloned();
+            result.message = "no rule matched, falls through to MATCH".into();
+            return result;
+        }
+
+        let ips = match (host_ip, no_resolve) {
+            (Some(ip), _) => vec![ip],
+            (None, false) => resolved.to_vec(),
+            (None, true) => vec![],
+        };
+
+        let matched = match rule_type {
+            "DOMAIN" if host_ip.is_none() => bool_match(host == payload.to_lowercase()),
+            "DOMAIN-SUFFIX" if host_ip.is_none() => {
+                let suffix = payload.to_lowercase();
+                bool_match(host == suffix || host.ends_with(&format!(".{suffix}")))
+            }
+            "DOMAIN-KEYWORD" if host_ip.is_none() => {
+                bool_match(host.contains(&payload.to_lowercase()))
+            }
+            "DOMAIN" | "DOMAIN-SUFFIX" | "DOMAIN-KEYWORD" | "DOMAIN-REGEX" if host_ip.is_some() => {
+                Matched::No
+            }
+            "IP-CIDR" | "IP-CIDR6" => {
+                if host_ip.is_none() && !ips.is_empty() {
+                    result.resolved = ips.iter().map(|ip| ip.to_string()).collect();
+                }
+                match cidr_contains_any(payload, &ips) {
+                    Some(matched) => bool_match(matched),
+                    None => Matched::Unknown("invalid cidr"),
+                }
+            }
+            "DST-PORT" => match port {
+                Some(port) => bool_match(port_in(payload, port)),
+                None => Matched::Unknown("the target has no port"),
+            },
+            "DOMAIN-REGEX" => Matched::Unknown("the regex is not supported locally"),
+            "GEOIP" | "GEOSITE" | "IP-ASN" | "RULE-SET" => {
+                Matched::Unknown("the database or the provider is only available in the core")
+            }
+            "AND" | "OR" | "NOT" | "SUB-RULE" => {
+                Matched::Unknown("the logic rule is not supported locally")
+            }
+            // the source, process and network rules depend on the connection
+            _ => Matched::Unknown("the rule depends on the connection"),
+        };
+
+        match matched {
+            Matched::Yes => {
+                result.index = Some(index);
+                result.rule = Some(rule.clone());
+                result.proxy = proxy_of(&parts);
+                result.message = format!("matched the rule at index {index}");
+                return result;
+            }
+            Matched::No => {}
+            Matched::Unknown(reason) => result.skipped.push(SkippedRule {
+                index,
+                rule: rule.clone(),
+                reason: reason.into(),
+            }),
+        }
+    }
+
+    result.message = "no rule matched, the connection goes DIRECT".into();
+    result
+}
+
+fn bool_match(matched: bool) -> Matched {
+    match matched {
+        true => Matched::Yes,
+        false => Matched::No,
+    }
+}
+
+fn split_rule(rule: &str) -> Vec<String> {
+    let mut parts = rule
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .collect::<Vec<String>>();
+    parts[0] = parts[0].to_uppercase();
+    parts
+}
+
+/// `TYPE,payload,proxy[,options]`
+fn proxy_of(parts: &[String]) -> Option<String> {
+    parts.get(2).cloned()
+}
+
+/// `80` / `8000-9000` / `80/443`
+fn port_in(payload: &str, port: u16) -> bool {
+    payload.split('/').any(|item| match item.split_once('-') {
+        Some((start, end)) => match (start.parse::<u16>(), end.parse::<u16>()) {
+            (Ok(start), Ok(end)) => (start..=end).contains(&port),
+            _ => false,
+        },
+        None => item.parse::<u16>() == Ok(port),
+    })
+}
+
+/// none if the cidr is invalid
+fn cidr_contains_any(cidr: &str, ips: &[IpAddr]) -> Option<bool> {
+    let (net, len) = cidr.split_once('/')?;
+    let net = net.parse::<IpAddr>().ok()?;
+    let len = len.parse::<u32>().ok()?;
+
+    let contains = |ip: &IpAddr| match (net, ip) {
+        (IpAddr::V4(net), IpAddr::V4(ip)) if len <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - len).unwrap_or(0);
+            u32::from(net) & mask == u32::from(*ip) & mask
+        }
+        (IpAddr::V6(net), IpAddr::V6(ip)) if len <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - len).unwrap_or(0);
+            u128::from(net) & mask == u128::from(*ip) & mask
+        }
+        _ => false,
+    };
+    Some(ips.iter().any(contains))
+}
+
+#[test]
+fn test_match_rules() {
+    let rules = [
+        "DOMAIN,exact.com,A",
+        "DOMAIN-SUFFIX,google.com,PROXY",
+        "DOMAIN-KEYWORD,github,PROXY",
+        "GEOSITE,cn,DIRECT",
+        "IP-CIDR,192.168.0.0/16,DIRECT,no-resolve",
+        "IP-CIDR6,2001:db8::/32,B",
+        "DST-PORT,8000-9000,C",
+        "IP-CIDR,10.0.0.0/8,LAN",
+        "MATCH,FINAL",
+    ]
+    .map(String::from);
+
+    let res = match_rules(&rules, "www.google.com", &[]);
+    assert_eq!(res.index, Some(1));
+    assert_eq!(res.proxy.as_deref(), Some("PROXY"));
+    assert!(res.skipped.is_empty());
+
+    // not the suffix
+    let res = match_rules(&rules, "notgoogle.com", &[]);
+    assert_eq!(res.index, Some(8));
+    assert_eq!(res.skipped.len(), 2);
+    assert_eq!(res.skipped[0].index, 3);
+    assert_eq!(res.skipped[1].index, 6);
+
+    let res = match_rules(&rules, "https://api.GitHub.com/path", &[]);
+    assert_eq!(res.index, Some(2));
+
+    let res = match_rules(&rules, "192.168.1.1", &[]);
+    assert_eq!(res.index, Some(4));
+    let res = match_rules(&rules, "[2001:db8::1]:443", &[]);
+    assert_eq!(res.index, Some(5));
+    let res = match_rules(&rules, "example.com:8080", &[]);
+    assert_eq!(res.index, Some(6));
+
+    // the domain is resolved for the ip rules without `no-resolve`
+    let resolved = ["10.1.2.3".parse().unwrap()];
+    let res = match_rules(&rules, "intranet.local", &resolved);
+    assert_eq!(res.index, Some(7));
+    assert_eq!(res.resolved, vec!["10.1.2.3".to_string()]);
+
+    let res = match_rules(&rules[..2], "example.com", &[]);
+    assert_eq!(res.index, None);
+    assert!(needs_resolve(&rules));
+    assert!(!needs_resolve(&rules[..5]));
+}
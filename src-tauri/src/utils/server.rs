@@ -1,58 +1,236 @@
 extern crate warp;
 
-use super::resolve;
-use crate::config::IVerge;
+use super::{dirs, local_api, resolve};
+use crate::{
+    config::IVerge,
+    core::{handle, sysopt::Sysopt},
+    log_err,
+};
 use anyhow::{bail, Result};
 use port_scanner::local_port_available;
-use std::convert::Infallible;
+use std::{
+    convert::Infallible,
+    fs,
+    io::{ErrorKind, Write},
+};
 use tauri::AppHandle;
 use warp::Filter;
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
 struct QueryParam {
     param: String,
 }
 
+/// 等待主实例的embed server启动，最多等 50 * 100ms
+const WAIT_PRIMARY_TIMES: usize = 50;
+
 /// check whether there is already exists
+/// the running instance listens on the port in the lock file,
+/// or on the configured port if the lock file is stale
+/// then take the instance lock, so only one of the simultaneous launches wins
 pub fn check_singleton() -> Result<()> {
-    let port = IVerge::get_singleton_port();
+    if std::env::args().any(|arg| arg == resolve::RELAUNCH_ARG) {
+        wait_previous_exit();
+    }
 
-    if !local_port_available(port) {
-        tauri::async_runtime::block_on(async {
-            let resp = reqwest::get(format!("http://127.0.0.1:{port}/commands/ping"))
-                .await?
-                .text()
-                .await?;
-
-            if &resp == "ok" {
-                let argvs: Vec<String> = std::env::args().collect();
-                if argvs.len() > 1 {
-                    let param = argvs[1].as_str();
-                    reqwest::get(format!(
-                        "http://127.0.0.1:{port}/commands/scheme?param={param}"
-                    ))
-                    .await?
-                    .text()
-                    .await?;
-                } else {
-                    reqwest::get(format!("http://127.0.0.1:{port}/commands/visible"))
-                        .await?
-                        .text()
-                        .await?;
-                }
-                bail!("app exists");
+    if notify_running() {
+        bail!("app exists");
+    }
+
+    for _ in 0..WAIT_PRIMARY_TIMES {
+        let pid = match acquire_instance_lock() {
+            Ok(None) => return Ok(()),
+            Ok(Some(pid)) => pid,
+            Err(err) => {
+                // 锁文件不可用时不影响启动
+                log::error!("failed to acquire the instance lock, {err}");
+                return Ok(());
             }
+        };
+
+        // 另一个实例刚启动，等它的embed server就绪后转发参数
+        if !is_app_process(pid) {
+            remove_stale_lock(pid);
+            continue;
+        }
+        if notify_running() {
+            bail!("app exists");
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    bail!("the primary instance is not ready")
+}
+
+/// 以管理员权限重启时，旧实例还在做退出前的清理，等它的embed server关闭
+fn wait_previous_exit() {
+    let port = read_lock_port().unwrap_or(IVerge::get_singleton_port());
+    for _ in 0..WAIT_PRIMARY_TIMES {
+        if local_port_available(port) {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+}
+
+/// remove the instance lock on exit
+pub fn release_instance_lock() {
+    if read_lock().0 == Some(std::process::id()) {
+        log_err!(dirs::singleton_lock_path().and_then(|path| Ok(fs::remove_file(path)?)));
+    }
+}
+
+/// notify the running instance if any
+fn notify_running() -> bool {
+    let mut ports = vec![];
+    if let Some(port) = read_lock_port() {
+        ports.push(port);
+    }
+    let port = IVerge::get_singleton_port();
+    if !ports.contains(&port) {
+        ports.push(port);
+    }
+
+    for port in ports {
+        if local_port_available(port) {
+            continue;
+        }
+
+        match tauri::async_runtime::block_on(notify_instance(port)) {
+            Ok(true) => return true,
+            Ok(false) => {}
+            Err(err) => log::error!("failed to notify the instance on port {port}, {err}"),
+        }
+    }
+    false
+}
+
+/// create the lock file exclusively and write the pid
+/// return the pid of the owner if the lock is taken
+fn acquire_instance_lock() -> Result<Option<u32>> {
+    let path = dirs::singleton_lock_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+    {
+        Ok(mut file) => {
+            file.write_all(std::process::id().to_string().as_bytes())?;
+            Ok(None)
+        }
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            // 刚创建还没写入pid时当作0，下一轮再读
+            Ok(Some(read_lock().0.unwrap_or(0)))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// remove the lock only if it is still owned by the stale pid
+/// another launch may have taken it already
+fn remove_stale_lock(pid: u32) {
+    if let Ok(path) = dirs::singleton_lock_path() {
+        if read_lock().0.unwrap_or(0) == pid {
+            log::info!("remove the stale instance lock of pid {pid}");
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// the pid is alive and is the app itself, not a reused pid
+fn is_app_process(pid: u32) -> bool {
+    use sysinfo::{Pid, System};
+
+    if pid == 0 {
+        return lock_is_fresh();
+    }
+    let exe = std::env::current_exe().ok();
+    let name = exe.as_ref().and_then(|exe| exe.file_name());
+
+    let mut system = System::new();
+    system.refresh_process(Pid::from_u32(pid));
+    match (system.process(Pid::from_u32(pid)), name) {
+        // linux截断了进程名
+        (Some(proc), Some(name)) => {
+            let proc_name = proc.name().to_lowercase();
+            let name = name.to_string_lossy().to_lowercase();
+            proc_name.starts_with(&name) || name.starts_with(&proc_name)
+        }
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+/// the lock without the pid is being written by another launch
+/// or left by a crash if it is old
+fn lock_is_fresh() -> bool {
+    dirs::singleton_lock_path()
+        .and_then(|path| Ok(fs::metadata(path)?.modified()?.elapsed()?))
+        .map_or(false, |elapsed| elapsed.as_secs() < 2)
+}
 
-            log::error!("failed to setup singleton listen server");
-            Ok(())
-        })
-    } else {
-        Ok(())
+/// forward the deep link (or just show the window) to the running instance
+/// return false if the port is not owned by the app
+async fn notify_instance(port: u16) -> Result<bool> {
+    let client = reqwest::ClientBuilder::new().no_proxy().build()?;
+    let resp = client
+        .get(format!("http://127.0.0.1:{port}/commands/ping"))
+        .send()
+        .await?
+        .text()
+        .await?;
+
+    if &resp != "ok" {
+        return Ok(false);
     }
+
+    let deep_link = std::env::args()
+        .nth(1)
+        .filter(|arg| arg.starts_with("clash://"));
+
+    match deep_link {
+        Some(param) => {
+            client
+                .post(format!("http://127.0.0.1:{port}/commands/scheme"))
+                .json(&QueryParam { param })
+                .send()
+                .await?
+        }
+        None => {
+            client
+                .get(format!("http://127.0.0.1:{port}/commands/visible"))
+                .send()
+                .await?
+        }
+    };
+    Ok(true)
+}
+
+fn read_lock_port() -> Option<u16> {
+    read_lock().1
+}
+
+/// the pid in the first line, the port in the second line
+fn read_lock() -> (Option<u32>, Option<u16>) {
+    let content = dirs::singleton_lock_path()
+        .and_then(|path| Ok(fs::read_to_string(path)?))
+        .unwrap_or_default();
+    parse_lock(&content)
 }
 
-/// The embed server only be used to implement singleton process
-/// maybe it can be used as pac server later
+fn parse_lock(content: &str) -> (Option<u32>, Option<u16>) {
+    let mut lines = content.lines().map(|line| line.trim());
+    let pid = lines.next().and_then(|line| line.parse().ok());
+    let port = lines.next().and_then(|line| line.parse().ok());
+    (pid, port)
+}
+
+/// The embed server is used to implement singleton process
+/// and serves the opt-in local api
+/// and serves the pac file of the system proxy
 pub fn embed_server(app_handle: AppHandle) {
     let port = IVerge::get_singleton_port();
 
@@ -64,15 +242,59 @@ pub fn embed_server(app_handle: AppHandle) {
             "ok"
         });
 
+        // the older version sends the deep link by query
         let scheme = warp::path!("commands" / "scheme")
+            .and(warp::get())
             .and(warp::query::<QueryParam>())
             .and_then(scheme_handler);
 
+        let scheme_post = warp::path!("commands" / "scheme")
+            .and(warp::post())
+            .and(warp::body::content_length_limit(1024 * 16))
+            .and(warp::body::json::<QueryParam>())
+            .and_then(scheme_handler);
+
         async fn scheme_handler(query: QueryParam) -> Result<impl warp::Reply, Infallible> {
             resolve::resolve_scheme(query.param).await;
+
+            let app_handle = handle::Handle::global().app_handle.lock().clone();
+            if let Some(app_handle) = app_handle {
+                resolve::create_window(&app_handle);
+            }
             Ok("ok")
         }
-        let commands = ping.or(visible).or(scheme);
-        warp::serve(commands).run(([127, 0, 0, 1], port)).await;
+        // 每次请求时生成，端口和bypass总是最新的
+        let pac = warp::path!("commands" / "pac").map(|| {
+            warp::reply::with_header(
+                Sysopt::global().pac_content(),
+                "Content-Type",
+                "application/x-ns-proxy-autoconfig",
+            )
+        });
+
+        let commands = ping
+            .or(visible)
+            .or(scheme)
+            .or(scheme_post)
+            .or(pac)
+            .or(local_api::routes());
+
+        match warp::serve(commands).try_bind_ephemeral(([127, 0, 0, 1], port)) {
+            Ok((addr, server)) => {
+                // 单例锁里追加端口，pid不变
+                let lock = format!("{}\n{}", std::process::id(), addr.port());
+                log_err!(dirs::singleton_lock_path().and_then(|path| Ok(fs::write(path, lock)?)));
+                server.await;
+            }
+            Err(err) => log::error!(target: "app", "failed to launch embed server, {err}"),
+        }
     });
 }
+
+#[test]
+fn test_parse_lock() {
+    assert_eq!(parse_lock(""), (None, None));
+    assert_eq!(parse_lock("1234"), (Some(1234), None));
+    assert_eq!(parse_lock("1234\n33331\n"), (Some(1234), Some(33331)));
+    assert_eq!(parse_lock("x\n33331"), (None, Some(33331)));
+}
@@ -16,7 +16,11 @@ import {
   Menu,
   CircularProgress,
 } from "@mui/material";
-import { RefreshRounded, DragIndicator } from "@mui/icons-material";
+import {
+  RefreshRounded,
+  DragIndicator,
+  WarningRounded,
+} from "@mui/icons-material";
 import { atomLoadingCache } from "@/services/states";
 import { updateProfile, deleteProfile, viewProfile } from "@/services/cmds";
 import { Notice } from "@/components/base";
@@ -49,13 +53,26 @@ export const ProfileItem = (props: Props) => {
   const [position, setPosition] = useState({ left: 0, top: 0 });
   const [loadingCache, setLoadingCache] = useRecoilState(atomLoadingCache);
 
-  const { uid, name = "Profile", extra, updated = 0 } = itemData;
+  const { uid, name = "Profile", icon, extra, updated = 0 } = itemData;
 
   // local file mode
   // remote file mode
   const hasUrl = !!itemData.url;
   const hasExtra = !!extra; // only subscription url has extra info
   const hasHome = !!itemData.home; // only subscription url has home page
+  const invalid = itemData.validation?.valid === false; // the last update failed the validation
+  const failed = !!itemData.last_update_error; // the last update failed to download
+  const warning = invalid || failed;
+  const warningMessage = failed
+    ? itemData.last_update_error
+    : itemData.validation?.message;
+
+  // 很久没有成功更新的订阅，内容未变的检查也算成功
+  const lastSuccess = Math.max(updated, itemData.checked ?? 0);
+  const interval = itemData.option?.update_interval ?? 0;
+  const staleSecs = interval > 0 ? Math.max(interval * 180, 864e2) : 7 * 864e2;
+  const stale =
+    hasUrl && lastSuccess > 0 && Date.now() / 1000 - lastSuccess > staleSecs;
 
   const { upload = 0, download = 0, total = 0 } = extra ?? {};
   const from = parseUrl(itemData.url);
@@ -263,14 +280,32 @@ export const ProfileItem = (props: Props) => {
               />
             </Box>
 
+            {warning && (
+              <WarningRounded
+                color={failed ? "error" : "warning"}
+                titleAccess={warningMessage}
+                sx={{ margin: "auto 4px auto 0" }}
+              />
+            )}
+
             <Typography
-              width="calc(100% - 36px)"
+              width={warning ? "calc(100% - 64px)" : "calc(100% - 36px)"}
               sx={{ fontSize: "18px", fontWeight: "600", lineHeight: "26px" }}
               variant="h6"
               component="h2"
               noWrap
               title={name}
             >
+              {icon &&
+                (/^(https?:|data:image\/)/.test(icon) ? (
+                  <img
+                    src={icon}
+                    alt=""
+                    style={{ width: 18, height: 18, marginRight: 6 }}
+                  />
+                ) : (
+                  <span style={{ marginRight: 6 }}>{icon}</span>
+                ))}
               {name}
             </Typography>
           </Box>
@@ -322,7 +357,10 @@ export const ProfileItem = (props: Props) => {
                   flex="1 0 auto"
                   fontSize={14}
                   textAlign="right"
-                  title={`Updated Time: ${parseExpire(updated)}`}
+                  color={stale ? "warning.main" : undefined}
+                  title={`${
+                    stale ? `${t("Not Updated For A Long Time")}, ` : ""
+                  }Updated Time: ${parseExpire(updated)}`}
                 >
                   {updated > 0 ? dayjs(updated * 1000).fromNow() : ""}
                 </Typography>
@@ -94,19 +94,22 @@ export const ClashPortViewer = forwardRef<DialogRef>((props, ref) => {
       return;
     }
     try {
+      // 一次性修改，避免交换端口时中途冲突
+      await patchInfo({
+        "mixed-port": mixedPort,
+        "socks-port": socksPort,
+        port,
+        ...(OS !== "windows" ? { "redir-port": redirPort } : {}),
+        ...(OS === "linux" ? { "tproxy-port": tproxyPort } : {}),
+      });
       if (OS !== "windows") {
-        await patchInfo({ "redir-port": redirPort });
         await patchVerge({ verge_redir_port: redirPort });
         await patchVerge({ verge_redir_enabled: redirEnabled });
       }
       if (OS === "linux") {
-        await patchInfo({ "tproxy-port": tproxyPort });
         await patchVerge({ verge_tproxy_port: tproxyPort });
         await patchVerge({ verge_tproxy_enabled: tproxyEnabled });
       }
-      await patchInfo({ "mixed-port": mixedPort });
-      await patchInfo({ "socks-port": socksPort });
-      await patchInfo({ port });
       await patchVerge({ verge_mixed_port: mixedPort });
       await patchVerge({ verge_socks_port: socksPort });
       await patchVerge({ verge_port: port });
@@ -15,11 +15,14 @@ const ItemWrapper = styled("div")`
 
 const HOTKEY_FUNC = [
   "open_or_close_dashboard",
+  "toggle_window",
   "clash_mode_rule",
   "clash_mode_global",
   "clash_mode_direct",
   "toggle_system_proxy",
   "toggle_tun_mode",
+  "open_config_dir",
+  "open_logs_dir",
 ];
 
 export const HotkeyViewer = forwardRef<DialogRef>((props, ref) => {
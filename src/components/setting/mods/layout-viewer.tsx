@@ -7,7 +7,12 @@ import { SettingItem } from "./setting-comp";
 import { GuardState } from "./guard-state";
 import { open as openDialog } from "@tauri-apps/api/dialog";
 import { convertFileSrc } from "@tauri-apps/api/tauri";
-import { copyIconFile, getAppDir } from "@/services/cmds";
+import {
+  copyIconFile,
+  getAppDir,
+  setAlwaysOnTop,
+  setSoftwareRendering,
+} from "@/services/cmds";
 import { join } from "@tauri-apps/api/path";
 import { exists } from "@tauri-apps/api/fs";
 import getSystem from "@/utils/get-system";
@@ -151,6 +156,63 @@ export const LayoutViewer = forwardRef<DialogRef>((props, ref) => {
           </SettingItem>
         )}
 
+        <SettingItem label={t("Always On Top")}>
+          <GuardState
+            value={verge?.window_always_on_top ?? false}
+            valueProps="checked"
+            onCatch={onError}
+            onFormat={onSwitchFormat}
+            onChange={(e) => onChangeData({ window_always_on_top: e })}
+            onGuard={(e) => setAlwaysOnTop(e)}
+          >
+            <Switch edge="end" />
+          </GuardState>
+        </SettingItem>
+
+        {OS !== "macos" && (
+          <SettingItem label={t("Software Rendering")}>
+            <GuardState
+              value={verge?.software_rendering ?? false}
+              valueProps="checked"
+              onCatch={onError}
+              onFormat={onSwitchFormat}
+              onChange={(e) => onChangeData({ software_rendering: e })}
+              onGuard={async (e) => {
+                await setSoftwareRendering(e);
+                Notice.success(t("Restart Application to Apply Modifications"));
+              }}
+            >
+              <Switch edge="end" />
+            </GuardState>
+          </SettingItem>
+        )}
+
+        <SettingItem label={t("Tray Mode Badge")}>
+          <GuardState
+            value={verge?.tray_mode_icon ?? false}
+            valueProps="checked"
+            onCatch={onError}
+            onFormat={onSwitchFormat}
+            onChange={(e) => onChangeData({ tray_mode_icon: e })}
+            onGuard={(e) => patchVerge({ tray_mode_icon: e })}
+          >
+            <Switch edge="end" />
+          </GuardState>
+        </SettingItem>
+
+        <SettingItem label={t("Tray Quick Actions")}>
+          <GuardState
+            value={verge?.tray_quick_actions ?? true}
+            valueProps="checked"
+            onCatch={onError}
+            onFormat={onSwitchFormat}
+            onChange={(e) => onChangeData({ tray_quick_actions: e })}
+            onGuard={(e) => patchVerge({ tray_quick_actions: e })}
+          >
+            <Switch edge="end" />
+          </GuardState>
+        </SettingItem>
+
         <SettingItem label={t("Common Tray Icon")}>
           <GuardState
             value={verge?.common_tray_icon}
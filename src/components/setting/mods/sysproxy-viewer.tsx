@@ -7,6 +7,8 @@ import {
   List,
   ListItem,
   ListItemText,
+  MenuItem,
+  Select,
   styled,
   TextField,
   Typography,
@@ -29,13 +31,19 @@ export const SysproxyViewer = forwardRef<DialogRef>((props, ref) => {
     enable_system_proxy: enabled,
     enable_proxy_guard,
     system_proxy_bypass,
+    system_proxy_mode,
     proxy_guard_duration,
+    kill_switch,
+    enable_captive_portal_detect,
   } = verge ?? {};
 
   const [value, setValue] = useState({
     guard: enable_proxy_guard,
     bypass: system_proxy_bypass,
     duration: proxy_guard_duration ?? 10,
+    killSwitch: kill_switch ?? false,
+    mode: system_proxy_mode ?? "fixed",
+    portal: enable_captive_portal_detect ?? false,
   });
 
   useImperativeHandle(ref, () => ({
@@ -45,6 +53,9 @@ export const SysproxyViewer = forwardRef<DialogRef>((props, ref) => {
         guard: enable_proxy_guard,
         bypass: system_proxy_bypass,
         duration: proxy_guard_duration ?? 10,
+        killSwitch: kill_switch ?? false,
+        mode: system_proxy_mode ?? "fixed",
+        portal: enable_captive_portal_detect ?? false,
       });
       getSystemProxy().then((p) => setSysproxy(p));
     },
@@ -65,6 +76,15 @@ export const SysproxyViewer = forwardRef<DialogRef>((props, ref) => {
     if (value.duration !== proxy_guard_duration) {
       patch.proxy_guard_duration = value.duration;
     }
+    if (value.killSwitch !== (kill_switch ?? false)) {
+      patch.kill_switch = value.killSwitch;
+    }
+    if (value.portal !== (enable_captive_portal_detect ?? false)) {
+      patch.enable_captive_portal_detect = value.portal;
+    }
+    if (value.mode !== (system_proxy_mode ?? "fixed")) {
+      patch.system_proxy_mode = value.mode;
+    }
     if (value.bypass !== system_proxy_bypass) {
       patch.system_proxy_bypass = value.bypass;
     }
@@ -89,6 +109,27 @@ export const SysproxyViewer = forwardRef<DialogRef>((props, ref) => {
       onOk={onSave}
     >
       <List>
+        <ListItem sx={{ padding: "5px 2px" }}>
+          <ListItemText
+            primary={t("Proxy Mode")}
+            secondary={t("Proxy Mode Info")}
+          />
+          <Select
+            size="small"
+            sx={{ width: 100, "> div": { py: "7.5px" } }}
+            value={value.mode}
+            onChange={(e) =>
+              setValue((v) => ({
+                ...v,
+                mode: e.target.value as "fixed" | "pac",
+              }))
+            }
+          >
+            <MenuItem value="fixed">{t("Fixed")}</MenuItem>
+            <MenuItem value="pac">PAC</MenuItem>
+          </Select>
+        </ListItem>
+
         <ListItem sx={{ padding: "5px 2px" }}>
           <ListItemText primary={t("Proxy Guard")} />
           <Switch
@@ -118,6 +159,31 @@ export const SysproxyViewer = forwardRef<DialogRef>((props, ref) => {
           />
         </ListItem>
 
+        <ListItem sx={{ padding: "5px 2px" }}>
+          <ListItemText
+            primary={t("Kill Switch")}
+            secondary={t("Kill Switch Info")}
+          />
+          <Switch
+            edge="end"
+            disabled={!enabled}
+            checked={value.killSwitch}
+            onChange={(_, e) => setValue((v) => ({ ...v, killSwitch: e }))}
+          />
+        </ListItem>
+
+        <ListItem sx={{ padding: "5px 2px" }}>
+          <ListItemText
+            primary={t("Captive Portal Detection")}
+            secondary={t("Captive Portal Detection Info")}
+          />
+          <Switch
+            edge="end"
+            checked={value.portal}
+            onChange={(_, e) => setValue((v) => ({ ...v, portal: e }))}
+          />
+        </ListItem>
+
         <ListItem sx={{ padding: "5px 2px", alignItems: "start" }}>
           <ListItemText primary={t("Proxy Bypass")} sx={{ padding: "3px 0" }} />
         </ListItem>
@@ -1,3 +1,4 @@
+import dayjs from "dayjs";
 import { useRef } from "react";
 import { useTranslation } from "react-i18next";
 import { useLockFn } from "ahooks";
@@ -18,10 +19,9 @@ import { ClashPortViewer } from "./mods/clash-port-viewer";
 import { ControllerViewer } from "./mods/controller-viewer";
 import { SettingList, SettingItem } from "./mods/setting-comp";
 import { ClashCoreViewer } from "./mods/clash-core-viewer";
-import { invoke_uwp_tool } from "@/services/cmds";
+import { invoke_uwp_tool, updateGeodata } from "@/services/cmds";
 import getSystem from "@/utils/get-system";
 import { useVerge } from "@/hooks/use-verge";
-import { updateGeoData } from "@/services/api";
 
 const isWIN = getSystem() === "windows";
 
@@ -37,7 +37,11 @@ const SettingClash = ({ onError }: Props) => {
 
   const { ipv6, "allow-lan": allowLan, "log-level": logLevel } = clash ?? {};
 
-  const { enable_random_port = false, verge_mixed_port } = verge ?? {};
+  const {
+    enable_random_port = false,
+    verge_mixed_port,
+    geodata_updated,
+  } = verge ?? {};
 
   const webRef = useRef<DialogRef>(null);
   const portRef = useRef<DialogRef>(null);
@@ -53,10 +57,11 @@ const SettingClash = ({ onError }: Props) => {
   };
   const onUpdateGeo = useLockFn(async () => {
     try {
-      await updateGeoData();
+      const updated = await updateGeodata();
+      onChangeVerge({ geodata_updated: updated });
       Notice.success(t("GeoData Updated"));
     } catch (err: any) {
-      Notice.error(err?.response.data.message || err.toString());
+      Notice.error(err?.message || err.toString());
     }
   });
 
@@ -199,7 +204,16 @@ const SettingClash = ({ onError }: Props) => {
         </SettingItem>
       )}
 
-      <SettingItem label={t("Update GeoData")}>
+      <SettingItem
+        label={t("Update GeoData")}
+        secondary={
+          geodata_updated
+            ? `${t("Update At")} ${dayjs(geodata_updated * 1000).format(
+                "YYYY-MM-DD HH:mm"
+              )}`
+            : undefined
+        }
+      >
         <IconButton
           color="inherit"
           size="small"
@@ -59,6 +59,7 @@ const Layout = () => {
     listen("verge://refresh-clash-config", async () => {
       // the clash info may be updated
       await getAxios(true);
+      mutate("getClashInfo");
       mutate("getProxies");
       mutate("getVersion");
       mutate("getClashConfig");
@@ -68,6 +69,12 @@ const Layout = () => {
     // update the verge config
     listen("verge://refresh-verge-config", () => mutate("getVergeConfig"));
 
+    // the proxy address displayed depends on the mixed port in use
+    listen("verge://mixed-port-changed", () => {
+      mutate("getRunningPort");
+      mutate("getVergeConfig");
+    });
+
     // 设置提示监听
     listen("verge://notice-message", ({ payload }) => {
       const [status, msg] = payload as [string, string];
@@ -78,6 +85,9 @@ const Layout = () => {
         case "set_config::error":
           Notice.error(msg);
           break;
+        case "set_config::warn":
+          Notice.info(msg);
+          break;
         default:
           break;
       }
@@ -85,6 +95,8 @@ const Layout = () => {
 
     setTimeout(async () => {
       portableFlag = await getPortableFlag();
+      // the window is minimized to the tray on startup
+      if ((window as any).__VERGE_START_MINIMIZED__) return;
       await appWindow.unminimize();
       await appWindow.show();
       await appWindow.setFocus();
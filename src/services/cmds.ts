@@ -26,10 +26,18 @@ export async function getClashLogs() {
     .filter(Boolean) as ILogItem[];
 }
 
+export async function getCoreLogs() {
+  return invoke<ICoreLog[]>("get_core_logs");
+}
+
 export async function getProfiles() {
   return invoke<IProfilesConfig>("get_profiles");
 }
 
+export async function searchProfiles(query: string) {
+  return invoke<string[]>("search_profiles", { query });
+}
+
 export async function enhanceProfiles() {
   return invoke<void>("enhance_profiles");
 }
@@ -38,6 +46,22 @@ export async function patchProfilesConfig(profiles: IProfilesConfig) {
   return invoke<void>("patch_profiles_config", { profiles });
 }
 
+export async function addProfileChain(
+  base: string,
+  uid: string,
+  index?: number
+) {
+  return invoke<void>("add_profile_chain", { base, uid, index });
+}
+
+export async function removeProfileChain(base: string, uid: string) {
+  return invoke<void>("remove_profile_chain", { base, uid });
+}
+
+export async function reorderProfileChain(base: string, order: string[]) {
+  return invoke<void>("reorder_profile_chain", { base, order });
+}
+
 export async function createProfile(
   item: Partial<IProfileItem>,
   fileData?: string | null
@@ -45,6 +69,10 @@ export async function createProfile(
   return invoke<void>("create_profile", { item, fileData });
 }
 
+export async function createMergeProfile(base: string, name?: string) {
+  return invoke<string>("create_merge_profile", { base, name });
+}
+
 export async function viewProfile(index: string) {
   return invoke<void>("view_profile", { index });
 }
@@ -53,14 +81,37 @@ export async function readProfileFile(index: string) {
   return invoke<string>("read_profile_file", { index });
 }
 
+export async function getProfileUserinfo(index: string) {
+  return invoke<IProfileItem["extra"] | null>("get_profile_userinfo", {
+    index,
+  });
+}
+
 export async function saveProfileFile(index: string, fileData: string) {
   return invoke<void>("save_profile_file", { index, fileData });
 }
 
-export async function importProfile(url: string) {
+export async function importProfilesBatch(urls: string[]) {
+  return invoke<IImportResult[]>("import_profiles_batch", { urls });
+}
+
+export async function probeSubscription(
+  url: string,
+  option?: IProfileOption
+) {
+  return invoke<ISubscriptionProbe>("probe_subscription", { url, option });
+}
+
+export async function importProfile(
+  url: string,
+  name?: string,
+  icon?: string
+) {
   return invoke<void>("import_profile", {
     url,
     option: { with_proxy: true },
+    name,
+    icon,
   });
 }
 
@@ -75,6 +126,22 @@ export async function updateProfile(index: string, option?: IProfileOption) {
   return invoke<void>("update_profile", { index, option });
 }
 
+export async function updateProfileNow(uid: string) {
+  return invoke<IUpdateResult>("update_profile_now", { uid });
+}
+
+export async function retryUpdate(uid: string) {
+  return invoke<void>("retry_update", { uid });
+}
+
+export async function updateAllProfiles() {
+  return invoke<IUpdateSummary>("update_all_profiles");
+}
+
+export async function cancelUpdateAll() {
+  return invoke<boolean>("cancel_update_all");
+}
+
 export async function deleteProfile(index: string) {
   return invoke<void>("delete_profile", { index });
 }
@@ -86,10 +153,39 @@ export async function patchProfile(
   return invoke<void>("patch_profile", { index, profile });
 }
 
+export async function renameProfile(uid: string, name: string) {
+  return invoke<void>("rename_profile", { uid, name });
+}
+
+export async function setUpdateInterval(
+  uid: string,
+  value: number,
+  unit: "minutes" | "hours" | "days"
+) {
+  return invoke<number>("set_update_interval", { uid, value, unit });
+}
+
+export async function raiseMergeOrder(uid: string) {
+  return invoke<number>("raise_merge_order", { uid });
+}
+
+export async function lowerMergeOrder(uid: string) {
+  return invoke<number>("lower_merge_order", { uid });
+}
+
 export async function getClashInfo() {
   return invoke<IClashInfo | null>("get_clash_info");
 }
 
+export async function getControllerInfo() {
+  return invoke<IControllerInfo>("get_controller_info");
+}
+
+/// rotate the controller secret, a random one if `secret` is omitted
+export async function setControllerSecret(secret?: string) {
+  return invoke<string>("set_controller_secret", { secret });
+}
+
 // Get runtime config which controlled by verge
 export async function getRuntimeConfig() {
   return invoke<IConfigData | null>("get_runtime_config");
@@ -99,6 +195,11 @@ export async function getRuntimeYaml() {
   return invoke<string | null>("get_runtime_yaml");
 }
 
+/// the config generated from the profile, the profile is not activated
+export async function dryRunProfile(uid: string) {
+  return invoke<string>("dry_run_profile", { uid });
+}
+
 export async function getRuntimeExists() {
   return invoke<string[]>("get_runtime_exists");
 }
@@ -107,10 +208,48 @@ export async function getRuntimeLogs() {
   return invoke<Record<string, [string, string][]>>("get_runtime_logs");
 }
 
+export async function getCoreMemory() {
+  return invoke<ICoreMemory>("get_core_memory");
+}
+
+export async function getConnectionStats(limit?: number) {
+  return invoke<IConnectionStats>("get_connection_stats", { limit });
+}
+
+export async function getStartupScriptResult() {
+  return invoke<IStartupScriptResult | null>("get_startup_script_result");
+}
+
+export async function getRunningPort() {
+  return invoke<number>("get_running_port");
+}
+
+export async function matchRule(target: string) {
+  return invoke<IRuleMatch>("match_rule", { target });
+}
+
 export async function patchClashConfig(payload: Partial<IConfigData>) {
   return invoke<void>("patch_clash_config", { payload });
 }
 
+export async function getCoreLogLevel() {
+  return invoke<string>("get_core_log_level");
+}
+
+export async function setCoreLogLevel(
+  level: "silent" | "error" | "warning" | "info" | "debug"
+) {
+  return invoke<void>("set_core_log_level", { level });
+}
+
+export async function getDnsConfig() {
+  return invoke<IClashDNS>("get_dns_config");
+}
+
+export async function patchDnsConfig(payload: Partial<IClashDNS>) {
+  return invoke<void>("patch_dns_config", { payload });
+}
+
 export async function getVergeConfig() {
   return invoke<IVergeConfig>("get_verge_config");
 }
@@ -119,6 +258,22 @@ export async function patchVergeConfig(payload: IVergeConfig) {
   return invoke<void>("patch_verge_config", { payload });
 }
 
+export async function resetVergeSettings() {
+  return invoke<string | null>("reset_verge_settings");
+}
+
+export async function resetVergeKey(key: keyof IVergeConfig) {
+  return invoke<any>("reset_verge_key", { key });
+}
+
+export async function enableSystemProxy() {
+  return invoke<void>("enable_system_proxy");
+}
+
+export async function disableSystemProxy() {
+  return invoke<void>("disable_system_proxy");
+}
+
 export async function getSystemProxy() {
   return invoke<{
     enable: boolean;
@@ -127,18 +282,124 @@ export async function getSystemProxy() {
   }>("get_sys_proxy");
 }
 
+export async function detectExistingProxy() {
+  return invoke<IExistingProxy | null>("detect_existing_proxy");
+}
+
+export async function getProxyEnv(shell: IShellKind) {
+  return invoke<string>("get_proxy_env", { shell });
+}
+
+export async function copyProxyEnv(shell: IShellKind) {
+  return invoke<void>("copy_proxy_env", { shell });
+}
+
 export async function changeClashCore(clashCore: string) {
   return invoke<any>("change_clash_core", { clashCore });
 }
 
+export async function getCoreVersion() {
+  return invoke<{
+    version: string;
+    meta: boolean;
+    premium: boolean;
+    features: string[];
+    path?: string;
+  }>("get_core_version");
+}
+
 export async function restartSidecar() {
   return invoke<void>("restart_sidecar");
 }
 
+export async function getCoreCrashState() {
+  return invoke<ICoreCrashState>("get_core_crash_state");
+}
+
+export async function flushFakeip() {
+  return invoke<IFlushResult>("flush_fakeip");
+}
+
+export async function refreshTrayIcon() {
+  return invoke<void>("refresh_tray_icon");
+}
+
 export async function grantPermission(core: string) {
   return invoke<void>("grant_permission", { core });
 }
 
+export async function checkTunCapability() {
+  return invoke<{ available: boolean; missing: string[] }>(
+    "check_tun_capability"
+  );
+}
+
+export async function isElevated() {
+  return invoke<boolean>("is_elevated");
+}
+
+export async function relaunchAsAdmin() {
+  return invoke<void>("relaunch_as_admin");
+}
+
+export async function readLogTail(lines: number) {
+  return invoke<string[]>("read_log_tail", { lines });
+}
+
+/// the new lines are emitted with `verge://log-lines`
+export async function startLogStream(lines: number) {
+  return invoke<string[]>("start_log_stream", { lines });
+}
+
+export async function stopLogStream() {
+  return invoke<void>("stop_log_stream");
+}
+
+export async function updateGeodata() {
+  return invoke<number>("update_geodata");
+}
+
+export async function exportHotkeys() {
+  return invoke<string>("export_hotkeys");
+}
+
+export async function importHotkeys(json: string) {
+  return invoke<void>("import_hotkeys", { json });
+}
+
+export async function getMode() {
+  return invoke<string>("get_mode");
+}
+
+/// return the previous mode
+export async function setMode(mode: string) {
+  return invoke<string>("set_mode", { mode });
+}
+
+export async function setTunMode(enable: boolean) {
+  return invoke<void>("set_tun_mode", { enable });
+}
+
+export async function setFallbackProfile(uid: string | null) {
+  return invoke<void>("set_fallback_profile", { uid });
+}
+
+export async function setTunAutoRoute(enable: boolean) {
+  return invoke<void>("set_tun_auto_route", { enable });
+}
+
+export async function setTunStrictRoute(enable: boolean) {
+  return invoke<void>("set_tun_strict_route", { enable });
+}
+
+export async function setTunDnsHijack(list: string[]) {
+  return invoke<void>("set_tun_dns_hijack", { list });
+}
+
+export async function setTunStack(stack: "system" | "gvisor" | "mixed") {
+  return invoke<void>("set_tun_stack", { stack });
+}
+
 export async function getAppDir() {
   return invoke<string>("get_app_dir");
 }
@@ -149,6 +410,12 @@ export async function openAppDir() {
   );
 }
 
+export async function openConfigDir() {
+  return invoke<void>("open_config_dir").catch((err) =>
+    Notice.error(err?.message || err.toString(), 1500)
+  );
+}
+
 export async function openCoreDir() {
   return invoke<void>("open_core_dir").catch((err) =>
     Notice.error(err?.message || err.toString(), 1500)
@@ -178,6 +445,110 @@ export async function cmdGetProxyDelay(
   });
 }
 
+export async function testProxyChain(
+  names: string[],
+  timeout: number,
+  url?: string
+) {
+  return invoke<IChainResult>("test_proxy_chain", { names, url, timeout });
+}
+
+export async function listProviders() {
+  return invoke<IProviderInfo[]>("list_providers");
+}
+
+export async function refreshProvider(kind: IProviderKind, name: string) {
+  return invoke<IProviderInfo>("refresh_provider", { kind, name });
+}
+
+export async function listRuleProviders() {
+  return invoke<IRuleProviderState[]>("list_rule_providers");
+}
+
+export async function setRuleProviderEnabled(name: string, enabled: boolean) {
+  return invoke<void>("set_rule_provider_enabled", { name, enabled });
+}
+
+export async function getLanAuthUsers() {
+  return invoke<string[]>("get_lan_auth_users");
+}
+
+export async function addLanAuthUser(user: string, pass: string) {
+  return invoke<void>("add_lan_auth_user", { user, pass });
+}
+
+export async function removeLanAuthUser(user: string) {
+  return invoke<void>("remove_lan_auth_user", { user });
+}
+
+export async function getConnections() {
+  return invoke<IConnectionInfo[]>("get_connections");
+}
+
+export async function closeConnection(id: string) {
+  return invoke<void>("close_connection", { id });
+}
+
+export async function getLocalApiToken() {
+  return invoke<string>("get_local_api_token");
+}
+
+export async function resetLocalApiToken() {
+  return invoke<string>("reset_local_api_token");
+}
+
+export async function isAutostartEnabled() {
+  return invoke<boolean>("is_autostart_enabled");
+}
+
+export async function setAutoLaunch(enable: boolean) {
+  return invoke<boolean>("set_auto_launch", { enable });
+}
+
+export async function setProxyPorts(socksPort?: number, port?: number) {
+  return invoke<void>("set_proxy_ports", { socksPort, port });
+}
+
+export async function setAlwaysOnTop(enable: boolean) {
+  return invoke<void>("set_always_on_top", { enable });
+}
+
+export async function setSoftwareRendering(enable: boolean) {
+  return invoke<void>("set_software_rendering", { enable });
+}
+
+export async function pauseTimers() {
+  return invoke<void>("pause_timers");
+}
+
+export async function resumeTimers() {
+  return invoke<void>("resume_timers");
+}
+
+export async function isTimerPaused() {
+  return invoke<boolean>("is_timer_paused");
+}
+
+export async function checkPorts() {
+  return invoke<IPortStatus[]>("check_ports");
+}
+
+export async function isPortAvailable(port: number) {
+  return invoke<boolean>("is_port_available", { port });
+}
+
+export async function getStartupTimings() {
+  return invoke<IStartupTimings>("get_startup_timings");
+}
+
+export async function verifyResources() {
+  return invoke<IResourceState[]>("verify_resources");
+}
+
+export async function repairAppDirs() {
+  return invoke<{ created: string[]; failed: string[] }>("repair_app_dirs");
+}
+
 export async function cmdTestDelay(url: string) {
   return invoke<number>("test_delay", { url });
 }
@@ -231,3 +602,11 @@ export async function copyIconFile(
 export async function downloadIconCache(url: string, name: string) {
   return invoke<string>("download_icon_cache", { url, name });
 }
+
+export async function exportBackup(path: string, includeLogs?: boolean) {
+  return invoke<void>("export_backup", { path, includeLogs });
+}
+
+export async function restoreBackup(path: string, force?: boolean) {
+  return invoke<void>("restore_backup", { path, force });
+}
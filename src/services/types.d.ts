@@ -155,6 +155,9 @@ interface IProfileItem {
   type?: "local" | "remote" | "merge" | "script";
   name?: string;
   desc?: string;
+  group?: string;
+  icon?: string;
+  color?: string;
   file?: string;
   url?: string;
   updated?: number;
@@ -169,7 +172,24 @@ interface IProfileItem {
     expire: number;
   };
   option?: IProfileOption;
+  base?: string;
+  chain?: string[];
+  merge_order?: number;
+  download_via?: "proxy-url" | "self-proxy" | "system-proxy" | "direct";
+  validation?: {
+    valid: boolean;
+    message?: string;
+    line?: number;
+    column?: number;
+    checked: number;
+  };
   home?: string;
+  etag?: string;
+  last_modified?: string;
+  content_hash?: string;
+  checked?: number;
+  last_update_attempt?: number;
+  last_update_error?: string;
 }
 
 interface IProfileOption {
@@ -178,6 +198,12 @@ interface IProfileOption {
   self_proxy?: boolean;
   update_interval?: number;
   danger_accept_invalid_certs?: boolean;
+  download_timeout_secs?: number;
+  proxy_url?: string;
+  doh_url?: string;
+  http_method?: "GET" | "POST";
+  http_headers?: Record<string, string>;
+  http_body?: string;
 }
 
 interface IProfilesConfig {
@@ -185,6 +211,7 @@ interface IProfilesConfig {
   chain?: string[];
   valid?: string[];
   items?: IProfileItem[];
+  recent?: string[];
 }
 
 interface IVergeTestItem {
@@ -194,14 +221,38 @@ interface IVergeTestItem {
   url: string;
 }
 
+interface IClashDNS {
+  enable?: boolean;
+  listen?: string;
+  "default-nameserver"?: string[];
+  "enhanced-mode"?: "fake-ip" | "redir-host" | "normal";
+  "fake-ip-range"?: string;
+  "use-hosts"?: boolean;
+  "fake-ip-filter"?: string[];
+  nameserver?: string[];
+  fallback?: string[];
+  "fallback-filter"?: {
+    geoip?: boolean;
+    "geoip-code"?: string;
+    ipcidr?: string[];
+    domain?: string[];
+  };
+  "nameserver-policy"?: Record<string, string | string[]>;
+}
+
+type IShellKind = "bash" | "zsh" | "fish" | "powershell" | "cmd";
+
 interface IVergeConfig {
+  config_version?: number;
   app_log_level?: "trace" | "debug" | "info" | "warn" | "error" | string;
   language?: string;
   tray_event?: "main_window" | "system_proxy" | "tun_mode" | string;
-  env_type?: "bash" | "cmd" | "powershell" | string;
+  env_type?: IShellKind | string;
   startup_script?: string;
   start_page?: string;
   clash_core?: string;
+  core_binary_path?: string;
+  core_extra_args?: string[];
   theme_mode?: "light" | "dark" | "system";
   traffic_graph?: boolean;
   enable_memory_usage?: boolean;
@@ -211,12 +262,40 @@ interface IVergeConfig {
   common_tray_icon?: boolean;
   sysproxy_tray_icon?: boolean;
   tun_tray_icon?: boolean;
+  tray_mode_icon?: boolean;
+  window_always_on_top?: boolean;
+  webview_extra_args?: string;
+  software_rendering?: boolean;
+  geodata_urls?: {
+    geoip?: string;
+    geosite?: string;
+    mmdb?: string;
+  };
+  geodata_updated?: number;
+  fallback_profile_uid?: string;
+  core_crash_action?: "restart" | "notify";
+  tray_profiles_limit?: number;
+  tray_quick_actions?: boolean;
   enable_tun_mode?: boolean;
   enable_auto_launch?: boolean;
+  auto_launch_args?: string[];
   enable_service_mode?: boolean;
   enable_silent_start?: boolean;
+  start_minimized?: boolean;
+  close_to_tray?: boolean;
+  close_to_tray_noticed?: boolean;
+  enable_local_api?: boolean;
+  startup_profile_policy?: "last-selected" | "specific-uid" | "none";
+  startup_profile_uid?: string;
+  profile_doh_url?: string;
+  profile_max_size_mb?: number;
   enable_system_proxy?: boolean;
   enable_random_port?: boolean;
+  ip_version?: "v4" | "v6" | "dual";
+  enable_dns_override?: boolean;
+  dns_override?: Record<string, any>;
+  disabled_rule_providers?: string[];
+  lan_authentication?: string[];
   verge_mixed_port?: number;
   verge_socks_port?: number;
   verge_redir_port?: number;
@@ -228,7 +307,10 @@ interface IVergeConfig {
   verge_http_enabled?: boolean;
   enable_proxy_guard?: boolean;
   proxy_guard_duration?: number;
+  kill_switch?: boolean;
   system_proxy_bypass?: string;
+  system_proxy_mode?: "fixed" | "pac";
+  enable_captive_portal_detect?: boolean;
   web_ui_list?: string[];
   hotkeys?: string[];
   theme_setting?: {
@@ -248,6 +330,7 @@ interface IVergeConfig {
   default_latency_test?: string;
   default_latency_timeout?: number;
   enable_builtin_enhanced?: boolean;
+  timer_paused?: boolean;
   auto_log_clean?: 0 | 1 | 2 | 3;
   proxy_layout_column?: number;
   test_list?: IVergeTestItem[];
@@ -324,3 +407,186 @@ interface IEnhancedResult {
   status: string;
   error?: string;
 }
+
+type IProviderKind = "rules" | "proxies";
+
+interface IProviderInfo {
+  name: string;
+  kind: IProviderKind;
+  vehicle_type: string;
+  behavior?: string;
+  updated_at?: string;
+  count: number;
+}
+
+interface IRuleProviderState {
+  name: string;
+  behavior?: string;
+  enabled: boolean;
+}
+
+interface IConnectionInfo {
+  id: string;
+  network: string;
+  source: string;
+  destination: string;
+  rule: string;
+  chains: string[];
+  upload: number;
+  download: number;
+  start?: string;
+}
+
+interface IUpdateProgress {
+  uid: string;
+  name?: string;
+  status: "ok" | "failed" | "canceled";
+  error?: string;
+  finished: number;
+  total: number;
+}
+
+interface IChainHop {
+  name: string;
+  delay?: number;
+  error?: string;
+}
+
+interface IChainResult {
+  hops: IChainHop[];
+  failed_hop?: number;
+  delay?: number;
+}
+
+interface IImportResult {
+  url: string;
+  uid?: string;
+  error?: string;
+}
+
+interface IUpdateResult {
+  success: boolean;
+  error?: string;
+  extra?: IProfileItem["extra"];
+}
+
+interface IUpdateSummary {
+  succeeded: number;
+  failed: number;
+  canceled: number;
+  total: number;
+}
+
+type ICoreMemory =
+  | { status: "unknown" | "unsupported" }
+  | { status: "ok"; inuse: number; oslimit: number };
+
+interface IStartupScriptResult {
+  path: string;
+  code?: number;
+  stdout: string;
+  stderr: string;
+  timed_out: boolean;
+  started: number;
+}
+
+interface ICoreLog {
+  time: string;
+  level: string;
+  payload: string;
+}
+
+interface IControllerInfo {
+  host: string;
+  port: number;
+  server: string;
+  secret: string;
+  has_secret: boolean;
+}
+
+interface IDownloadProgress {
+  key: string;
+  stage: "start" | "progress" | "done" | "error";
+  received: number;
+  total?: number;
+  error?: string;
+}
+
+interface IRuleMatch {
+  target: string;
+  index?: number;
+  rule?: string;
+  proxy?: string;
+  resolved: string[];
+  skipped: { index: number; rule: string; reason: string }[];
+  message: string;
+}
+
+type IFlushResult =
+  | { status: "flushed" }
+  | { status: "not-applicable"; reason: string };
+
+interface IExistingProxy {
+  host: string;
+  port: number;
+  bypass: string;
+  first_run: boolean;
+}
+
+interface IPortStatus {
+  name: "mixed" | "socks" | "http" | "redir" | "tproxy" | "controller";
+  port: number;
+  available: boolean;
+  used_by_core: boolean;
+}
+
+interface IStartupTimings {
+  started: number;
+  phases: { name: string; ms: number }[];
+  total_ms: number;
+  core_ready_ms?: number | null;
+  missing_resources: string[];
+}
+
+interface ICoreCrashState {
+  count: number;
+  last_code: number | null;
+  last_signal: number | null;
+  last_time: number | null;
+  restart_blocked: boolean;
+}
+
+interface ISubscriptionProbe {
+  name: string;
+  proxies: number;
+  proxy_providers: number;
+  proxy_groups: number;
+  rules: number;
+  extra?: IProfileItem["extra"] | null;
+  update_interval?: number | null;
+  home?: string | null;
+  download_via?: string | null;
+}
+
+interface IConnectionStatsItem {
+  name: string;
+  upload: number;
+  download: number;
+}
+
+interface IConnectionStats {
+  since: number;
+  upload: number;
+  download: number;
+  hosts: IConnectionStatsItem[];
+  proxies: IConnectionStatsItem[];
+  groups: IConnectionStatsItem[];
+  rules: IConnectionStatsItem[];
+}
+
+interface IResourceState {
+  name: string;
+  status: "ok" | "restored" | "missing" | "empty";
+  size: number;
+  error?: string;
+}